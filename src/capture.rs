@@ -0,0 +1,177 @@
+use pipewire::{
+    core::CoreRc,
+    properties::properties,
+    spa::{
+        param::{
+            audio::{AudioFormat, AudioInfoRaw},
+            format::{MediaSubtype, MediaType},
+            format_utils, ParamType,
+        },
+        pod::{serialize::PodSerializer, Object, Pod, Value},
+        utils::{Direction, SpaTypes},
+    },
+    stream::{StreamFlags, StreamListener, StreamRc},
+};
+use std::{cell::RefCell, rc::Rc};
+
+// Anything that wants to consume the audio of a node implements this trait.
+// Samples are always interleaved 32 bit floats.
+pub(crate) trait CaptureProcessor {
+    fn process(&mut self, samples: &[f32], channels: usize);
+
+    // Called periodically by the pipewire thread, also when the node sends no buffers.
+    fn tick(&mut self) {}
+}
+
+struct CaptureData {
+    channels: usize,
+    // Samples of the current buffer, reused to not allocate in the process callback.
+    samples: Vec<f32>,
+    processor: Rc<RefCell<Box<dyn CaptureProcessor>>>,
+}
+
+// A capture stream connected to a node (or to the monitor of a sink).
+// The stream is disconnected when this struct is dropped.
+pub(crate) struct Capture {
+    pub node_id: u32,
+    processor: Rc<RefCell<Box<dyn CaptureProcessor>>>,
+    _listener: StreamListener<CaptureData>,
+    _stream: StreamRc,
+}
+
+impl Capture {
+    pub fn new(
+        core: &CoreRc,
+        node_id: u32,
+        target_serial: Option<String>,
+        capture_sink: bool,
+        processor: Box<dyn CaptureProcessor>,
+    ) -> Result<Self, pipewire::Error> {
+        let mut props = properties! {
+            "media.type" => "Audio",
+            "media.category" => "Capture",
+            "media.role" => "DSP",
+            "node.name" => "node-pipewire:capture",
+            "node.passive" => "true",
+            "stream.capture.sink" => capture_sink.to_string(),
+        };
+
+        // Prefer the object serial to target the node, the node id is only used as fallback.
+        let connect_id = match target_serial {
+            Some(serial) => {
+                props.insert("target.object", serial);
+                None
+            }
+            None => Some(node_id),
+        };
+
+        let stream = StreamRc::new(core.clone(), "node-pipewire-capture", props)?;
+        let processor = Rc::new(RefCell::new(processor));
+
+        let listener = stream
+            .add_local_listener_with_user_data(CaptureData {
+                channels: 0,
+                samples: Vec::new(),
+                processor: processor.clone(),
+            })
+            .param_changed(|_, data, id, param| {
+                // None means that the format was cleared
+                let Some(param) = param else {
+                    return;
+                };
+                if id != ParamType::Format.as_raw() {
+                    return;
+                }
+
+                let Ok((media_type, media_subtype)) = format_utils::parse_format(param) else {
+                    return;
+                };
+                if media_type != MediaType::Audio || media_subtype != MediaSubtype::Raw {
+                    return;
+                }
+
+                let mut format = AudioInfoRaw::new();
+                if format.parse(param).is_ok() {
+                    data.channels = format.channels() as usize;
+                }
+            })
+            .process(|stream, data| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                if data.channels == 0 {
+                    return;
+                }
+
+                let datas = buffer.datas_mut();
+                if datas.is_empty() {
+                    return;
+                }
+
+                let chunk_offset = datas[0].chunk().offset() as usize;
+                let chunk_size = datas[0].chunk().size() as usize;
+
+                if let Some(bytes) = datas[0].data() {
+                    let start = chunk_offset.min(bytes.len());
+                    let end = (start + chunk_size).min(bytes.len());
+
+                    data.samples.clear();
+                    data.samples.extend(
+                        bytes[start..end]
+                            .chunks_exact(std::mem::size_of::<f32>())
+                            .map(|sample| {
+                                f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]])
+                            }),
+                    );
+
+                    data.processor
+                        .borrow_mut()
+                        .process(&data.samples, data.channels);
+                }
+            })
+            .register()?;
+
+        // Ask for interleaved F32 samples, leaving rate and channels empty to get the ones of the node.
+        let mut audio_info = AudioInfoRaw::new();
+        audio_info.set_format(AudioFormat::F32LE);
+        let format = Object {
+            type_: SpaTypes::ObjectParamFormat.as_raw(),
+            id: ParamType::EnumFormat.as_raw(),
+            properties: audio_info.into(),
+        };
+        let format: Vec<u8> =
+            PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(format))
+                .expect("ERROR: error at serializing capture format")
+                .0
+                .into_inner();
+        let mut params =
+            [Pod::from_bytes(&format).expect("ERROR: error at reading capture format")];
+
+        stream.connect(
+            Direction::Input,
+            connect_id,
+            StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS | StreamFlags::DONT_RECONNECT,
+            &mut params,
+        )?;
+
+        Ok(Self {
+            node_id,
+            processor,
+            _listener: listener,
+            _stream: stream,
+        })
+    }
+
+    pub fn tick(&self) {
+        self.processor.borrow_mut().tick();
+    }
+}
+
+// Convert a linear amplitude to dBFS.
+pub(crate) fn to_dbfs(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        20.0 * amplitude.log10()
+    } else {
+        f32::NEG_INFINITY
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+// Classification of a node from its media.class and properties:
+//
+// | media.class                       | kind     | category                         |
+// |-----------------------------------|----------|----------------------------------|
+// | Audio/Sink, Video/Sink            | sink     | device (with a device.id)        |
+// | Audio/Source, Video/Source, ...   | source   | or virtual (null sinks, ...)     |
+// | Audio/Duplex                      | duplex   |                                  |
+// | Stream/Output/Audio               | playback | stream                           |
+// | Stream/Input/Audio                | capture  | stream                           |
+// | Midi/Bridge                       | duplex   | bridge                           |
+//
+// Nodes in a link group (filter-chain, echo-cancel, loopback...) and Filter classes are filters,
+// whatever their media.class. Monitors are capture streams recording the output of a sink.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct NodeClass {
+    pub category: String,
+    pub kind: String,
+    pub is_monitor: bool,
+}
+
+pub(crate) fn classify(props: &HashMap<String, String>) -> NodeClass {
+    let media_class = props.get("media.class").map_or("", String::as_str);
+    let is_true = |key: &str| props.get(key).is_some_and(|value| value == "true");
+
+    let kind = if media_class.starts_with("Stream/Output") {
+        "playback"
+    } else if media_class.starts_with("Stream/Input") {
+        "capture"
+    } else if media_class.contains("Duplex") || media_class.contains("Bridge") {
+        "duplex"
+    } else if media_class.contains("Sink") {
+        "sink"
+    } else if media_class.contains("Source") {
+        "source"
+    } else {
+        ""
+    };
+
+    let category = if media_class.ends_with("/Bridge") {
+        "bridge"
+    } else if media_class.contains("Filter") || props.contains_key("node.link-group") {
+        "filter"
+    } else if media_class.starts_with("Stream/") {
+        "stream"
+    } else if kind.is_empty() {
+        ""
+    } else if props.contains_key("device.id") && !is_true("node.virtual") {
+        "device"
+    } else {
+        "virtual"
+    };
+
+    let is_monitor = (kind == "capture"
+        && (is_true("stream.monitor") || is_true("stream.capture.sink")))
+        || media_class.ends_with("/Monitor");
+
+    NodeClass {
+        category: category.to_string(),
+        kind: kind.to_string(),
+        is_monitor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify_props(props: &[(&str, &str)]) -> (String, String, bool) {
+        let props = props
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let class = classify(&props);
+        (class.category, class.kind, class.is_monitor)
+    }
+
+    #[test]
+    fn classifies_the_nodes() {
+        let class = |category: &str, kind: &str, is_monitor| {
+            (category.to_string(), kind.to_string(), is_monitor)
+        };
+
+        assert_eq!(
+            classify_props(&[("media.class", "Audio/Sink"), ("device.id", "42")]),
+            class("device", "sink", false)
+        );
+        assert_eq!(
+            classify_props(&[("media.class", "Audio/Sink")]),
+            class("virtual", "sink", false)
+        );
+        assert_eq!(
+            classify_props(&[("media.class", "Audio/Duplex"), ("device.id", "42")]),
+            class("device", "duplex", false)
+        );
+        assert_eq!(
+            classify_props(&[("media.class", "Stream/Output/Audio")]),
+            class("stream", "playback", false)
+        );
+        assert_eq!(
+            classify_props(&[
+                ("media.class", "Stream/Input/Audio"),
+                ("stream.capture.sink", "true")
+            ]),
+            class("stream", "capture", true)
+        );
+        assert_eq!(
+            classify_props(&[
+                ("media.class", "Stream/Output/Audio"),
+                ("node.link-group", "filter-chain-1")
+            ]),
+            class("filter", "playback", false)
+        );
+        assert_eq!(
+            classify_props(&[("media.class", "Midi/Bridge")]),
+            class("bridge", "duplex", false)
+        );
+        assert_eq!(classify_props(&[]), class("", "", false));
+    }
+}
@@ -0,0 +1,391 @@
+// Properties of the virtual devices created by createSource / createSink / createVirtualDevice, checked before
+// creating the node so an invalid option is reported to JS instead of failing in the server.
+
+// Prefix of the node.name of the devices, unless they are created without it.
+pub(crate) const NAME_PREFIX: &str = "node-pipewire:";
+
+// Max number of channels of a device (SPA_AUDIO_MAX_CHANNELS).
+const MAX_CHANNELS: usize = 64;
+
+// Channel positions of SPA (the names of spa_audio_channel), AUX0 to AUX63 are accepted too.
+const POSITIONS: &[&str] = &[
+    "MONO", "FL", "FR", "FC", "LFE", "SL", "SR", "FLC", "FRC", "RC", "RL", "RR", "TC", "TFL",
+    "TFC", "TFR", "TRL", "TRC", "TRR", "RLC", "RRC", "FLW", "FRW", "LFE2", "FLH", "FCH", "FRH",
+    "TFLC", "TFRC", "TSL", "TSR", "LLFE", "RLFE", "BC", "BLC", "BRC",
+];
+
+// Sample formats of audio.format (the names of spa_audio_format).
+const FORMATS: &[&str] = &[
+    "S8", "U8", "S16LE", "S16BE", "U16LE", "U16BE", "S24_32LE", "S24_32BE", "U24_32LE", "U24_32BE",
+    "S32LE", "S32BE", "U32LE", "U32BE", "S24LE", "S24BE", "U24LE", "U24BE", "S20LE", "S20BE",
+    "U20LE", "U20BE", "S18LE", "S18BE", "U18LE", "U18BE", "F32LE", "F32BE", "F64LE", "F64BE",
+    "S16", "U16", "S24_32", "U24_32", "S32", "U32", "S24", "U24", "S20", "U20", "S18", "U18",
+    "F32", "F64", "U8P", "S16P", "S24_32P", "S32P", "S24P", "F32P", "F64P", "S8P", "ULAW", "ALAW",
+];
+
+// Keys set from the options, the extra properties can't override them.
+const RESERVED_KEYS: &[&str] = &[
+    "factory.name",
+    "media.class",
+    "node.name",
+    "audio.position",
+    "audio.channels",
+    "object.linger",
+    "adapter.auto-port-config",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DeviceKind {
+    // Input ports, and monitor output ports unless disabled.
+    Sink,
+    // Output ports for the applications recording it, fed through its input ports.
+    Source,
+    // Input ports and output ports (the monitor ports of the sink).
+    Duplex,
+}
+
+impl DeviceKind {
+    pub(crate) fn parse(kind: &str) -> Option<DeviceKind> {
+        match kind {
+            "sink" => Some(DeviceKind::Sink),
+            "source" => Some(DeviceKind::Source),
+            "duplex" => Some(DeviceKind::Duplex),
+            _ => None,
+        }
+    }
+
+    fn media_class(&self) -> &'static str {
+        match self {
+            DeviceKind::Sink => "Audio/Sink/Virtual",
+            DeviceKind::Source => "Audio/Source/Virtual",
+            DeviceKind::Duplex => "Audio/Duplex",
+        }
+    }
+}
+
+// Options of a virtual device. The node.name is the prefix followed by `node_name`, or by `name`
+// (the node.nick) when there is no custom node name.
+#[derive(Clone, Debug)]
+pub(crate) struct DeviceOptions {
+    pub kind: DeviceKind,
+    pub name: String,
+    pub node_name: Option<String>,
+    pub prefix: bool,
+    pub positions: Vec<String>,
+    pub description: Option<String>,
+    pub rate: Option<u32>,
+    pub format: Option<String>,
+    pub channel_volumes: Option<bool>,
+    // node.latency as "quantum/rate"
+    pub latency: Option<String>,
+    pub priority: Option<i32>,
+    pub icon_name: Option<String>,
+    pub extra_props: Vec<(String, String)>,
+    pub permanent: bool,
+    // Monitor ports of a sink (None keeps the default of the server, which creates them).
+    pub monitor: Option<bool>,
+}
+
+impl DeviceOptions {
+    pub(crate) fn new(kind: DeviceKind, name: String, positions: Vec<String>) -> DeviceOptions {
+        DeviceOptions {
+            kind,
+            name,
+            node_name: None,
+            prefix: true,
+            positions,
+            description: None,
+            rate: None,
+            format: None,
+            channel_volumes: None,
+            latency: None,
+            priority: None,
+            icon_name: None,
+            extra_props: Vec::new(),
+            permanent: false,
+            monitor: None,
+        }
+    }
+
+    pub(crate) fn node_name(&self) -> String {
+        let name = self.node_name.as_ref().unwrap_or(&self.name);
+        match self.prefix {
+            true => format!("{}{}", NAME_PREFIX, name),
+            false => name.clone(),
+        }
+    }
+
+    // Properties of the adapter node of the device, or the first invalid option.
+    pub(crate) fn props(&self) -> Result<Vec<(String, String)>, String> {
+        let node_name = self.node_name();
+        if self.node_name.as_ref().unwrap_or(&self.name).is_empty() {
+            return Err(String::from("The name of the device can't be empty"));
+        }
+
+        if self.positions.is_empty() {
+            return Err(String::from(
+                "Cannot create a device with no audio positions",
+            ));
+        }
+        if self.positions.len() > MAX_CHANNELS {
+            return Err(format!(
+                "A device can't have more than {} channels",
+                MAX_CHANNELS
+            ));
+        }
+        for (i, position) in self.positions.iter().enumerate() {
+            if !valid_position(position) {
+                return Err(format!("Unknown audio position {:?}", position));
+            }
+            if self.positions[..i].contains(position) {
+                return Err(format!("The audio position {:?} is repeated", position));
+            }
+        }
+
+        let mut props = vec![
+            (
+                String::from("factory.name"),
+                String::from("support.null-audio-sink"),
+            ),
+            (
+                String::from("media.class"),
+                self.kind.media_class().to_string(),
+            ),
+            (String::from("node.name"), node_name),
+            (String::from("node.nick"), self.name.clone()),
+            (String::from("audio.position"), self.positions.join(",")),
+            (
+                String::from("audio.channels"),
+                self.positions.len().to_string(),
+            ),
+            (String::from("object.linger"), self.permanent.to_string()),
+        ];
+
+        // the ports of the adapter, the outputs of a duplex device are the monitor ports
+        let monitor = match (self.kind, self.monitor) {
+            (DeviceKind::Sink, monitor) => monitor,
+            (DeviceKind::Source, None) => None,
+            (DeviceKind::Source, Some(_)) => {
+                return Err(String::from("Only sinks have monitor ports"))
+            }
+            (DeviceKind::Duplex, Some(false)) => {
+                return Err(String::from("A duplex device needs its monitor ports"))
+            }
+            (DeviceKind::Duplex, _) => Some(true),
+        };
+        if let Some(monitor) = monitor {
+            props.push((
+                String::from("adapter.auto-port-config"),
+                format!("{{ mode = dsp monitor = {} position = preserve }}", monitor),
+            ));
+        }
+
+        if let Some(description) = &self.description {
+            props.push((String::from("node.description"), description.clone()));
+        }
+        if let Some(rate) = self.rate {
+            if !(8000..=768000).contains(&rate) {
+                return Err(format!(
+                    "The rate must be between 8000 and 768000, got {}",
+                    rate
+                ));
+            }
+            props.push((String::from("audio.rate"), rate.to_string()));
+        }
+        if let Some(format) = &self.format {
+            if !FORMATS.contains(&format.as_str()) {
+                return Err(format!("Unknown audio format {:?}", format));
+            }
+            props.push((String::from("audio.format"), format.clone()));
+        }
+        if let Some(channel_volumes) = self.channel_volumes {
+            props.push((
+                String::from("monitor.channel-volumes"),
+                channel_volumes.to_string(),
+            ));
+        }
+        if let Some(latency) = &self.latency {
+            if !valid_fraction(latency) {
+                return Err(format!(
+                    "The latency must be a fraction like 256/48000, got {:?}",
+                    latency
+                ));
+            }
+            props.push((String::from("node.latency"), latency.clone()));
+        }
+        if let Some(priority) = self.priority {
+            props.push((String::from("priority.session"), priority.to_string()));
+        }
+        if let Some(icon_name) = &self.icon_name {
+            props.push((String::from("device.icon-name"), icon_name.clone()));
+        }
+
+        for (key, value) in &self.extra_props {
+            if key.is_empty() {
+                return Err(String::from("The keys of the properties can't be empty"));
+            }
+            if RESERVED_KEYS.contains(&key.as_str()) {
+                return Err(format!("The property {:?} is set by the options", key));
+            }
+            if props.iter().any(|(set, _)| set == key) {
+                return Err(format!("The property {:?} is set twice", key));
+            }
+            props.push((key.clone(), value.clone()));
+        }
+
+        Ok(props)
+    }
+}
+
+fn valid_position(position: &str) -> bool {
+    POSITIONS.contains(&position)
+        || position
+            .strip_prefix("AUX")
+            .and_then(|index| index.parse::<u32>().ok())
+            .is_some_and(|index| index < MAX_CHANNELS as u32)
+}
+
+// A positive fraction "num/denom".
+fn valid_fraction(value: &str) -> bool {
+    let mut parts = value.splitn(2, '/');
+    let mut part = || {
+        parts
+            .next()
+            .and_then(|part| part.parse::<u32>().ok())
+            .is_some_and(|part| part > 0)
+    };
+    part() && part()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> DeviceOptions {
+        DeviceOptions::new(
+            DeviceKind::Sink,
+            String::from("mixer"),
+            vec![String::from("FL"), String::from("FR")],
+        )
+    }
+
+    fn prop<'a>(props: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        props
+            .iter()
+            .find(|(set, _)| set == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn builds_the_properties() {
+        let props = options().props().unwrap();
+        assert_eq!(prop(&props, "node.name"), Some("node-pipewire:mixer"));
+        assert_eq!(prop(&props, "node.nick"), Some("mixer"));
+        assert_eq!(prop(&props, "audio.position"), Some("FL,FR"));
+        assert_eq!(prop(&props, "audio.channels"), Some("2"));
+        assert_eq!(prop(&props, "object.linger"), Some("false"));
+        assert_eq!(prop(&props, "node.description"), None);
+        assert_eq!(prop(&props, "adapter.auto-port-config"), None);
+
+        let mut custom = options();
+        custom.node_name = Some(String::from("my_sink"));
+        custom.prefix = false;
+        custom.positions = vec![String::from("MONO")];
+        custom.description = Some(String::from("My sink"));
+        custom.rate = Some(48000);
+        custom.format = Some(String::from("F32LE"));
+        custom.channel_volumes = Some(true);
+        custom.latency = Some(String::from("256/48000"));
+        custom.priority = Some(-10);
+        custom.icon_name = Some(String::from("audio-card"));
+        custom.extra_props = vec![(String::from("node.group"), String::from("mixers"))];
+
+        let props = custom.props().unwrap();
+        assert_eq!(prop(&props, "node.name"), Some("my_sink"));
+        assert_eq!(prop(&props, "node.description"), Some("My sink"));
+        assert_eq!(prop(&props, "audio.rate"), Some("48000"));
+        assert_eq!(prop(&props, "audio.format"), Some("F32LE"));
+        assert_eq!(prop(&props, "monitor.channel-volumes"), Some("true"));
+        assert_eq!(prop(&props, "node.latency"), Some("256/48000"));
+        assert_eq!(prop(&props, "priority.session"), Some("-10"));
+        assert_eq!(prop(&props, "device.icon-name"), Some("audio-card"));
+        assert_eq!(prop(&props, "node.group"), Some("mixers"));
+    }
+
+    #[test]
+    fn configures_the_ports() {
+        let port_config = |kind: DeviceKind, monitor: Option<bool>| {
+            let mut options = options();
+            options.kind = kind;
+            options.monitor = monitor;
+            options.props().map(|props| {
+                (
+                    prop(&props, "media.class").unwrap().to_string(),
+                    prop(&props, "adapter.auto-port-config").map(String::from),
+                )
+            })
+        };
+        let config = |monitor: bool| {
+            Some(format!(
+                "{{ mode = dsp monitor = {} position = preserve }}",
+                monitor
+            ))
+        };
+
+        assert_eq!(
+            port_config(DeviceKind::Sink, Some(false)),
+            Ok((String::from("Audio/Sink/Virtual"), config(false)))
+        );
+        assert_eq!(
+            port_config(DeviceKind::Source, None),
+            Ok((String::from("Audio/Source/Virtual"), None))
+        );
+        assert_eq!(
+            port_config(DeviceKind::Duplex, None),
+            Ok((String::from("Audio/Duplex"), config(true)))
+        );
+        assert!(port_config(DeviceKind::Duplex, Some(false)).is_err());
+        assert!(port_config(DeviceKind::Source, Some(true)).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let invalid = |change: &dyn Fn(&mut DeviceOptions)| {
+            let mut options = options();
+            change(&mut options);
+            options.props().is_err()
+        };
+
+        assert!(!invalid(&|options| options
+            .positions
+            .push(String::from("AUX3"))));
+        assert!(invalid(&|options| options.name.clear()));
+        assert!(invalid(&|options| options.positions.clear()));
+        assert!(invalid(&|options| options
+            .positions
+            .push(String::from("FL"))));
+        assert!(invalid(&|options| options
+            .positions
+            .push(String::from("AUX64"))));
+        assert!(invalid(&|options| options
+            .positions
+            .push(String::from("XX"))));
+        assert!(invalid(&|options| options.rate = Some(0)));
+        assert!(invalid(
+            &|options| options.format = Some(String::from("F33"))
+        ));
+        assert!(invalid(
+            &|options| options.latency = Some(String::from("256"))
+        ));
+        assert!(invalid(
+            &|options| options.latency = Some(String::from("0/48000"))
+        ));
+        assert!(invalid(&|options| {
+            options.extra_props = vec![(String::from("media.class"), String::from("Audio/Sink"))]
+        }));
+        assert!(invalid(&|options| {
+            options.description = Some(String::from("a"));
+            options.extra_props = vec![(String::from("node.description"), String::from("b"))]
+        }));
+    }
+}
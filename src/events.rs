@@ -0,0 +1,271 @@
+use crate::query::{self, Selector};
+use crate::PipewireData;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Change {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl Change {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Changed => "changed",
+            Change::Removed => "removed",
+        }
+    }
+}
+
+// A change of an object of the graph, with the object as it was after the change (before for a removal).
+#[derive(Clone, Debug)]
+pub(crate) struct GraphEvent {
+    pub change: Change,
+    pub data: PipewireData,
+}
+
+// Filter of a subscription. Every field that isn't empty must match, a list matches any of its values.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EventFilter {
+    // Types of the objects (node, port, link, client, metadata, device).
+    pub types: Vec<String>,
+    // Prefixes of the media.class of the node, of the node of a port, or of a node at either end of a link.
+    pub media_classes: Vec<String>,
+    pub selector: Option<Selector>,
+    // Ids of the node, of the node of a port, or of a node at either end of a link.
+    pub node_ids: Vec<u32>,
+}
+
+// Ids of the nodes an object belongs to.
+fn node_ids(data: &PipewireData) -> Vec<u32> {
+    match data {
+        PipewireData::Node(node) => vec![node.id],
+        PipewireData::Port(port) => vec![port.node_id],
+        PipewireData::Link(link) => vec![link.output_node_id, link.input_node_id],
+        PipewireData::Client(_) | PipewireData::Metadata(_) | PipewireData::Device(_) => Vec::new(),
+    }
+}
+
+fn media_classes<'a>(
+    all_data: &'a HashMap<u32, PipewireData>,
+    data: &'a PipewireData,
+) -> Vec<&'a str> {
+    let media_class = |data: &'a PipewireData| match data {
+        PipewireData::Node(node) => node.props.get("media.class").map(String::as_str),
+        _ => None,
+    };
+
+    match data {
+        PipewireData::Node(_) => media_class(data).into_iter().collect(),
+        _ => node_ids(data)
+            .iter()
+            .filter_map(|id| all_data.get(id).and_then(media_class))
+            .collect(),
+    }
+}
+
+impl EventFilter {
+    pub(crate) fn matches(
+        &self,
+        all_data: &HashMap<u32, PipewireData>,
+        data: &PipewireData,
+    ) -> bool {
+        let type_name = query::type_name(data);
+
+        (self.types.is_empty() || self.types.iter().any(|type_| type_ == type_name))
+            && (self.media_classes.is_empty()
+                || media_classes(all_data, data).iter().any(|class| {
+                    self.media_classes
+                        .iter()
+                        .any(|prefix| class.starts_with(prefix.as_str()))
+                }))
+            && (self.node_ids.is_empty()
+                || node_ids(data).iter().any(|id| self.node_ids.contains(id)))
+            && self
+                .selector
+                .as_ref()
+                .is_none_or(|selector| selector.matches(all_data, data))
+    }
+}
+
+// Events waiting for the end of the coalescing window of their subscription.
+// The window starts with the first event of a batch, so a burst of changes is sent as one batch.
+#[derive(Debug)]
+pub(crate) struct EventBatch<T> {
+    window: Duration,
+    events: Vec<T>,
+    deadline: Option<Instant>,
+}
+
+impl<T> EventBatch<T> {
+    pub(crate) fn new(window: Duration) -> EventBatch<T> {
+        EventBatch {
+            window,
+            events: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: T, now: Instant) {
+        if self.deadline.is_none() {
+            self.deadline = Some(now + self.window);
+        }
+        self.events.push(event);
+    }
+
+    // When the batch must be sent, None while it's empty.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    // Take the events if the window ended.
+    pub(crate) fn take_due(&mut self, now: Instant) -> Option<Vec<T>> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {
+                self.deadline = None;
+                Some(std::mem::take(&mut self.events))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct EventSubscription {
+    pub filter: EventFilter,
+    pub batch: EventBatch<GraphEvent>,
+}
+
+impl EventSubscription {
+    // Queue the change of `data` if it matches the filter.
+    pub(crate) fn offer(
+        &mut self,
+        all_data: &HashMap<u32, PipewireData>,
+        change: Change,
+        data: &PipewireData,
+        now: Instant,
+    ) {
+        if self.filter.matches(all_data, data) {
+            self.batch.push(
+                GraphEvent {
+                    change,
+                    data: data.clone(),
+                },
+                now,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipewireNode, PipewirePort};
+    use pipewire::permissions::PermissionFlags;
+
+    fn graph() -> HashMap<u32, PipewireData> {
+        let mut all_data = HashMap::new();
+
+        for (id, media_class) in [(1, "Audio/Sink"), (2, "Stream/Output/Audio")] {
+            all_data.insert(
+                id,
+                PipewireData::Node(PipewireNode {
+                    id,
+                    permissions: PermissionFlags::all(),
+                    props: std::iter::once(("media.class".to_string(), media_class.to_string()))
+                        .collect(),
+                    name: format!("node {}", id),
+                    node_direction: String::new(),
+                    node_type: String::from("Audio"),
+                    category: String::new(),
+                    kind: String::new(),
+                    is_monitor: false,
+                    ports: Vec::new(),
+                    quantum: None,
+                    rate: None,
+                    process_latency: None,
+                    stats: None,
+                }),
+            );
+        }
+
+        for (id, node_id) in [(10, 1), (20, 2)] {
+            all_data.insert(
+                id,
+                PipewireData::Port(PipewirePort {
+                    id,
+                    permissions: PermissionFlags::all(),
+                    props: HashMap::new(),
+                    node_id,
+                    name: format!("port {}", id),
+                    direction: String::from("Input"),
+                    format: None,
+                    formats: Vec::new(),
+                    latency: Vec::new(),
+                    process_latency: None,
+                }),
+            );
+        }
+
+        all_data
+    }
+
+    #[test]
+    fn filters_the_events() {
+        let all_data = graph();
+        let matching = |filter: &EventFilter| {
+            let mut ids: Vec<u32> = all_data
+                .iter()
+                .filter(|(_, data)| filter.matches(&all_data, data))
+                .map(|(id, _)| *id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(matching(&EventFilter::default()), vec![1, 2, 10, 20]);
+        assert_eq!(
+            matching(&EventFilter {
+                media_classes: vec![String::from("Audio/Sink")],
+                ..Default::default()
+            }),
+            vec![1, 10]
+        );
+        assert_eq!(
+            matching(&EventFilter {
+                types: vec![String::from("port")],
+                node_ids: vec![2],
+                ..Default::default()
+            }),
+            vec![20]
+        );
+        assert_eq!(
+            matching(&EventFilter {
+                selector: Some(Selector::parse("node[name$=2] > port").unwrap()),
+                ..Default::default()
+            }),
+            vec![20]
+        );
+    }
+
+    #[test]
+    fn coalesces_the_events() {
+        let start = Instant::now();
+        let mut batch = EventBatch::new(Duration::from_millis(100));
+
+        assert_eq!(batch.deadline(), None);
+        batch.push(1, start);
+        batch.push(2, start + Duration::from_millis(60));
+        assert_eq!(batch.take_due(start + Duration::from_millis(99)), None);
+        assert_eq!(
+            batch.take_due(start + Duration::from_millis(100)),
+            Some(vec![1, 2])
+        );
+
+        // the next event starts a new window
+        batch.push(3, start + Duration::from_millis(150));
+        assert_eq!(batch.deadline(), Some(start + Duration::from_millis(250)));
+    }
+}
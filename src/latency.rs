@@ -0,0 +1,417 @@
+use crate::metadata;
+use crate::{PipewireData, PipewireLatency, PipewireNode, PipewirePort, PipewireProcessLatency};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// Defaults of the PipeWire graph, used when the node doesn't tell its quantum or rate.
+const DEFAULT_QUANTUM: u32 = 1024;
+const DEFAULT_RATE: u32 = 48000;
+
+// Latency of the shortest path (in number of links) between two nodes.
+#[derive(Clone, Debug)]
+pub(crate) struct PathLatency {
+    pub nodes: Vec<u32>,
+    pub links: Vec<u32>,
+    pub min_ns: f64,
+    pub max_ns: f64,
+}
+
+// Parse a "num/denom" property (node.latency, node.rate).
+fn parse_fraction(value: &str) -> Option<(u32, u32)> {
+    let (num, denom) = value.split_once('/')?;
+    Some((num.trim().parse().ok()?, denom.trim().parse().ok()?))
+}
+
+// Quantum and rate requested by a node, from its properties.
+// Forced values take precedence over the latency requested by the node.
+// This is only what the node asks for, the driver may run with another quantum.
+pub(crate) fn node_clock(props: &HashMap<String, String>) -> (Option<u32>, Option<u32>) {
+    let get_number = |key: &str| {
+        props
+            .get(key)
+            .and_then(|value| value.parse::<u32>().ok())
+            .filter(|value| *value > 0)
+    };
+    let latency = props
+        .get("node.latency")
+        .and_then(|value| parse_fraction(value));
+
+    let quantum = get_number("node.force-quantum")
+        .or_else(|| latency.map(|latency| latency.0))
+        .filter(|quantum| *quantum > 0);
+    let rate = get_number("node.force-rate")
+        .or_else(|| {
+            props
+                .get("node.rate")
+                .and_then(|value| parse_fraction(value))
+                .map(|rate| rate.1)
+        })
+        .or_else(|| latency.map(|latency| latency.1))
+        .filter(|rate| *rate > 0);
+
+    (quantum, rate)
+}
+
+// Quantum and rate used to convert the latency of a node to nanoseconds, from the first known of:
+// - the profiler stats of the node (the current clock of its driver),
+// - the clock settings of the server (forced values first),
+// - the quantum and rate requested by the node, and the rate of its format.
+fn clock_of(all_data: &HashMap<u32, PipewireData>, node: &PipewireNode) -> (u32, u32) {
+    let stats = node.stats.as_ref();
+    let settings = all_data
+        .values()
+        .find_map(|data| match data {
+            PipewireData::Metadata(metadata) if metadata.name == metadata::SETTINGS => {
+                Some(metadata::clock_settings(&metadata.properties))
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+    let format_rate = node
+        .ports
+        .iter()
+        .find_map(|port| port.format.as_ref().and_then(|format| format.rate));
+
+    let quantum = stats
+        .map(|stats| stats.quantum)
+        .filter(|quantum| *quantum > 0)
+        .or(settings.force_quantum.filter(|quantum| *quantum > 0))
+        .or(settings.quantum.filter(|quantum| *quantum > 0))
+        .or(node.quantum)
+        .unwrap_or(DEFAULT_QUANTUM);
+    let rate = stats
+        .map(|stats| stats.rate)
+        .filter(|rate| *rate > 0)
+        .or(settings.force_rate.filter(|rate| *rate > 0))
+        .or(settings.rate.filter(|rate| *rate > 0))
+        .or(node.rate)
+        .or(format_rate)
+        .unwrap_or(DEFAULT_RATE);
+
+    (quantum, rate)
+}
+
+fn to_ns(quantum: f32, rate: i32, ns: i64, clock: (u32, u32)) -> f64 {
+    let (clock_quantum, clock_rate) = clock;
+    let seconds = (quantum as f64 * clock_quantum as f64 + rate as f64) / clock_rate as f64;
+    seconds * 1e9 + ns as f64
+}
+
+// Min and max latency of a port in one direction, None if the port doesn't report it.
+fn port_latency(
+    port: Option<&PipewirePort>,
+    direction: &str,
+    clock: (u32, u32),
+) -> Option<(f64, f64)> {
+    port.and_then(|port| {
+        port.latency
+            .iter()
+            .find(|latency| latency.direction == direction)
+    })
+    .map(|latency: &PipewireLatency| {
+        (
+            to_ns(latency.min_quantum, latency.min_rate, latency.min_ns, clock),
+            to_ns(latency.max_quantum, latency.max_rate, latency.max_ns, clock),
+        )
+    })
+}
+
+fn process_latency(latency: &Option<PipewireProcessLatency>, clock: (u32, u32)) -> f64 {
+    latency.as_ref().map_or(0.0, |latency| {
+        to_ns(latency.quantum, latency.rate, latency.ns, clock)
+    })
+}
+
+// Walk the links from `from` to `to` and compute the latency along the path.
+// The "Input" latency of a port is the latency from the sources up to it, so when the input port
+// of `to` reports it, the latency of the path is that one minus the "Input" latency of the output
+// port of `from` (what was already there before `from`).
+// Otherwise the process latency of every node in the middle and of the ports used is summed.
+pub(crate) fn path_latency(
+    all_data: &HashMap<u32, PipewireData>,
+    from: u32,
+    to: u32,
+) -> Option<PathLatency> {
+    let node = |id: u32| match all_data.get(&id) {
+        Some(PipewireData::Node(node)) => Some(node),
+        _ => None,
+    };
+    let port = |id: u32| match all_data.get(&id) {
+        Some(PipewireData::Port(port)) => Some(port),
+        _ => None,
+    };
+
+    node(from)?;
+    node(to)?;
+
+    // Links sorted by id, so the path found is always the same.
+    let mut links: Vec<_> = all_data
+        .values()
+        .filter_map(|data| match data {
+            PipewireData::Link(link) => Some(link),
+            _ => None,
+        })
+        .collect();
+    links.sort_by_key(|link| link.id);
+
+    // Breadth first search, keeping the link used to reach every node.
+    let mut previous = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(from);
+    queue.push_back(from);
+
+    while let Some(current) = queue.pop_front() {
+        if current == to {
+            break;
+        }
+        for link in links.iter().filter(|link| link.output_node_id == current) {
+            if visited.insert(link.input_node_id) {
+                previous.insert(link.input_node_id, *link);
+                queue.push_back(link.input_node_id);
+            }
+        }
+    }
+
+    if !visited.contains(&to) {
+        return None;
+    }
+
+    let mut path_links = Vec::new();
+    let mut current = to;
+    while current != from {
+        let link = previous[&current];
+        path_links.push(link);
+        current = link.output_node_id;
+    }
+    path_links.reverse();
+
+    let mut nodes = vec![from];
+    nodes.extend(path_links.iter().map(|link| link.input_node_id));
+
+    let (first, last) = match (path_links.first(), path_links.last()) {
+        (Some(first), Some(last)) => (first, last),
+        // `from` and `to` are the same node
+        _ => {
+            return Some(PathLatency {
+                nodes,
+                links: Vec::new(),
+                min_ns: 0.0,
+                max_ns: 0.0,
+            })
+        }
+    };
+
+    let clock = clock_of(all_data, node(to)?);
+    let (min_ns, max_ns) = match port_latency(port(last.input_port_id), "Input", clock) {
+        Some((to_min, to_max)) => {
+            let (from_min, from_max) =
+                port_latency(port(first.output_port_id), "Input", clock).unwrap_or((0.0, 0.0));
+            ((to_min - from_min).max(0.0), (to_max - from_max).max(0.0))
+        }
+        None => {
+            // Nodes in the middle are entered by one link and left by the next one.
+            let mut total = 0.0;
+            for hop in path_links.windows(2) {
+                let middle = node(hop[0].input_node_id)?;
+                let clock = clock_of(all_data, middle);
+
+                total += process_latency(&middle.process_latency, clock);
+                for port_id in [hop[0].input_port_id, hop[1].output_port_id] {
+                    if let Some(port) = port(port_id) {
+                        total += process_latency(&port.process_latency, clock);
+                    }
+                }
+            }
+            (total, total)
+        }
+    };
+
+    Some(PathLatency {
+        nodes,
+        links: path_links.iter().map(|link| link.id).collect(),
+        min_ns,
+        max_ns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipewireLink, PipewireNodeStats};
+    use pipewire::permissions::PermissionFlags;
+
+    fn add_node(all_data: &mut HashMap<u32, PipewireData>, id: u32, ports: Vec<PipewirePort>) {
+        for port in ports.iter() {
+            all_data.insert(port.id, PipewireData::Port(port.clone()));
+        }
+        all_data.insert(
+            id,
+            PipewireData::Node(PipewireNode {
+                id,
+                permissions: PermissionFlags::all(),
+                props: HashMap::new(),
+                name: format!("node {}", id),
+                node_direction: String::new(),
+                node_type: String::from("Audio"),
+                category: String::new(),
+                kind: String::new(),
+                is_monitor: false,
+                ports,
+                quantum: Some(256),
+                rate: Some(48000),
+                process_latency: None,
+                stats: None,
+            }),
+        );
+    }
+
+    fn new_port(id: u32, node_id: u32, latency: Vec<PipewireLatency>) -> PipewirePort {
+        PipewirePort {
+            id,
+            permissions: PermissionFlags::all(),
+            props: HashMap::new(),
+            node_id,
+            name: format!("port {}", id),
+            direction: String::new(),
+            format: None,
+            formats: Vec::new(),
+            latency,
+            process_latency: None,
+        }
+    }
+
+    fn add_link(
+        all_data: &mut HashMap<u32, PipewireData>,
+        id: u32,
+        output: (u32, u32),
+        input: (u32, u32),
+    ) {
+        all_data.insert(
+            id,
+            PipewireData::Link(PipewireLink {
+                id,
+                permissions: PermissionFlags::all(),
+                props: HashMap::new(),
+                output_node_id: output.0,
+                output_port_id: output.1,
+                input_node_id: input.0,
+                input_port_id: input.1,
+                format: None,
+            }),
+        );
+    }
+
+    #[test]
+    fn sums_the_latency_along_the_path() {
+        let mut all_data = HashMap::new();
+
+        // source (1) -> filter (2) -> sink (3), no port reports its latency
+        add_node(&mut all_data, 1, vec![new_port(10, 1, Vec::new())]);
+
+        let mut filter_input = new_port(20, 2, Vec::new());
+        filter_input.process_latency = Some(PipewireProcessLatency {
+            rate: 480,
+            ..Default::default()
+        });
+        add_node(
+            &mut all_data,
+            2,
+            vec![filter_input, new_port(21, 2, Vec::new())],
+        );
+        if let Some(PipewireData::Node(filter)) = all_data.get_mut(&2) {
+            filter.process_latency = Some(PipewireProcessLatency {
+                quantum: 1.0,
+                ..Default::default()
+            });
+        }
+
+        add_node(&mut all_data, 3, vec![new_port(30, 3, Vec::new())]);
+
+        add_link(&mut all_data, 100, (1, 10), (2, 20));
+        add_link(&mut all_data, 101, (2, 21), (3, 30));
+
+        let path = path_latency(&all_data, 1, 3).expect("ERROR: path not found");
+        assert_eq!(path.nodes, vec![1, 2, 3]);
+        assert_eq!(path.links, vec![100, 101]);
+
+        // 256 samples + 480 samples at 48 kHz
+        let expected = (256.0 + 480.0) / 48000.0 * 1e9;
+        assert!((path.min_ns - expected).abs() < 1.0);
+        assert!((path.max_ns - expected).abs() < 1.0);
+
+        assert!(path_latency(&all_data, 3, 1).is_none());
+    }
+
+    #[test]
+    fn subtracts_the_latency_before_the_path() {
+        let mut all_data = HashMap::new();
+
+        // Both ends report the latency up to them and the latency after them, which overlap:
+        // only the part between the output port of 1 and the input port of 2 must be counted.
+        let source = vec![
+            PipewireLatency {
+                direction: String::from("Input"),
+                min_quantum: 1.0,
+                max_quantum: 1.0,
+                ..Default::default()
+            },
+            PipewireLatency {
+                direction: String::from("Output"),
+                min_ns: 5_000_000,
+                max_ns: 5_000_000,
+                ..Default::default()
+            },
+        ];
+        add_node(&mut all_data, 1, vec![new_port(10, 1, source)]);
+
+        let sink = vec![
+            PipewireLatency {
+                direction: String::from("Input"),
+                min_quantum: 1.0,
+                max_quantum: 1.0,
+                min_rate: 480,
+                max_rate: 480,
+                max_ns: 1_000_000,
+                ..Default::default()
+            },
+            PipewireLatency {
+                direction: String::from("Output"),
+                min_ns: 3_000_000,
+                max_ns: 3_000_000,
+                ..Default::default()
+            },
+        ];
+        add_node(&mut all_data, 2, vec![new_port(20, 2, sink)]);
+
+        // the driver runs at 96 kHz, not at the rate requested by the nodes
+        if let Some(PipewireData::Node(node)) = all_data.get_mut(&2) {
+            node.stats = Some(PipewireNodeStats {
+                quantum: 1024,
+                rate: 96000,
+                ..Default::default()
+            });
+        }
+
+        add_link(&mut all_data, 100, (1, 10), (2, 20));
+
+        let path = path_latency(&all_data, 1, 2).expect("ERROR: path not found");
+
+        // 480 samples at 96 kHz, + 1 ms for the max
+        let expected = 480.0 / 96000.0 * 1e9;
+        assert!((path.min_ns - expected).abs() < 1.0);
+        assert!((path.max_ns - (expected + 1e6)).abs() < 1.0);
+    }
+
+    #[test]
+    fn reads_the_clock_of_the_node_props() {
+        let mut props = HashMap::new();
+        props.insert(String::from("node.latency"), String::from("512/44100"));
+        assert_eq!(node_clock(&props), (Some(512), Some(44100)));
+
+        props.insert(String::from("node.rate"), String::from("1/96000"));
+        props.insert(String::from("node.force-quantum"), String::from("64"));
+        assert_eq!(node_clock(&props), (Some(64), Some(96000)));
+
+        assert_eq!(node_clock(&HashMap::new()), (None, None));
+    }
+}
@@ -1,18 +1,344 @@
+mod capture;
+mod classify;
+mod device;
+mod events;
+mod latency;
+mod loopback;
+mod metadata;
+mod meter;
+mod midi;
+mod params;
+mod permissions;
 mod pipewire_thread;
+mod profiler;
 mod proxy;
+mod query;
+mod spectrum;
+mod video;
 
 use lazy_static::lazy_static;
 use neon::prelude::*;
 use once_cell::sync::OnceCell;
 use pipewire::permissions::PermissionFlags;
 use std::{
-    cell::RefCell,
-    collections::HashMap,
-    sync::{mpsc, Arc, Mutex},
-    time::Instant,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 use tokio::runtime::Runtime;
 
+// A format (negotiated or supported) of a port or a link.
+// Audio fields are only set on audio formats and video fields on video formats.
+#[derive(Clone, Debug, Default)]
+pub struct PipewireFormat {
+    pub kind: String,
+    pub media_type: String,
+    pub media_subtype: String,
+    pub sample_format: Option<String>,
+    pub rate: Option<u32>,
+    pub channels: Option<u32>,
+    pub video_format: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub framerate: Option<f64>,
+    // The values supported by the fields that are a choice (only in supported formats).
+    pub choices: PipewireFormatChoices,
+}
+
+// The values a field can take: an enumeration, or a range with an optional step.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatChoice<T> {
+    Values(Vec<T>),
+    Range { min: T, max: T, step: Option<T> },
+}
+
+impl<T> FormatChoice<T> {
+    pub fn map<U>(self, f: impl Fn(T) -> U) -> FormatChoice<U> {
+        match self {
+            FormatChoice::Values(values) => {
+                FormatChoice::Values(values.into_iter().map(f).collect())
+            }
+            FormatChoice::Range { min, max, step } => FormatChoice::Range {
+                min: f(min),
+                max: f(max),
+                step: step.map(f),
+            },
+        }
+    }
+
+    fn to_object<'a>(
+        &self,
+        cx: &mut Cx<'a>,
+        to_js: impl Fn(&mut Cx<'a>, &T) -> JsResult<'a, JsValue>,
+    ) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        match self {
+            FormatChoice::Values(values) => {
+                let js_values = cx.empty_array();
+                for (i, value) in values.iter().enumerate() {
+                    let js_value = to_js(cx, value)?;
+                    js_values.set(cx, i as u32, js_value)?;
+                }
+                obj.set(cx, "values", js_values)?;
+            }
+            FormatChoice::Range { min, max, step } => {
+                let js_min = to_js(cx, min)?;
+                let js_max = to_js(cx, max)?;
+                obj.set(cx, "min", js_min)?;
+                obj.set(cx, "max", js_max)?;
+                if let Some(step) = step {
+                    let js_step = to_js(cx, step)?;
+                    obj.set(cx, "step", js_step)?;
+                }
+            }
+        }
+
+        Ok(obj)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PipewireFormatChoices {
+    pub sample_format: Option<FormatChoice<String>>,
+    pub rate: Option<FormatChoice<u32>>,
+    pub channels: Option<FormatChoice<u32>>,
+    pub video_format: Option<FormatChoice<String>>,
+    // width and height
+    pub size: Option<FormatChoice<(u32, u32)>>,
+    pub framerate: Option<FormatChoice<f64>>,
+}
+
+impl PipewireFormatChoices {
+    fn is_empty(&self) -> bool {
+        self.sample_format.is_none()
+            && self.rate.is_none()
+            && self.channels.is_none()
+            && self.video_format.is_none()
+            && self.size.is_none()
+            && self.framerate.is_none()
+    }
+
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let string = |cx: &mut Cx<'a>, value: &String| -> JsResult<'a, JsValue> {
+            Ok(cx.string(value).upcast())
+        };
+        let number = |cx: &mut Cx<'a>, value: &u32| -> JsResult<'a, JsValue> {
+            Ok(cx.number(*value).upcast())
+        };
+
+        // only set the fields that are a choice
+        if let Some(sample_format) = &self.sample_format {
+            let js_sample_format = sample_format.to_object(cx, string)?;
+            obj.set(cx, "sample_format", js_sample_format)?;
+        }
+        if let Some(rate) = &self.rate {
+            let js_rate = rate.to_object(cx, number)?;
+            obj.set(cx, "rate", js_rate)?;
+        }
+        if let Some(channels) = &self.channels {
+            let js_channels = channels.to_object(cx, number)?;
+            obj.set(cx, "channels", js_channels)?;
+        }
+        if let Some(video_format) = &self.video_format {
+            let js_video_format = video_format.to_object(cx, string)?;
+            obj.set(cx, "video_format", js_video_format)?;
+        }
+        if let Some(size) = &self.size {
+            let js_size = size.to_object(cx, |cx, (width, height)| {
+                let js_size = cx.empty_object();
+                let js_width = cx.number(*width);
+                let js_height = cx.number(*height);
+                js_size.set(cx, "width", js_width)?;
+                js_size.set(cx, "height", js_height)?;
+                Ok(js_size.upcast())
+            })?;
+            obj.set(cx, "size", js_size)?;
+        }
+        if let Some(framerate) = &self.framerate {
+            let js_framerate =
+                framerate.to_object(cx, |cx, value| Ok(cx.number(*value).upcast()))?;
+            obj.set(cx, "framerate", js_framerate)?;
+        }
+
+        Ok(obj)
+    }
+}
+
+impl PipewireFormat {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_kind = cx.string(self.kind.clone());
+        let js_media_type = cx.string(self.media_type.clone());
+        let js_media_subtype = cx.string(self.media_subtype.clone());
+
+        obj.set(cx, "kind", js_kind)?;
+        obj.set(cx, "media_type", js_media_type)?;
+        obj.set(cx, "media_subtype", js_media_subtype)?;
+
+        // only set the fields that are known
+        if let Some(sample_format) = &self.sample_format {
+            let js_sample_format = cx.string(sample_format);
+            obj.set(cx, "sample_format", js_sample_format)?;
+        }
+        if let Some(rate) = self.rate {
+            let js_rate = cx.number(rate);
+            obj.set(cx, "rate", js_rate)?;
+        }
+        if let Some(channels) = self.channels {
+            let js_channels = cx.number(channels);
+            obj.set(cx, "channels", js_channels)?;
+        }
+        if let Some(video_format) = &self.video_format {
+            let js_video_format = cx.string(video_format);
+            obj.set(cx, "video_format", js_video_format)?;
+        }
+        if let Some(width) = self.width {
+            let js_width = cx.number(width);
+            obj.set(cx, "width", js_width)?;
+        }
+        if let Some(height) = self.height {
+            let js_height = cx.number(height);
+            obj.set(cx, "height", js_height)?;
+        }
+        if let Some(framerate) = self.framerate {
+            let js_framerate = cx.number(framerate);
+            obj.set(cx, "framerate", js_framerate)?;
+        }
+        if !self.choices.is_empty() {
+            let js_choices = self.choices.to_object(cx)?;
+            obj.set(cx, "choices", js_choices)?;
+        }
+
+        Ok(obj)
+    }
+}
+
+// Convert an optional format to a JS object or null.
+fn format_to_js<'a>(cx: &mut Cx<'a>, format: &Option<PipewireFormat>) -> JsResult<'a, JsValue> {
+    match format {
+        Some(format) => Ok(format.to_object(cx)?.upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+// Latency param of a port: latency of the graph up to the port in one direction.
+// The latency is the sum of the quantum, rate and ns values.
+#[derive(Clone, Debug, Default)]
+pub struct PipewireLatency {
+    pub direction: String,
+    pub min_quantum: f32,
+    pub max_quantum: f32,
+    pub min_rate: i32,
+    pub max_rate: i32,
+    pub min_ns: i64,
+    pub max_ns: i64,
+}
+
+impl PipewireLatency {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_direction = cx.string(self.direction.clone());
+        let js_min_quantum = cx.number(self.min_quantum);
+        let js_max_quantum = cx.number(self.max_quantum);
+        let js_min_rate = cx.number(self.min_rate);
+        let js_max_rate = cx.number(self.max_rate);
+        let js_min_ns = cx.number(self.min_ns as f64);
+        let js_max_ns = cx.number(self.max_ns as f64);
+
+        obj.set(cx, "direction", js_direction)?;
+        obj.set(cx, "min_quantum", js_min_quantum)?;
+        obj.set(cx, "max_quantum", js_max_quantum)?;
+        obj.set(cx, "min_rate", js_min_rate)?;
+        obj.set(cx, "max_rate", js_max_rate)?;
+        obj.set(cx, "min_ns", js_min_ns)?;
+        obj.set(cx, "max_ns", js_max_ns)?;
+
+        Ok(obj)
+    }
+}
+
+// ProcessLatency param of a node or port: latency added by the object itself.
+#[derive(Clone, Debug, Default)]
+pub struct PipewireProcessLatency {
+    pub quantum: f32,
+    pub rate: i32,
+    pub ns: i64,
+}
+
+impl PipewireProcessLatency {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_quantum = cx.number(self.quantum);
+        let js_rate = cx.number(self.rate);
+        let js_ns = cx.number(self.ns as f64);
+
+        obj.set(cx, "quantum", js_quantum)?;
+        obj.set(cx, "rate", js_rate)?;
+        obj.set(cx, "ns", js_ns)?;
+
+        Ok(obj)
+    }
+}
+
+// Convert an optional process latency to a JS object or null.
+fn process_latency_to_js<'a>(
+    cx: &mut Cx<'a>,
+    latency: &Option<PipewireProcessLatency>,
+) -> JsResult<'a, JsValue> {
+    match latency {
+        Some(latency) => Ok(latency.to_object(cx)?.upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+// Permissions of this connection on an object.
+fn permission_flags_to_object<'a>(
+    cx: &mut Cx<'a>,
+    permissions: PermissionFlags,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let js_read = cx.boolean(permissions.contains(PermissionFlags::R));
+    let js_write = cx.boolean(permissions.contains(PermissionFlags::W));
+    let js_execute = cx.boolean(permissions.contains(PermissionFlags::X));
+    let js_metadata = cx.boolean(permissions.contains(PermissionFlags::M));
+    let js_link = cx.boolean(permissions.contains(permissions::LINK));
+
+    obj.set(cx, "read", js_read)?;
+    obj.set(cx, "write", js_write)?;
+    obj.set(cx, "execute", js_execute)?;
+    obj.set(cx, "metadata", js_metadata)?;
+    obj.set(cx, "link", js_link)?;
+
+    Ok(obj)
+}
+
+// Convert an optional string to a JS string or null.
+fn optional_string<'a>(cx: &mut Cx<'a>, value: &Option<String>) -> JsResult<'a, JsValue> {
+    match value {
+        Some(value) => Ok(cx.string(value).upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+// Convert an optional number to a JS number or null.
+fn optional_number<'a>(cx: &mut Cx<'a>, value: Option<u32>) -> JsResult<'a, JsValue> {
+    match value {
+        Some(value) => Ok(cx.number(value).upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PipewirePort {
     pub id: u32,
@@ -21,6 +347,13 @@ pub struct PipewirePort {
     pub node_id: u32,
     pub name: String,
     pub direction: String,
+    // Negotiated format, None until the port is linked.
+    pub format: Option<PipewireFormat>,
+    // Formats supported by the port (EnumFormat).
+    pub formats: Vec<PipewireFormat>,
+    // Latency params, one per direction.
+    pub latency: Vec<PipewireLatency>,
+    pub process_latency: Option<PipewireProcessLatency>,
 }
 
 impl PipewirePort {
@@ -29,9 +362,24 @@ impl PipewirePort {
 
         let js_id = cx.number(self.id);
         let js_permissions = cx.number(self.permissions.bits() as i32);
+        let js_permission_flags = permission_flags_to_object(cx, self.permissions)?;
         let js_node_id = cx.number(self.node_id as i32);
         let js_name = cx.string(self.name.clone());
         let js_direction = cx.string(self.direction.clone());
+        let js_format = format_to_js(cx, &self.format)?;
+
+        let js_formats = cx.empty_array();
+        for (i, format) in self.formats.iter().enumerate() {
+            let js_format = format.to_object(cx)?;
+            js_formats.set(cx, i as u32, js_format)?;
+        }
+
+        let js_latency = cx.empty_array();
+        for (i, latency) in self.latency.iter().enumerate() {
+            let js_item = latency.to_object(cx)?;
+            js_latency.set(cx, i as u32, js_item)?;
+        }
+        let js_process_latency = process_latency_to_js(cx, &self.process_latency)?;
 
         let js_props = cx.empty_object();
         for entry in &self.props {
@@ -42,10 +390,15 @@ impl PipewirePort {
 
         obj.set(cx, "id", js_id)?;
         obj.set(cx, "permissions", js_permissions)?;
+        obj.set(cx, "permission_flags", js_permission_flags)?;
         obj.set(cx, "props", js_props)?;
         obj.set(cx, "node_id", js_node_id)?;
         obj.set(cx, "name", js_name)?;
         obj.set(cx, "direction", js_direction)?;
+        obj.set(cx, "format", js_format)?;
+        obj.set(cx, "formats", js_formats)?;
+        obj.set(cx, "latency", js_latency)?;
+        obj.set(cx, "process_latency", js_process_latency)?;
 
         Ok(obj)
     }
@@ -59,7 +412,18 @@ pub struct PipewireNode {
     pub name: String,
     pub node_direction: String,
     pub node_type: String,
+    // Classification from the media.class (see classify.rs): device, stream, virtual, bridge or filter,
+    // and sink, source, duplex, playback or capture.
+    pub category: String,
+    pub kind: String,
+    pub is_monitor: bool,
     pub ports: Vec<PipewirePort>,
+    // Quantum and rate requested by the node (node.latency, node.force-quantum, ...).
+    pub quantum: Option<u32>,
+    pub rate: Option<u32>,
+    pub process_latency: Option<PipewireProcessLatency>,
+    // Profiler stats of the last interval, only collected once getNodeStats() was called.
+    pub stats: Option<PipewireNodeStats>,
 }
 
 impl PipewireNode {
@@ -68,9 +432,20 @@ impl PipewireNode {
 
         let js_id = cx.number(self.id);
         let js_permissions = cx.number(self.permissions.bits() as i32);
+        let js_permission_flags = permission_flags_to_object(cx, self.permissions)?;
         let js_name = cx.string(self.name.clone());
         let js_node_direction = cx.string(self.node_direction.clone());
         let js_node_type = cx.string(self.node_type.clone());
+        let js_category = cx.string(self.category.clone());
+        let js_kind = cx.string(self.kind.clone());
+        let js_is_monitor = cx.boolean(self.is_monitor);
+        let js_quantum = optional_number(cx, self.quantum)?;
+        let js_rate = optional_number(cx, self.rate)?;
+        let js_process_latency = process_latency_to_js(cx, &self.process_latency)?;
+        let js_stats: Handle<JsValue> = match &self.stats {
+            Some(stats) => stats.to_object(cx)?.upcast(),
+            None => cx.null().upcast(),
+        };
 
         let js_ports = cx.empty_array();
         for (i, port) in self.ports.iter().enumerate() {
@@ -87,11 +462,19 @@ impl PipewireNode {
 
         obj.set(cx, "id", js_id)?;
         obj.set(cx, "permissions", js_permissions)?;
+        obj.set(cx, "permission_flags", js_permission_flags)?;
         obj.set(cx, "props", js_props)?;
         obj.set(cx, "name", js_name)?;
         obj.set(cx, "node_direction", js_node_direction)?;
         obj.set(cx, "node_type", js_node_type)?;
+        obj.set(cx, "category", js_category)?;
+        obj.set(cx, "kind", js_kind)?;
+        obj.set(cx, "is_monitor", js_is_monitor)?;
         obj.set(cx, "ports", js_ports)?;
+        obj.set(cx, "quantum", js_quantum)?;
+        obj.set(cx, "rate", js_rate)?;
+        obj.set(cx, "process_latency", js_process_latency)?;
+        obj.set(cx, "stats", js_stats)?;
 
         Ok(obj)
     }
@@ -106,6 +489,8 @@ pub struct PipewireLink {
     pub input_port_id: u32,
     pub output_node_id: u32,
     pub output_port_id: u32,
+    // Format negotiated between both ports.
+    pub format: Option<PipewireFormat>,
 }
 
 impl PipewireLink {
@@ -114,10 +499,12 @@ impl PipewireLink {
 
         let js_id = cx.number(self.id);
         let js_permissions = cx.number(self.permissions.bits() as i32);
+        let js_permission_flags = permission_flags_to_object(cx, self.permissions)?;
         let js_input_node_id = cx.number(self.input_node_id as i32);
         let js_input_port_id = cx.number(self.input_port_id as i32);
         let js_output_node_id = cx.number(self.output_node_id as i32);
         let js_output_port_id = cx.number(self.output_port_id as i32);
+        let js_format = format_to_js(cx, &self.format)?;
 
         let js_props = cx.empty_object();
         for entry in &self.props {
@@ -128,11 +515,13 @@ impl PipewireLink {
 
         obj.set(cx, "id", js_id)?;
         obj.set(cx, "permissions", js_permissions)?;
+        obj.set(cx, "permission_flags", js_permission_flags)?;
         obj.set(cx, "props", js_props)?;
         obj.set(cx, "input_node_id", js_input_node_id)?;
         obj.set(cx, "input_port_id", js_input_port_id)?;
         obj.set(cx, "output_node_id", js_output_node_id)?;
         obj.set(cx, "output_port_id", js_output_port_id)?;
+        obj.set(cx, "format", js_format)?;
 
         Ok(obj)
     }
@@ -142,9 +531,18 @@ impl PipewireLink {
 pub struct PipewireClient {
     pub id: u32,
     pub permissions: PermissionFlags,
-    pub pid: u32,
-    pub application_name: String,
+    // Unknown for some clients (portals, sandboxed or remote clients...).
+    pub pid: Option<u32>,
+    pub application_name: Option<String>,
     pub props: HashMap<String, String>,
+    pub process_binary: Option<String>,
+    pub process_user: Option<String>,
+    // How the client is allowed to access the server (unrestricted, flatpak, restricted...).
+    pub access: Option<String>,
+    pub is_flatpak: bool,
+    // Id of the sandboxed application, given by the portal.
+    pub app_id: Option<String>,
+    pub icon_name: Option<String>,
 }
 
 impl PipewireClient {
@@ -153,8 +551,15 @@ impl PipewireClient {
 
         let js_id = cx.number(self.id);
         let js_permissions = cx.number(self.permissions.bits() as i32);
-        let js_pid = cx.number(self.pid as i32);
-        let js_application_name = cx.string(self.application_name.clone());
+        let js_permission_flags = permission_flags_to_object(cx, self.permissions)?;
+        let js_pid = optional_number(cx, self.pid)?;
+        let js_application_name = optional_string(cx, &self.application_name)?;
+        let js_process_binary = optional_string(cx, &self.process_binary)?;
+        let js_process_user = optional_string(cx, &self.process_user)?;
+        let js_access = optional_string(cx, &self.access)?;
+        let js_is_flatpak = cx.boolean(self.is_flatpak);
+        let js_app_id = optional_string(cx, &self.app_id)?;
+        let js_icon_name = optional_string(cx, &self.icon_name)?;
 
         let js_props = cx.empty_object();
         for entry in &self.props {
@@ -165,9 +570,224 @@ impl PipewireClient {
 
         obj.set(cx, "id", js_id)?;
         obj.set(cx, "permissions", js_permissions)?;
+        obj.set(cx, "permission_flags", js_permission_flags)?;
         obj.set(cx, "pid", js_pid)?;
         obj.set(cx, "props", js_props)?;
         obj.set(cx, "application_name", js_application_name)?;
+        obj.set(cx, "process_binary", js_process_binary)?;
+        obj.set(cx, "process_user", js_process_user)?;
+        obj.set(cx, "access", js_access)?;
+        obj.set(cx, "is_flatpak", js_is_flatpak)?;
+        obj.set(cx, "app_id", js_app_id)?;
+        obj.set(cx, "icon_name", js_icon_name)?;
+
+        Ok(obj)
+    }
+}
+
+// A property of a metadata object, the value of `key` for the global `subject`.
+#[derive(Clone, Debug)]
+pub struct PipewireMetadataProperty {
+    pub subject: u32,
+    pub key: String,
+    pub type_: Option<String>,
+    pub value: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct PipewireMetadata {
+    pub id: u32,
+    pub permissions: PermissionFlags,
+    pub props: HashMap<String, String>,
+    pub name: String,
+    pub properties: Vec<PipewireMetadataProperty>,
+}
+
+impl PipewireMetadataProperty {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_subject = cx.number(self.subject);
+        let js_key = cx.string(self.key.clone());
+        let js_type = optional_string(cx, &self.type_)?;
+        let js_value = cx.string(self.value.clone());
+
+        obj.set(cx, "subject", js_subject)?;
+        obj.set(cx, "key", js_key)?;
+        obj.set(cx, "type", js_type)?;
+        obj.set(cx, "value", js_value)?;
+
+        Ok(obj)
+    }
+}
+
+impl PipewireMetadata {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_id = cx.number(self.id);
+        let js_permissions = cx.number(self.permissions.bits() as i32);
+        let js_permission_flags = permission_flags_to_object(cx, self.permissions)?;
+        let js_name = cx.string(self.name.clone());
+
+        let js_props = cx.empty_object();
+        for entry in &self.props {
+            let prop = cx.string(entry.1);
+            let key = entry.0.as_str();
+            js_props.set(cx, key, prop)?;
+        }
+
+        let js_properties = cx.empty_array();
+        for (i, property) in self.properties.iter().enumerate() {
+            let js_property = property.to_object(cx)?;
+            js_properties.set(cx, i as u32, js_property)?;
+        }
+
+        obj.set(cx, "id", js_id)?;
+        obj.set(cx, "permissions", js_permissions)?;
+        obj.set(cx, "permission_flags", js_permission_flags)?;
+        obj.set(cx, "props", js_props)?;
+        obj.set(cx, "name", js_name)?;
+        obj.set(cx, "properties", js_properties)?;
+
+        Ok(obj)
+    }
+}
+
+// A device (a sound card, a camera, a Bluetooth headset...), its nodes have its id in their device.id prop.
+#[derive(Clone, Debug)]
+pub struct PipewireDevice {
+    pub id: u32,
+    pub permissions: PermissionFlags,
+    pub props: HashMap<String, String>,
+    pub name: String,
+}
+
+impl PipewireDevice {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_id = cx.number(self.id);
+        let js_permissions = cx.number(self.permissions.bits() as i32);
+        let js_permission_flags = permission_flags_to_object(cx, self.permissions)?;
+        let js_name = cx.string(self.name.clone());
+
+        let js_props = cx.empty_object();
+        for entry in &self.props {
+            let prop = cx.string(entry.1);
+            let key = entry.0.as_str();
+            js_props.set(cx, key, prop)?;
+        }
+
+        obj.set(cx, "id", js_id)?;
+        obj.set(cx, "permissions", js_permissions)?;
+        obj.set(cx, "permission_flags", js_permission_flags)?;
+        obj.set(cx, "props", js_props)?;
+        obj.set(cx, "name", js_name)?;
+
+        Ok(obj)
+    }
+}
+
+// Timing of a node aggregated over the cycles of one profiler interval.
+#[derive(Clone, Debug, Default)]
+pub struct PipewireNodeStats {
+    pub id: u32,
+    pub name: String,
+    pub driver_id: u32,
+    pub quantum: u32,
+    pub rate: u32,
+    pub cycles: u32,
+    // Average and max time (in ns) between the driver signal and the node wake up.
+    pub wait_ns: f64,
+    pub max_wait_ns: f64,
+    // Average and max time (in ns) the node took to process a cycle.
+    pub busy_ns: f64,
+    pub max_busy_ns: f64,
+    // Average busy time, in percent of the period of the driver.
+    pub dsp_load: f64,
+    pub xruns: u32,
+}
+
+impl PipewireNodeStats {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_id = cx.number(self.id);
+        let js_name = cx.string(self.name.clone());
+        let js_driver_id = cx.number(self.driver_id);
+        let js_quantum = cx.number(self.quantum);
+        let js_rate = cx.number(self.rate);
+        let js_cycles = cx.number(self.cycles);
+        let js_wait_ns = cx.number(self.wait_ns);
+        let js_max_wait_ns = cx.number(self.max_wait_ns);
+        let js_busy_ns = cx.number(self.busy_ns);
+        let js_max_busy_ns = cx.number(self.max_busy_ns);
+        let js_dsp_load = cx.number(self.dsp_load);
+        let js_xruns = cx.number(self.xruns);
+
+        obj.set(cx, "id", js_id)?;
+        obj.set(cx, "name", js_name)?;
+        obj.set(cx, "driver_id", js_driver_id)?;
+        obj.set(cx, "quantum", js_quantum)?;
+        obj.set(cx, "rate", js_rate)?;
+        obj.set(cx, "cycles", js_cycles)?;
+        obj.set(cx, "wait_ns", js_wait_ns)?;
+        obj.set(cx, "max_wait_ns", js_max_wait_ns)?;
+        obj.set(cx, "busy_ns", js_busy_ns)?;
+        obj.set(cx, "max_busy_ns", js_max_busy_ns)?;
+        obj.set(cx, "dsp_load", js_dsp_load)?;
+        obj.set(cx, "xruns", js_xruns)?;
+
+        Ok(obj)
+    }
+}
+
+// Timing of a driver and of the nodes following it over one profiler interval.
+#[derive(Clone, Debug, Default)]
+pub struct PipewireDriverStats {
+    pub id: u32,
+    pub name: String,
+    pub quantum: u32,
+    pub rate: u32,
+    pub cycles: u32,
+    // Duration of a cycle in ns (quantum / rate).
+    pub period_ns: f64,
+    // CPU load of the driver graph (fast, medium and slow averages).
+    pub cpu_load: Vec<f32>,
+    pub xruns: u32,
+    // The driver itself is the first node.
+    pub nodes: Vec<PipewireNodeStats>,
+}
+
+impl PipewireDriverStats {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let js_id = cx.number(self.id);
+        let js_name = cx.string(self.name.clone());
+        let js_quantum = cx.number(self.quantum);
+        let js_rate = cx.number(self.rate);
+        let js_cycles = cx.number(self.cycles);
+        let js_period_ns = cx.number(self.period_ns);
+        let js_cpu_load = f32_array(cx, &self.cpu_load)?;
+        let js_xruns = cx.number(self.xruns);
+
+        let js_nodes = cx.empty_array();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let js_node = node.to_object(cx)?;
+            js_nodes.set(cx, i as u32, js_node)?;
+        }
+
+        obj.set(cx, "id", js_id)?;
+        obj.set(cx, "name", js_name)?;
+        obj.set(cx, "quantum", js_quantum)?;
+        obj.set(cx, "rate", js_rate)?;
+        obj.set(cx, "cycles", js_cycles)?;
+        obj.set(cx, "period_ns", js_period_ns)?;
+        obj.set(cx, "cpu_load", js_cpu_load)?;
+        obj.set(cx, "xruns", js_xruns)?;
+        obj.set(cx, "nodes", js_nodes)?;
 
         Ok(obj)
     }
@@ -180,6 +800,21 @@ pub enum PipewireData {
     Port(PipewirePort),
     Node(PipewireNode),
     Client(PipewireClient),
+    Metadata(PipewireMetadata),
+    Device(PipewireDevice),
+}
+
+impl PipewireData {
+    fn to_object<'a>(&self, cx: &mut Cx<'a>) -> JsResult<'a, JsObject> {
+        match self {
+            PipewireData::Node(node) => node.to_object(cx),
+            PipewireData::Port(port) => port.to_object(cx),
+            PipewireData::Link(link) => link.to_object(cx),
+            PipewireData::Client(client) => client.to_object(cx),
+            PipewireData::Metadata(metadata) => metadata.to_object(cx),
+            PipewireData::Device(device) => device.to_object(cx),
+        }
+    }
 }
 
 // Create an enum with all the options that are available to send in front. (Pipewire thread -> Front)
@@ -216,53 +851,316 @@ enum MainOptions {
     CreateClient {
         id: u32,
         permissions: PermissionFlags,
-        pid: u32,
-        application_name: String,
+        pid: Option<u32>,
+        application_name: Option<String>,
         props: HashMap<String, String>,
     },
-
-    // Delete item (node, port, link).
-    DeleteItem {
+    // Create a metadata object.
+    CreateMetadata {
         id: u32,
+        permissions: PermissionFlags,
+        props: HashMap<String, String>,
+        name: String,
+    },
+    // Create a device.
+    CreateDevice {
+        id: u32,
+        permissions: PermissionFlags,
+        props: HashMap<String, String>,
+        name: String,
     },
-}
 
-// Create an enum with all the options that are available to send in back. (Front -> Pipewire thread)
-enum PipewireOptions {
-    // Close the mainloop of the pipewire thread.
-    CloseThread,
-    // TODO: add more options (LinkNodes, UnLinkNodes, Delete...).
-    LinkPorts {
-        input_port: u32,
-        output_port: u32,
-        permanent: bool,
+    // Params (formats, latency) of a port changed.
+    UpdatePortParams {
+        id: u32,
+        format: Option<PipewireFormat>,
+        formats: Vec<PipewireFormat>,
+        latency: Vec<PipewireLatency>,
+        process_latency: Option<PipewireProcessLatency>,
     },
-    UnLinkPorts {
-        input_port: u32,
-        output_port: u32,
+    // Latency of a node changed.
+    UpdateNodeLatency {
+        id: u32,
+        quantum: Option<u32>,
+        rate: Option<u32>,
+        process_latency: Option<PipewireProcessLatency>,
     },
-    LinkNodesNameToId {
-        output_nodes_name: String,
-        input_node_id: u32,
-        permanent: bool,
+    // Negotiated format of a link changed.
+    UpdateLinkFormat {
+        id: u32,
+        format: Option<PipewireFormat>,
     },
-    UnLinkNodesNameToId {
-        output_nodes_name: String,
-        input_node_id: u32,
+
+    // A property of a metadata object changed.
+    // No key means all the properties of the subject were removed, no value that the key was removed.
+    MetadataProperty {
+        id: u32,
+        subject: u32,
+        key: Option<String>,
+        type_: Option<String>,
+        value: Option<String>,
     },
-    CreateSource {
-        source_name: String,
-        audio_position: String,
-        channel_count: u32,
-        permanent: bool,
+
+    // Permissions of the globals for a client, the answer of the GetClientPermissions `id`.
+    ClientPermissions {
+        id: u32,
+        permissions: Vec<(u32, PermissionFlags)>,
     },
-    CreateSink {
-        sink_name: String,
-        audio_position: String,
-        channel_count: u32,
-        permanent: bool,
+    // The server processed an UpdateClientPermissions.
+    ClientPermissionsUpdated {
+        id: u32,
     },
-    DeleteObject {
+    // The request or update `id` failed (client not bound, or refused by the server).
+    ClientPermissionsFailed {
+        id: u32,
+        error: String,
+    },
+
+    // The server answered a DisconnectClient, the client is gone if it allowed it.
+    ClientDisconnected {
+        id: u32,
+        client_id: u32,
+    },
+
+    // A proxy created by this connection (link, virtual device) was bound to its global.
+    ObjectOwned {
+        id: u32,
+        lingering: bool,
+    },
+
+    // The server destroyed the objects of a CleanupOwnedObjects.
+    ObjectsCleanedUp {
+        done: mpsc::Sender<()>,
+    },
+
+    // Delete item (node, port, link).
+    DeleteItem {
+        id: u32,
+    },
+
+    // Peak and RMS levels (in dBFS) of a meter.
+    MeterLevels {
+        id: u32,
+        peak: Vec<f32>,
+        rms: Vec<f32>,
+    },
+    // Magnitude (in dBFS) of the frequency bands of a spectrum analyzer.
+    SpectrumBands {
+        id: u32,
+        bands: Vec<f32>,
+    },
+    // The signal of an activity detector crossed its threshold.
+    ActivityChanged {
+        id: u32,
+        active: bool,
+        level: f32,
+    },
+    // A capture (meter, ...) was closed, usually because its node disappeared.
+    CaptureClosed {
+        id: u32,
+    },
+    // Profiler stats aggregated over the interval of a subscription.
+    ProfilerStats {
+        id: u32,
+        drivers: Vec<PipewireDriverStats>,
+    },
+    // The first interval of the node stats ended, even if no node ran.
+    NodeStatsReady,
+    // getNodeStats() wasn't called for a while, its profiler subscription was stopped.
+    NodeStatsStopped,
+    // The node of a MIDI port was created.
+    MidiPortReady {
+        id: u32,
+        node_id: u32,
+    },
+    // MIDI events received by an input MIDI port during a cycle.
+    MidiEvents {
+        id: u32,
+        events: Vec<midi::MidiEvent>,
+    },
+    // A MIDI port was closed by the server.
+    MidiPortClosed {
+        id: u32,
+    },
+    // The node of a video capture or of a virtual camera was created.
+    VideoStreamReady {
+        id: u32,
+        node_id: u32,
+    },
+    // A frame received by a video capture, `in_flight` must be cleared once it's handed to JS.
+    VideoFrame {
+        id: u32,
+        frame: video::VideoFrame,
+        in_flight: Arc<AtomicBool>,
+    },
+    // A video stream was closed, usually because its node disappeared.
+    VideoStreamClosed {
+        id: u32,
+    },
+    // The registry announced both nodes of a loopback, they were sent to the front before.
+    LoopbackLoaded {
+        id: u32,
+        capture_id: u32,
+        playback_id: u32,
+    },
+    // The module failed to load, or its nodes were not announced in time (the module is unloaded).
+    LoopbackFailed {
+        id: u32,
+        error: String,
+    },
+}
+
+// Create an enum with all the options that are available to send in back. (Front -> Pipewire thread)
+enum PipewireOptions {
+    // Close the mainloop of the pipewire thread.
+    CloseThread,
+    // TODO: add more options (LinkNodes, UnLinkNodes, Delete...).
+    LinkPorts {
+        input_port: u32,
+        output_port: u32,
+        permanent: bool,
+    },
+    UnLinkPorts {
+        input_port: u32,
+        output_port: u32,
+    },
+    LinkNodesNameToId {
+        output_nodes_name: String,
+        input_node_id: u32,
+        permanent: bool,
+    },
+    UnLinkNodesNameToId {
+        output_nodes_name: String,
+        input_node_id: u32,
+    },
+    // Create a virtual device (adapter node) with the properties checked by device::DeviceOptions.
+    CreateDevice {
+        props: Vec<(String, String)>,
+        permanent: bool,
+    },
+    DeleteObject {
+        id: u32,
+    },
+    SetProcessLatency {
+        node_id: u32,
+        latency: PipewireProcessLatency,
+    },
+    // Set (or remove, without value) a property of a metadata object.
+    SetMetadataProperty {
+        id: u32,
+        subject: u32,
+        key: String,
+        type_: Option<String>,
+        value: Option<String>,
+    },
+    // Remove all the properties of a metadata object.
+    ClearMetadata {
+        id: u32,
+    },
+    // Replace the links of a stream by links to another node.
+    MoveStreamLinks {
+        stream_id: u32,
+        target_id: u32,
+    },
+    // Request the permissions of the globals for a client.
+    GetClientPermissions {
+        id: u32,
+        client_id: u32,
+    },
+    // Set the permissions of globals (by id) for a client.
+    UpdateClientPermissions {
+        id: u32,
+        client_id: u32,
+        permissions: Vec<(u32, PermissionFlags)>,
+    },
+    // Destroy the owned nodes and links (the lingering ones too with include_lingering), `done` is
+    // notified once the server destroyed them.
+    CleanupOwnedObjects {
+        include_lingering: bool,
+        done: mpsc::Sender<()>,
+    },
+    // Release the proxy of an owned object, which destroys it unless it's lingering.
+    ReleaseProxy {
+        id: u32,
+    },
+    // Destroy the global of a client, which disconnects it.
+    DisconnectClient {
+        id: u32,
+        client_id: u32,
+    },
+    CreateMeter {
+        id: u32,
+        node_id: u32,
+        channel: Option<usize>,
+        interval_ms: u64,
+    },
+    CreateSpectrumAnalyzer {
+        id: u32,
+        node_id: u32,
+        fft_size: usize,
+        bands: usize,
+        interval_ms: u64,
+    },
+    CreateActivityDetector {
+        id: u32,
+        node_id: u32,
+        threshold_db: f32,
+        hold_ms: u64,
+    },
+    StopCapture {
+        id: u32,
+    },
+    SubscribeProfiler {
+        id: u32,
+        interval_ms: u64,
+    },
+    UnsubscribeProfiler {
+        id: u32,
+    },
+    // Start or keep the profiler subscription of the node stats (NODE_STATS_ID).
+    RequestNodeStats,
+    // Create a Midi/Bridge node with an output port (sending the messages of SendMidi) or an input port.
+    CreateMidiPort {
+        id: u32,
+        name: String,
+        output: bool,
+    },
+    SendMidi {
+        id: u32,
+        message: Vec<u8>,
+    },
+    CloseMidiPort {
+        id: u32,
+    },
+    // Capture the raw frames of a video node, the format and the size of the node are used when None.
+    CreateVideoCapture {
+        id: u32,
+        node_id: u32,
+        format: Option<String>,
+        size: Option<(u32, u32)>,
+    },
+    // Create a Video/Source node publishing the frames of SendVideoFrame.
+    CreateVirtualCamera {
+        id: u32,
+        name: String,
+        format: String,
+        width: u32,
+        height: u32,
+    },
+    SendVideoFrame {
+        id: u32,
+        frame: Vec<u8>,
+    },
+    CloseVideoStream {
+        id: u32,
+    },
+    // Load module-loopback in the context of the pipewire thread.
+    CreateLoopback {
+        id: u32,
+        options: loopback::LoopbackOptions,
+    },
+    // Unload the module of a loopback, which destroys its nodes.
+    DestroyLoopback {
         id: u32,
     },
 }
@@ -273,6 +1171,254 @@ lazy_static! {
         Arc::new(Mutex::new(HashMap::new()));
 }
 
+// A JS callback that receives the events of a capture (meter, ...).
+struct JsCallback {
+    channel: Channel,
+    callback: Arc<Root<JsFunction>>,
+}
+
+// store the JS callbacks by the id used in the pipewire thread
+lazy_static! {
+    static ref CALLBACKS: Mutex<HashMap<u32, JsCallback>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_CALLBACK_ID: AtomicU32 = AtomicU32::new(1);
+
+// Profiler subscription feeding the stats of the nodes in ALL_DATA (callback ids start at 1).
+const NODE_STATS_ID: u32 = 0;
+
+// Disconnecting clients must be allowed explicitly.
+static CLIENT_DISCONNECT_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+// store the name of the metadata followed by every metadata subscription (by callback id)
+lazy_static! {
+    static ref METADATA_SUBSCRIPTIONS: Mutex<HashMap<u32, String>> = Mutex::new(HashMap::new());
+}
+
+// store the event subscriptions (by callback id) with their pending batch of events
+lazy_static! {
+    static ref EVENT_SUBSCRIPTIONS: Mutex<HashMap<u32, events::EventSubscription>> =
+        Mutex::new(HashMap::new());
+}
+
+// store the global ids of the proxies created by this connection (always locked after ALL_DATA)
+lazy_static! {
+    static ref OWNED_PROXIES: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+}
+
+// store the global ids of the owned proxies whose object outlives the proxy (always locked after OWNED_PROXIES)
+lazy_static! {
+    static ref LINGERING_PROXIES: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+}
+
+// Handle of an owned object given to JS, its proxy is released when it's garbage collected.
+struct ObjectHandle {
+    id: u32,
+    released: Cell<bool>,
+}
+
+impl ObjectHandle {
+    fn release(&self) {
+        if self.released.replace(true) {
+            return;
+        }
+
+        // the pipewire thread may be gone when the handle is collected at exit
+        PW_SENDER.with(|pw_sender| {
+            if let Some(pw_sender) = pw_sender.borrow().as_ref() {
+                let _ = pw_sender.send(PipewireOptions::ReleaseProxy { id: self.id });
+            }
+        });
+    }
+}
+
+impl Finalize for ObjectHandle {
+    fn finalize<'a, C: Context<'a>>(self, _: &mut C) {
+        self.release();
+    }
+}
+
+// store the ids of the open MIDI ports (their callback ids) with whether they are outputs
+lazy_static! {
+    static ref MIDI_PORTS: Mutex<HashMap<u32, bool>> = Mutex::new(HashMap::new());
+}
+
+// store the ids of the open video streams (their callback ids) with the frame size of the virtual cameras
+lazy_static! {
+    static ref VIDEO_STREAMS: Mutex<HashMap<u32, Option<usize>>> = Mutex::new(HashMap::new());
+}
+
+// store the client of every pending permissions request and update (by callback id)
+lazy_static! {
+    static ref PERMISSION_REQUESTS: Mutex<HashMap<u32, u32>> = Mutex::new(HashMap::new());
+    static ref PERMISSION_UPDATES: Mutex<HashMap<u32, u32>> = Mutex::new(HashMap::new());
+}
+
+// Calls to getNodeStats() waiting for the first stats (by callback id), and whether the stats are up to date.
+#[derive(Default)]
+struct NodeStatsWaiters {
+    ready: bool,
+    callbacks: Vec<u32>,
+}
+
+lazy_static! {
+    static ref NODE_STATS: Mutex<NodeStatsWaiters> = Mutex::new(NodeStatsWaiters::default());
+}
+
+fn node_stats(all_data: &HashMap<u32, PipewireData>) -> Vec<PipewireNodeStats> {
+    all_data
+        .values()
+        .filter_map(|data| match data {
+            PipewireData::Node(node) => node.stats.clone(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn node_stats_to_array<'a>(cx: &mut Cx<'a>, stats: &[PipewireNodeStats]) -> JsResult<'a, JsArray> {
+    let js_stats = cx.empty_array();
+    for (i, stats) in stats.iter().enumerate() {
+        let js_node_stats = stats.to_object(cx)?;
+        js_stats.set(cx, i as u32, js_node_stats)?;
+    }
+    Ok(js_stats)
+}
+
+// The node stats are up to date, answer the calls waiting for them.
+fn node_stats_ready() {
+    let mut waiters = NODE_STATS.lock().unwrap();
+    waiters.ready = true;
+    if waiters.callbacks.is_empty() {
+        return;
+    }
+
+    let stats = node_stats(&ALL_DATA.lock().unwrap());
+    for id in waiters.callbacks.drain(..) {
+        let stats = stats.clone();
+        emit_event(id, "stats", move |cx| {
+            Ok(node_stats_to_array(cx, &stats)?.upcast())
+        });
+        CALLBACKS.lock().unwrap().remove(&id);
+    }
+}
+
+// Answer the pending permissions requests and updates of a client with an event.
+fn end_permission_requests<F>(client_id: u32, event: &'static str, payload: F)
+where
+    F: for<'a> FnOnce(&mut Cx<'a>) -> JsResult<'a, JsValue> + Clone + Send + 'static,
+{
+    for requests in [&PERMISSION_REQUESTS, &PERMISSION_UPDATES] {
+        requests
+            .lock()
+            .unwrap()
+            .retain(|callback_id, request_client_id| {
+                if *request_client_id != client_id {
+                    return true;
+                }
+                emit_event(*callback_id, event, payload.clone());
+                CALLBACKS.lock().unwrap().remove(callback_id);
+                false
+            });
+    }
+}
+
+fn register_callback(channel: Channel, callback: Root<JsFunction>) -> u32 {
+    let id = NEXT_CALLBACK_ID.fetch_add(1, Ordering::Relaxed);
+
+    CALLBACKS.lock().unwrap().insert(
+        id,
+        JsCallback {
+            channel,
+            callback: Arc::new(callback),
+        },
+    );
+
+    id
+}
+
+// Call the JS callback with the id `id` as `callback(event, payload)`.
+fn emit_event<F>(id: u32, event: &'static str, payload: F)
+where
+    F: for<'a> FnOnce(&mut Cx<'a>) -> JsResult<'a, JsValue> + Send + 'static,
+{
+    let callbacks = CALLBACKS.lock().unwrap();
+
+    if let Some(js_callback) = callbacks.get(&id) {
+        let callback = js_callback.callback.clone();
+
+        js_callback.channel.send(move |mut cx| {
+            let js_payload = payload(&mut cx)?;
+            let js_event = cx.string(event).upcast::<JsValue>();
+            let this = cx.undefined();
+
+            callback
+                .to_inner(&mut cx)
+                .call(&mut cx, this, [js_event, js_payload])?;
+
+            Ok(())
+        });
+    }
+}
+
+fn f32_array<'a>(cx: &mut Cx<'a>, values: &[f32]) -> JsResult<'a, JsArray> {
+    let js_values = cx.empty_array();
+    for (i, value) in values.iter().enumerate() {
+        let js_value = cx.number(*value);
+        js_values.set(cx, i as u32, js_value)?;
+    }
+    Ok(js_values)
+}
+
+// Queue the change of the object `id` for the event subscriptions whose filter matches it.
+fn publish_event(all_data: &HashMap<u32, PipewireData>, change: events::Change, id: u32) {
+    let data = match all_data.get(&id) {
+        Some(data) => data,
+        None => return,
+    };
+    let now = Instant::now();
+
+    let mut subscriptions = EVENT_SUBSCRIPTIONS.lock().unwrap();
+    for subscription in subscriptions.values_mut() {
+        subscription.offer(all_data, change, data, now);
+    }
+}
+
+// Send the batches of events whose coalescing window ended, returns when the next window ends.
+fn flush_events() -> Option<Instant> {
+    let now = Instant::now();
+
+    let mut subscriptions = EVENT_SUBSCRIPTIONS.lock().unwrap();
+    for (id, subscription) in subscriptions.iter_mut() {
+        if let Some(batch) = subscription.batch.take_due(now) {
+            emit_event(*id, "events", move |cx| {
+                let js_events = cx.empty_array();
+                for (i, event) in batch.iter().enumerate() {
+                    let obj = cx.empty_object();
+
+                    let js_change = cx.string(event.change.as_str());
+                    let js_type = cx.string(query::type_name(&event.data));
+                    let js_object = event.data.to_object(cx)?;
+                    let js_id = cx.number(query::id_of(&event.data));
+
+                    obj.set(cx, "change", js_change)?;
+                    obj.set(cx, "type", js_type)?;
+                    obj.set(cx, "id", js_id)?;
+                    obj.set(cx, "object", js_object)?;
+
+                    js_events.set(cx, i as u32, obj)?;
+                }
+
+                Ok(js_events.upcast())
+            });
+        }
+    }
+
+    subscriptions
+        .values()
+        .filter_map(|subscription| subscription.batch.deadline())
+        .min()
+}
+
 // create a global variable that will store the sender of the main thread
 thread_local! {
     static PW_SENDER: RefCell<Option<pipewire::channel::Sender<PipewireOptions>>> = RefCell::new(None);
@@ -306,7 +1452,20 @@ fn create_pw_thread_internal(enable_debug: bool) {
 
     // Listen the main_receiver the options from "MainOptions" struct
     let _receiver = std::thread::spawn(move || loop {
-        let option = main_receiver.recv().unwrap();
+        // wait for the next option, or for the end of the next coalescing window of the events
+        let option = match flush_events() {
+            Some(deadline) => {
+                match main_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                {
+                    Ok(option) => option,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        panic!("ERROR: the pipewire thread is closed")
+                    }
+                }
+            }
+            None => main_receiver.recv().unwrap(),
+        };
         match option {
             MainOptions::CreateNode {
                 id,
@@ -324,6 +1483,8 @@ fn create_pw_thread_internal(enable_debug: bool) {
                 }
                 num_changes += 1;
 
+                let class = classify::classify(&props);
+
                 // add port to ALL_DATA
                 let mut all_data = ALL_DATA.lock().unwrap();
 
@@ -336,9 +1497,17 @@ fn create_pw_thread_internal(enable_debug: bool) {
                         name,
                         node_direction,
                         node_type,
+                        category: class.category,
+                        kind: class.kind,
+                        is_monitor: class.is_monitor,
                         ports: Vec::new(),
+                        quantum: None,
+                        rate: None,
+                        process_latency: None,
+                        stats: None,
                     }),
                 );
+                publish_event(&all_data, events::Change::Added, id);
             }
             MainOptions::CreatePort {
                 id,
@@ -367,6 +1536,10 @@ fn create_pw_thread_internal(enable_debug: bool) {
                     node_id,
                     name,
                     direction,
+                    format: None,
+                    formats: Vec::new(),
+                    latency: Vec::new(),
+                    process_latency: None,
                 };
 
                 all_data.insert(id, PipewireData::Port(new_port.clone()));
@@ -382,6 +1555,7 @@ fn create_pw_thread_internal(enable_debug: bool) {
                         _ => {}
                     }
                 }
+                publish_event(&all_data, events::Change::Added, id);
             }
             MainOptions::CreateLink {
                 id,
@@ -413,8 +1587,10 @@ fn create_pw_thread_internal(enable_debug: bool) {
                         input_port_id: input_port,
                         output_node_id: output_node,
                         output_port_id: output_port,
+                        format: None,
                     }),
                 );
+                publish_event(&all_data, events::Change::Added, id);
             }
             MainOptions::CreateClient {
                 id,
@@ -425,13 +1601,23 @@ fn create_pw_thread_internal(enable_debug: bool) {
             } => {
                 if enable_debug {
                     println!(
-                        "{} + Client added: id: {}, pid: {}, application_name: {}",
+                        "{} + Client added: id: {}, pid: {:?}, application_name: {:?}",
                         num_changes, id, pid, application_name,
                     );
                 }
                 num_changes += 1;
 
-                // add link to ALL_DATA
+                let prop = |key: &str| props.get(key).cloned();
+                let access = prop("pipewire.access");
+                let is_flatpak = props
+                    .get("pipewire.sec.flatpak")
+                    .is_some_and(|value| value == "true")
+                    || access.as_deref() == Some("flatpak");
+                let app_id = prop("pipewire.access.portal.app_id")
+                    .or_else(|| prop("pipewire.sec.app-id"))
+                    .or_else(|| prop("application.id"));
+
+                // add client to ALL_DATA
                 let mut all_data = ALL_DATA.lock().unwrap();
 
                 all_data.insert(
@@ -441,46 +1627,333 @@ fn create_pw_thread_internal(enable_debug: bool) {
                         permissions,
                         pid,
                         application_name,
+                        process_binary: prop("application.process.binary"),
+                        process_user: prop("application.process.user"),
+                        access,
+                        is_flatpak,
+                        app_id,
+                        icon_name: prop("application.icon-name"),
                         props,
                     }),
                 );
+                publish_event(&all_data, events::Change::Added, id);
             }
-            MainOptions::DeleteItem { id } => {
-                // remove item from ALL_DATA
-                let mut all_data = ALL_DATA.lock().unwrap();
-
-                // the next if is only to debug purposes
+            MainOptions::CreateMetadata {
+                id,
+                permissions,
+                props,
+                name,
+            } => {
                 if enable_debug {
-                    if let Some(item) = all_data.get(&id) {
-                        match item {
-                            PipewireData::Node(node) => {
-                                println!(
-                                    "{} - Removing node: id: {}, name: {}",
-                                    num_changes, node.id, node.name
-                                );
-                            }
-                            PipewireData::Port(port) => {
-                                println!(
-                                    "{} - Removing port: id: {}, name: {}",
-                                    num_changes, port.id, port.name
-                                );
-                            }
-                            PipewireData::Link(link) => {
-                                println!("{} - Removing link: id: {}, node_from: {}, port_from: {}, node_to: {}, port_to: {}", num_changes, link.id, link.input_node_id, link.input_port_id, link.output_node_id, link.output_port_id);
-                            }
-                            PipewireData::Client(client) => {
-                                println!(
-                                    "{} - Removing client: id: {}, pid: {}, application_name: {}",
-                                    num_changes, client.id, client.pid, client.application_name
-                                );
-                            }
-                        }
-                    } else {
-                        println!("{} - Removing unknown: {}", num_changes, id);
-                    }
+                    println!(
+                        "{} + Metadata added: id: {}, name: {}",
+                        num_changes, id, name
+                    );
                 }
+                num_changes += 1;
 
-                if let Some(_) = all_data.remove(&id) {
+                // add metadata to ALL_DATA, its properties are received after it
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                all_data.insert(
+                    id,
+                    PipewireData::Metadata(PipewireMetadata {
+                        id,
+                        permissions,
+                        props,
+                        name,
+                        properties: Vec::new(),
+                    }),
+                );
+                publish_event(&all_data, events::Change::Added, id);
+            }
+            MainOptions::CreateDevice {
+                id,
+                permissions,
+                props,
+                name,
+            } => {
+                if enable_debug {
+                    println!("{} + Device added: id: {}, name: {}", num_changes, id, name);
+                }
+                num_changes += 1;
+
+                // add device to ALL_DATA
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                all_data.insert(
+                    id,
+                    PipewireData::Device(PipewireDevice {
+                        id,
+                        permissions,
+                        props,
+                        name,
+                    }),
+                );
+                publish_event(&all_data, events::Change::Added, id);
+            }
+            MainOptions::UpdatePortParams {
+                id,
+                format,
+                formats,
+                latency,
+                process_latency,
+            } => {
+                if enable_debug {
+                    println!(
+                        "Port params changed: id: {}, format: {:?}, latency: {:?}",
+                        id, format, latency
+                    );
+                }
+
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                let updated_port = match all_data.get_mut(&id) {
+                    Some(PipewireData::Port(port)) => {
+                        port.format = format;
+                        port.formats = formats;
+                        port.latency = latency;
+                        port.process_latency = process_latency;
+                        port.clone()
+                    }
+                    _ => continue,
+                };
+
+                // update the copy of the port in its node
+                if let Some(PipewireData::Node(node)) = all_data.get_mut(&updated_port.node_id) {
+                    for port in node.ports.iter_mut().filter(|port| port.id == id) {
+                        *port = updated_port.clone();
+                    }
+                }
+                publish_event(&all_data, events::Change::Changed, id);
+            }
+            MainOptions::UpdateNodeLatency {
+                id,
+                quantum,
+                rate,
+                process_latency,
+            } => {
+                if enable_debug {
+                    println!(
+                        "Node latency changed: id: {}, quantum: {:?}, rate: {:?}",
+                        id, quantum, rate
+                    );
+                }
+
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                if let Some(PipewireData::Node(node)) = all_data.get_mut(&id) {
+                    node.quantum = quantum;
+                    node.rate = rate;
+                    node.process_latency = process_latency;
+                }
+                publish_event(&all_data, events::Change::Changed, id);
+            }
+            MainOptions::UpdateLinkFormat { id, format } => {
+                if enable_debug {
+                    println!("Link format changed: id: {}, format: {:?}", id, format);
+                }
+
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                if let Some(PipewireData::Link(link)) = all_data.get_mut(&id) {
+                    link.format = format;
+                }
+                publish_event(&all_data, events::Change::Changed, id);
+            }
+            MainOptions::MetadataProperty {
+                id,
+                subject,
+                key,
+                type_,
+                value,
+            } => {
+                if enable_debug {
+                    println!(
+                        "Metadata property changed: id: {}, subject: {}, key: {:?}, value: {:?}",
+                        id, subject, key, value
+                    );
+                }
+
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                let name = match all_data.get_mut(&id) {
+                    Some(PipewireData::Metadata(data)) => {
+                        metadata::apply_property(
+                            &mut data.properties,
+                            subject,
+                            key.clone(),
+                            type_.clone(),
+                            value.clone(),
+                        );
+                        data.name.clone()
+                    }
+                    _ => continue,
+                };
+                publish_event(&all_data, events::Change::Changed, id);
+                drop(all_data);
+
+                // notify the subscriptions to the metadata with this name
+                let subscriptions = METADATA_SUBSCRIPTIONS.lock().unwrap();
+                for (callback_id, _) in subscriptions.iter().filter(|entry| *entry.1 == name) {
+                    let key = key.clone();
+                    let type_ = type_.clone();
+                    let value = value.clone();
+
+                    emit_event(*callback_id, "change", move |cx| {
+                        let obj = cx.empty_object();
+
+                        let js_subject = cx.number(subject);
+                        let js_key = optional_string(cx, &key)?;
+                        let js_type = optional_string(cx, &type_)?;
+                        let js_value = optional_string(cx, &value)?;
+
+                        obj.set(cx, "subject", js_subject)?;
+                        obj.set(cx, "key", js_key)?;
+                        obj.set(cx, "type", js_type)?;
+                        obj.set(cx, "value", js_value)?;
+
+                        Ok(obj.upcast())
+                    });
+                }
+            }
+            MainOptions::ClientPermissions { id, permissions } => {
+                if enable_debug {
+                    println!(
+                        "Client permissions: id: {}, permissions: {:?}",
+                        id, permissions
+                    );
+                }
+
+                // the request may have been closed with its client already
+                if PERMISSION_REQUESTS.lock().unwrap().remove(&id).is_some() {
+                    emit_event(id, "permissions", move |cx| {
+                        let js_permissions = cx.empty_array();
+                        for (i, (id, flags)) in permissions.iter().enumerate() {
+                            let js_permission = permission_to_object(cx, *id, *flags)?;
+                            js_permissions.set(cx, i as u32, js_permission)?;
+                        }
+
+                        Ok(js_permissions.upcast())
+                    });
+                    CALLBACKS.lock().unwrap().remove(&id);
+                }
+            }
+            MainOptions::ClientPermissionsUpdated { id } => {
+                if enable_debug {
+                    println!("Client permissions updated: id: {}", id);
+                }
+
+                // the update may have been closed with its client already
+                if PERMISSION_UPDATES.lock().unwrap().remove(&id).is_some() {
+                    emit_event(id, "updated", |cx| Ok(cx.undefined().upcast()));
+                    CALLBACKS.lock().unwrap().remove(&id);
+                }
+            }
+            MainOptions::ClientPermissionsFailed { id, error } => {
+                if enable_debug {
+                    println!("Client permissions failed: id: {}, error: {}", id, error);
+                }
+
+                // the request may have been closed with its client already
+                let request = PERMISSION_REQUESTS.lock().unwrap().remove(&id);
+                let update = PERMISSION_UPDATES.lock().unwrap().remove(&id);
+                if request.is_some() || update.is_some() {
+                    emit_event(id, "error", move |cx| Ok(cx.string(error).upcast()));
+                    CALLBACKS.lock().unwrap().remove(&id);
+                }
+            }
+            MainOptions::ClientDisconnected { id, client_id } => {
+                // the removal of the client was received before the answer to the sync
+                let disconnected = !ALL_DATA.lock().unwrap().contains_key(&client_id);
+
+                if enable_debug {
+                    println!(
+                        "Client disconnection: id: {}, disconnected: {}",
+                        client_id, disconnected
+                    );
+                }
+
+                emit_event(id, "disconnected", move |cx| {
+                    Ok(cx.boolean(disconnected).upcast())
+                });
+                CALLBACKS.lock().unwrap().remove(&id);
+            }
+            MainOptions::ObjectOwned { id, lingering } => {
+                if enable_debug {
+                    println!("Owned object: id: {}, lingering: {}", id, lingering);
+                }
+
+                OWNED_PROXIES.lock().unwrap().insert(id);
+                if lingering {
+                    LINGERING_PROXIES.lock().unwrap().insert(id);
+                }
+            }
+            MainOptions::ObjectsCleanedUp { done } => {
+                if enable_debug {
+                    println!("Owned objects cleaned up");
+                }
+
+                // the cleanup may have timed out already
+                let _ = done.send(());
+            }
+            MainOptions::DeleteItem { id } => {
+                OWNED_PROXIES.lock().unwrap().remove(&id);
+                LINGERING_PROXIES.lock().unwrap().remove(&id);
+
+                // fail the permissions requests of a removed client
+                end_permission_requests(id, "close", |cx| Ok(cx.undefined().upcast()));
+
+                // remove item from ALL_DATA
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                // the next if is only to debug purposes
+                if enable_debug {
+                    if let Some(item) = all_data.get(&id) {
+                        match item {
+                            PipewireData::Node(node) => {
+                                println!(
+                                    "{} - Removing node: id: {}, name: {}",
+                                    num_changes, node.id, node.name
+                                );
+                            }
+                            PipewireData::Port(port) => {
+                                println!(
+                                    "{} - Removing port: id: {}, name: {}",
+                                    num_changes, port.id, port.name
+                                );
+                            }
+                            PipewireData::Link(link) => {
+                                println!("{} - Removing link: id: {}, node_from: {}, port_from: {}, node_to: {}, port_to: {}", num_changes, link.id, link.input_node_id, link.input_port_id, link.output_node_id, link.output_port_id);
+                            }
+                            PipewireData::Client(client) => {
+                                println!(
+                                    "{} - Removing client: id: {}, pid: {:?}, application_name: {:?}",
+                                    num_changes, client.id, client.pid, client.application_name
+                                );
+                            }
+                            PipewireData::Metadata(metadata) => {
+                                println!(
+                                    "{} - Removing metadata: id: {}, name: {}",
+                                    num_changes, metadata.id, metadata.name
+                                );
+                            }
+                            PipewireData::Device(device) => {
+                                println!(
+                                    "{} - Removing device: id: {}, name: {}",
+                                    num_changes, device.id, device.name
+                                );
+                            }
+                        }
+                    } else {
+                        println!("{} - Removing unknown: {}", num_changes, id);
+                    }
+                }
+
+                // the filters are matched while the object is still in ALL_DATA
+                publish_event(&all_data, events::Change::Removed, id);
+
+                if let Some(_) = all_data.remove(&id) {
                     num_changes += 1;
                 } else {
                     if enable_debug {
@@ -488,6 +1961,196 @@ fn create_pw_thread_internal(enable_debug: bool) {
                     }
                 }
             }
+            MainOptions::MeterLevels { id, peak, rms } => {
+                emit_event(id, "levels", move |cx| {
+                    let obj = cx.empty_object();
+
+                    let js_peak = f32_array(cx, &peak)?;
+                    let js_rms = f32_array(cx, &rms)?;
+
+                    obj.set(cx, "peak", js_peak)?;
+                    obj.set(cx, "rms", js_rms)?;
+
+                    Ok(obj.upcast())
+                });
+            }
+            MainOptions::SpectrumBands { id, bands } => {
+                emit_event(id, "bands", move |cx| {
+                    Ok(JsFloat32Array::from_slice(cx, &bands)?.upcast())
+                });
+            }
+            MainOptions::ActivityChanged { id, active, level } => {
+                let event = if active { "active" } else { "silent" };
+
+                emit_event(id, event, move |cx| {
+                    let obj = cx.empty_object();
+
+                    let js_level = cx.number(level);
+
+                    obj.set(cx, "level", js_level)?;
+
+                    Ok(obj.upcast())
+                });
+            }
+            MainOptions::ProfilerStats { id, drivers } if id == NODE_STATS_ID => {
+                let mut all_data = ALL_DATA.lock().unwrap();
+
+                let mut stats: HashMap<u32, PipewireNodeStats> = drivers
+                    .into_iter()
+                    .flat_map(|driver| driver.nodes)
+                    .map(|node| (node.id, node))
+                    .collect();
+
+                // nodes that didn't run in the last interval have no stats
+                for data in all_data.values_mut() {
+                    if let PipewireData::Node(node) = data {
+                        node.stats = stats.remove(&node.id);
+                    }
+                }
+                drop(all_data);
+
+                node_stats_ready();
+            }
+            MainOptions::NodeStatsReady => node_stats_ready(),
+            MainOptions::NodeStatsStopped => {
+                if enable_debug {
+                    println!("Node stats stopped");
+                }
+
+                NODE_STATS.lock().unwrap().ready = false;
+                for data in ALL_DATA.lock().unwrap().values_mut() {
+                    if let PipewireData::Node(node) = data {
+                        node.stats = None;
+                    }
+                }
+            }
+            MainOptions::ProfilerStats { id, drivers } => {
+                emit_event(id, "stats", move |cx| {
+                    let js_drivers = cx.empty_array();
+                    for (i, driver) in drivers.iter().enumerate() {
+                        let js_driver = driver.to_object(cx)?;
+                        js_drivers.set(cx, i as u32, js_driver)?;
+                    }
+
+                    Ok(js_drivers.upcast())
+                });
+            }
+            MainOptions::CaptureClosed { id } => {
+                if enable_debug {
+                    println!("Capture closed: id: {}", id);
+                }
+
+                emit_event(id, "close", |cx| Ok(cx.undefined().upcast()));
+                CALLBACKS.lock().unwrap().remove(&id);
+            }
+            MainOptions::MidiPortReady { id, node_id } => {
+                if enable_debug {
+                    println!("MIDI port ready: id: {}, node: {}", id, node_id);
+                }
+
+                emit_event(id, "ready", move |cx| Ok(cx.number(node_id).upcast()));
+            }
+            MainOptions::MidiEvents { id, events } => {
+                for event in events {
+                    emit_event(id, "midi", move |cx| {
+                        let obj = cx.empty_object();
+
+                        let js_time = cx.number(event.time);
+                        let js_offset = cx.number(event.offset);
+                        let js_data = JsBuffer::from_slice(cx, &event.data)?;
+
+                        obj.set(cx, "time", js_time)?;
+                        obj.set(cx, "offset", js_offset)?;
+                        obj.set(cx, "data", js_data)?;
+
+                        Ok(obj.upcast())
+                    });
+                }
+            }
+            MainOptions::MidiPortClosed { id } => {
+                if enable_debug {
+                    println!("MIDI port closed: id: {}", id);
+                }
+
+                MIDI_PORTS.lock().unwrap().remove(&id);
+                emit_event(id, "close", |cx| Ok(cx.undefined().upcast()));
+                CALLBACKS.lock().unwrap().remove(&id);
+            }
+            MainOptions::VideoStreamReady { id, node_id } => {
+                if enable_debug {
+                    println!("Video stream ready: id: {}, node: {}", id, node_id);
+                }
+
+                emit_event(id, "ready", move |cx| Ok(cx.number(node_id).upcast()));
+            }
+            MainOptions::VideoFrame {
+                id,
+                frame,
+                in_flight,
+            } => {
+                emit_event(id, "frame", move |cx| {
+                    // the capture sends the next frame from now on, at most one frame waits for JS
+                    in_flight.store(false, Ordering::Release);
+
+                    let obj = cx.empty_object();
+
+                    let js_data = JsBuffer::from_slice(cx, &frame.data)?;
+                    let js_stride = cx.number(frame.stride);
+                    let js_format = cx.string(frame.format);
+                    let js_width = cx.number(frame.width);
+                    let js_height = cx.number(frame.height);
+
+                    obj.set(cx, "data", js_data)?;
+                    obj.set(cx, "stride", js_stride)?;
+                    obj.set(cx, "format", js_format)?;
+                    obj.set(cx, "width", js_width)?;
+                    obj.set(cx, "height", js_height)?;
+
+                    Ok(obj.upcast())
+                });
+            }
+            MainOptions::VideoStreamClosed { id } => {
+                if enable_debug {
+                    println!("Video stream closed: id: {}", id);
+                }
+
+                VIDEO_STREAMS.lock().unwrap().remove(&id);
+                emit_event(id, "close", |cx| Ok(cx.undefined().upcast()));
+                CALLBACKS.lock().unwrap().remove(&id);
+            }
+            MainOptions::LoopbackLoaded {
+                id,
+                capture_id,
+                playback_id,
+            } => {
+                if enable_debug {
+                    println!(
+                        "Loopback loaded: id: {}, capture: {}, playback: {}",
+                        id, capture_id, playback_id
+                    );
+                }
+
+                emit_event(id, "loaded", move |cx| {
+                    let obj = cx.empty_object();
+
+                    let js_capture_id = cx.number(capture_id);
+                    let js_playback_id = cx.number(playback_id);
+
+                    obj.set(cx, "captureId", js_capture_id)?;
+                    obj.set(cx, "playbackId", js_playback_id)?;
+
+                    Ok(obj.upcast())
+                });
+                CALLBACKS.lock().unwrap().remove(&id);
+            }
+            MainOptions::LoopbackFailed { id, error } => {
+                if enable_debug {
+                    println!("Loopback failed: id: {}, error: {}", id, error);
+                }
+
+                emit_event(id, "error", move |cx| Ok(cx.string(error).upcast()));
+                CALLBACKS.lock().unwrap().remove(&id);
+            }
         }
     });
 
@@ -612,21 +2275,57 @@ fn get_nodes(mut cx: FunctionContext) -> JsResult<JsArray> {
     Ok(output)
 }
 
-fn get_clients(mut cx: FunctionContext) -> JsResult<JsArray> {
-    let output = JsArray::new(&mut cx, 0);
+// Arguments: the callback called with the stats when they are not ready yet.
+fn get_node_stats(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let callback = cx.argument::<JsFunction>(0)?;
 
-    let all_data = ALL_DATA.lock().unwrap();
+    // Profiling starts on the first call and is stopped once the stats are no longer requested.
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
 
-    let mut counter = 0;
-    // From all_data, get all clients and add them to the output array
-    for (_, data) in all_data.iter() {
-        match data {
-            PipewireData::Client(client) => {
-                let js_client = client.to_object(&mut cx);
+    let _ = temp_pw_sender.send(PipewireOptions::RequestNodeStats);
 
-                // if js_client result is Ok, add it to the output array
-                if let Ok(js_client) = js_client {
-                    output.set(&mut cx, counter, js_client).unwrap();
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    let mut waiters = NODE_STATS.lock().unwrap();
+    if waiters.ready {
+        let stats = node_stats(&ALL_DATA.lock().unwrap());
+        drop(waiters);
+        return Ok(node_stats_to_array(&mut cx, &stats)?.upcast());
+    }
+
+    // the callback is called once the first interval of the profiler ended
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+    waiters.callbacks.push(id);
+
+    Ok(cx.undefined().upcast())
+}
+
+fn get_clients(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let output = JsArray::new(&mut cx, 0);
+
+    let all_data = ALL_DATA.lock().unwrap();
+
+    let mut counter = 0;
+    // From all_data, get all clients and add them to the output array
+    for (_, data) in all_data.iter() {
+        match data {
+            PipewireData::Client(client) => {
+                let js_client = client.to_object(&mut cx);
+
+                // if js_client result is Ok, add it to the output array
+                if let Ok(js_client) = js_client {
+                    output.set(&mut cx, counter, js_client).unwrap();
                     counter += 1;
                 }
             }
@@ -636,17 +2335,72 @@ fn get_clients(mut cx: FunctionContext) -> JsResult<JsArray> {
     Ok(output)
 }
 
+fn query_graph(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let selector = cx.argument::<JsString>(0)?;
+
+    let selector = selector.value(&mut cx);
+
+    let all_data = ALL_DATA.lock().unwrap();
+
+    let ids = match query::query(&all_data, &selector) {
+        Ok(ids) => ids,
+        Err(error) => return cx.throw_error(format!("Invalid selector: {}", error)),
+    };
+
+    tagged_objects(&mut cx, &all_data, &ids)
+}
+
+fn get_objects_of_client(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let client_id = cx.argument::<JsNumber>(0)?;
+
+    let client_id = client_id.value(&mut cx) as u32;
+
+    let all_data = ALL_DATA.lock().unwrap();
+
+    let ids = query::objects_of_client(&all_data, client_id);
+
+    tagged_objects(&mut cx, &all_data, &ids)
+}
+
+fn get_owned_objects(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let all_data = ALL_DATA.lock().unwrap();
+
+    let ids: Vec<u32> = permissions::owned_objects(&all_data, &OWNED_PROXIES.lock().unwrap())
+        .into_iter()
+        .collect();
+
+    tagged_objects(&mut cx, &all_data, &ids)
+}
+
+// Objects of several types, each tagged with its type.
+fn tagged_objects<'a>(
+    cx: &mut Cx<'a>,
+    all_data: &HashMap<u32, PipewireData>,
+    ids: &[u32],
+) -> JsResult<'a, JsArray> {
+    let output = JsArray::new(cx, 0);
+
+    for (counter, id) in ids.iter().enumerate() {
+        let data = &all_data[id];
+        let js_object = data.to_object(cx)?;
+        let js_type = cx.string(query::type_name(data));
+        js_object.set(cx, "type", js_type)?;
+        output.set(cx, counter as u32, js_object)?;
+    }
+    Ok(output)
+}
+
 fn get_output_nodes(mut cx: FunctionContext) -> JsResult<JsArray> {
     let output = JsArray::new(&mut cx, 0);
 
     let all_data = ALL_DATA.lock().unwrap();
 
     let mut counter = 0;
-    // From all_data, get all output nodes and add them to the output array
+    // From all_data, get all output nodes (duplex nodes included) and add them to the output array
     for (_, data) in all_data.iter() {
         match data {
             PipewireData::Node(node) => {
-                if node.node_direction == "Output" {
+                if node.node_direction == "Output" || node.node_direction == "Duplex" {
                     let js_node = node.to_object(&mut cx);
 
                     // if js_node result is Ok, add it to the output array
@@ -668,11 +2422,11 @@ fn get_input_nodes(mut cx: FunctionContext) -> JsResult<JsArray> {
     let all_data = ALL_DATA.lock().unwrap();
 
     let mut counter = 0;
-    // From all_data, get all input nodes and add them to the output array
+    // From all_data, get all input nodes (duplex nodes included) and add them to the output array
     for (_, data) in all_data.iter() {
         match data {
             PipewireData::Node(node) => {
-                if node.node_direction == "Input" {
+                if node.node_direction == "Input" || node.node_direction == "Duplex" {
                     let js_node = node.to_object(&mut cx);
 
                     // if js_node result is Ok, add it to the output array
@@ -688,14 +2442,1690 @@ fn get_input_nodes(mut cx: FunctionContext) -> JsResult<JsArray> {
     Ok(output)
 }
 
-fn link_nodes_name_to_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let input_nodes_name = cx.argument::<JsString>(0)?;
-    let output_node_id = cx.argument::<JsNumber>(1)?;
-    let permanent = cx.argument::<JsBoolean>(2)?;
+fn get_path_latency(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let from_node_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as u32;
+    let to_node_id = cx.argument::<JsNumber>(1)?.value(&mut cx) as u32;
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let path = latency::path_latency(&all_data, from_node_id, to_node_id);
+    drop(all_data);
+
+    // null if there is no path between the nodes
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(cx.null().upcast()),
+    };
+
+    let obj = cx.empty_object();
+
+    let js_nodes = cx.empty_array();
+    for (i, id) in path.nodes.iter().enumerate() {
+        let js_id = cx.number(*id);
+        js_nodes.set(&mut cx, i as u32, js_id)?;
+    }
+    let js_links = cx.empty_array();
+    for (i, id) in path.links.iter().enumerate() {
+        let js_id = cx.number(*id);
+        js_links.set(&mut cx, i as u32, js_id)?;
+    }
+    let js_min_ns = cx.number(path.min_ns);
+    let js_max_ns = cx.number(path.max_ns);
+
+    obj.set(&mut cx, "nodes", js_nodes)?;
+    obj.set(&mut cx, "links", js_links)?;
+    obj.set(&mut cx, "min_ns", js_min_ns)?;
+    obj.set(&mut cx, "max_ns", js_max_ns)?;
+
+    Ok(obj.upcast())
+}
+
+fn link_nodes_name_to_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let input_nodes_name = cx.argument::<JsString>(0)?;
+    let output_node_id = cx.argument::<JsNumber>(1)?;
+    let permanent = cx.argument::<JsBoolean>(2)?;
+
+    let input_nodes_name = input_nodes_name.value(&mut cx);
+    let output_node_id = output_node_id.value(&mut cx) as u32;
+    let permanent = permanent.value(&mut cx);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    // send the message to the pw thread
+    let result = temp_pw_sender.send(PipewireOptions::LinkNodesNameToId {
+        output_nodes_name: input_nodes_name,
+        input_node_id: output_node_id,
+        permanent: permanent,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    if let Err(_) = result {
+        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
+            println!("Error sending message to pw thread");
+        }
+    }
+
+    Ok(cx.undefined())
+}
+
+fn unlink_nodes_name_to_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let output_nodes_name = cx.argument::<JsString>(0)?;
+    let input_node_id = cx.argument::<JsNumber>(1)?;
+
+    let output_nodes_name = output_nodes_name.value(&mut cx);
+    let input_node_id = input_node_id.value(&mut cx) as u32;
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    // send the message to the pw thread
+    let result = temp_pw_sender.send(PipewireOptions::UnLinkNodesNameToId {
+        output_nodes_name,
+        input_node_id,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    if let Err(_) = result {
+        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
+            println!("Error sending message to pw thread");
+        }
+    }
+
+    Ok(cx.undefined())
+}
+
+fn link_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let input_port_id = cx.argument::<JsNumber>(0)?;
+    let output_port_id = cx.argument::<JsNumber>(1)?;
+    let permanent = cx.argument::<JsBoolean>(2)?;
+
+    let input_port_id = input_port_id.value(&mut cx) as u32;
+    let output_port_id = output_port_id.value(&mut cx) as u32;
+    let permanent = permanent.value(&mut cx);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    // send the message to the pw thread
+    let result = temp_pw_sender.send(PipewireOptions::LinkPorts {
+        input_port: input_port_id,
+        output_port: output_port_id,
+        permanent: permanent,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    if let Err(_) = result {
+        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
+            println!("Error sending message to pw thread");
+        }
+    }
+
+    Ok(cx.undefined())
+}
+
+fn unlink_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let input_port_id = cx.argument::<JsNumber>(0)?;
+    let output_port_id = cx.argument::<JsNumber>(1)?;
+
+    let input_port_id = input_port_id.value(&mut cx) as u32;
+    let output_port_id = output_port_id.value(&mut cx) as u32;
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    // send the message to the pw thread
+    let result = temp_pw_sender.send(PipewireOptions::UnLinkPorts {
+        input_port: input_port_id,
+        output_port: output_port_id,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    if let Err(_) = result {
+        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
+            println!("Error sending message to pw thread");
+        }
+    }
+
+    Ok(cx.undefined())
+}
+
+fn get_current_nodes(
+    node_name: String,
+    node_directions: Vec<String>,
+) -> Result<Vec<PipewireNode>, String> {
+    let all_data = ALL_DATA.lock().unwrap();
+    let mut new_nodes = Vec::new();
+    for data in all_data.iter() {
+        if let PipewireData::Node(node) = data.1 {
+            if node.name.contains(node_name.as_str())
+                && node_directions.contains(&node.node_direction)
+            {
+                new_nodes.push(node.clone());
+            }
+        }
+    }
+    drop(all_data);
+
+    Ok(new_nodes)
+}
+
+fn wait_for_new_node(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let rt = runtime(&mut cx)?;
+    let node_name = cx.argument::<JsString>(0)?;
+    let node_direction = cx.argument::<JsString>(1)?;
+    let timeout = cx.argument::<JsNumber>(2)?;
+    let channel = cx.channel();
+    let (deferred, promise) = cx.promise();
+
+    let node_name = node_name.value(&mut cx);
+    let node_direction = node_direction.value(&mut cx);
+    let timeout = timeout.value(&mut cx) as u128;
+
+    // Duplex nodes are both input and output nodes.
+    let mut node_directions = vec!["Duplex".to_string()];
+    if node_direction == "Input" || node_direction == "Output" {
+        node_directions.push(node_direction);
+    } else {
+        node_directions.push("Input".to_string());
+        node_directions.push("Output".to_string());
+    }
+
+    // get the actual nodes from the context data
+    let nodes = get_current_nodes(node_name.clone(), node_directions.clone());
+
+    match nodes {
+        Ok(nodes) => {
+            // get the actual time
+            let start_time = Instant::now();
+            // start async task
+            rt.spawn(async move {
+                let mut _node = None;
+
+                loop {
+                    // get the actual nodes from the context data
+                    let new_nodes =
+                        get_current_nodes(node_name.clone(), node_directions.clone()).unwrap();
+
+                    // check if the length of the nodes is different
+                    if new_nodes.len() > 0 {
+                        ENABLE_DEBUG.with(|debug| {
+                            if *debug.borrow() {
+                                println!("Old nodes:");
+                                for node in nodes.iter() {
+                                    println!("{} - {}", node.name, node.id);
+                                }
+                                println!("");
+                                println!("New nodes:");
+                                for node in new_nodes.iter() {
+                                    println!("{} - {}", node.name, node.id);
+                                }
+                            }
+                        });
+
+                        // get the new node compared to the old nodes
+                        for new_node in new_nodes.iter() {
+                            let mut found = false;
+                            for node in nodes.iter() {
+                                if new_node.id == node.id {
+                                    found = true;
+                                    break;
+                                }
+                            }
+                            if !found {
+                                _node = Some(new_node.clone());
+                                break;
+                            }
+                        }
+
+                        if _node.is_some() {
+                            break;
+                        }
+                    }
+
+                    // check if the timeout is reached (default 5 seconds)
+                    if start_time.elapsed().as_millis() > timeout {
+                        break;
+                    }
+
+                    // wait 500ms (Maybe this can be changed to a lower value)
+                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                }
+
+                // defer the result
+                deferred.settle_with(&channel, move |mut cx_task| match _node {
+                    Some(node) => {
+                        let obj = node.to_object(&mut cx_task)?;
+                        Ok(obj)
+                    }
+                    None => cx_task.throw_error("No node found"),
+                });
+            });
+        }
+        Err(_) => {
+            return cx.throw_error("Error getting the nodes");
+        }
+    };
+
+    Ok(promise)
+}
+
+fn create_source(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    create_device(&mut cx, device::DeviceKind::Source)?;
+
+    Ok(cx.undefined())
+}
+
+fn create_sink(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    create_device(&mut cx, device::DeviceKind::Sink)?;
+
+    Ok(cx.undefined())
+}
+
+// Arguments: name, audio positions, permanent and the options of the device (see device::DeviceOptions).
+fn create_device(cx: &mut FunctionContext, kind: device::DeviceKind) -> NeonResult<String> {
+    let name = cx.argument::<JsString>(0)?;
+    let positions = cx.argument::<JsArray>(1)?;
+    let permanent = cx.argument::<JsBoolean>(2)?;
+
+    let name = name.value(cx);
+    let positions = positions
+        .to_vec(cx)?
+        .into_iter()
+        .map(|position| Ok(position.downcast_or_throw::<JsString, _>(cx)?.value(cx)))
+        .collect::<NeonResult<Vec<String>>>()?;
+
+    let mut options = device::DeviceOptions::new(kind, name, positions);
+    options.permanent = permanent.value(cx);
+    if let Some(js_options) = cx.argument_opt(3) {
+        if !js_options.is_a::<JsUndefined, _>(cx) {
+            let js_options = js_options.downcast_or_throw::<JsObject, _>(cx)?;
+            read_device_options(cx, js_options, &mut options)?;
+        }
+    }
+
+    send_device(cx, options)
+}
+
+// Arguments: the options of the device, with its kind, name, audio positions (FL and FR by default),
+// whether it's permanent and whether a sink has monitor ports. Returns the node.name of the device.
+fn create_virtual_device(mut cx: FunctionContext) -> JsResult<JsString> {
+    let js_options = cx.argument::<JsObject>(0)?;
+
+    let kind = js_options
+        .get::<JsString, _, _>(&mut cx, "kind")?
+        .value(&mut cx);
+    let kind = match device::DeviceKind::parse(&kind) {
+        Some(kind) => kind,
+        None => {
+            return cx.throw_type_error(format!(
+                "The kind of the device must be sink, source or duplex, got {:?}",
+                kind
+            ))
+        }
+    };
+    let name = js_options
+        .get::<JsString, _, _>(&mut cx, "name")?
+        .value(&mut cx);
+    let positions = match js_options.get_opt::<JsArray, _, _>(&mut cx, "audioPositions")? {
+        Some(_) => string_list_option(&mut cx, js_options, "audioPositions")?,
+        None => vec![String::from("FL"), String::from("FR")],
+    };
+
+    let mut options = device::DeviceOptions::new(kind, name, positions);
+    if let Some(permanent) = js_options.get_opt::<JsBoolean, _, _>(&mut cx, "permanent")? {
+        options.permanent = permanent.value(&mut cx);
+    }
+    options.monitor = js_options
+        .get_opt::<JsBoolean, _, _>(&mut cx, "monitor")?
+        .map(|monitor| monitor.value(&mut cx));
+    read_device_options(&mut cx, js_options, &mut options)?;
+
+    let node_name = send_device(&mut cx, options)?;
+
+    Ok(cx.string(node_name))
+}
+
+// Check the options and create the device, returns its node.name.
+fn send_device(cx: &mut FunctionContext, options: device::DeviceOptions) -> NeonResult<String> {
+    let props = match options.props() {
+        Ok(props) => props,
+        Err(error) => return cx.throw_error(error),
+    };
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::CreateDevice {
+        props,
+        permanent: options.permanent,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(options.node_name())
+}
+
+fn read_device_options(
+    cx: &mut FunctionContext,
+    js_options: Handle<JsObject>,
+    options: &mut device::DeviceOptions,
+) -> NeonResult<()> {
+    let string_option = |cx: &mut FunctionContext, key: &str| -> NeonResult<Option<String>> {
+        Ok(js_options
+            .get_opt::<JsString, _, _>(cx, key)?
+            .map(|value| value.value(cx)))
+    };
+    let boolean_option = |cx: &mut FunctionContext, key: &str| -> NeonResult<Option<bool>> {
+        Ok(js_options
+            .get_opt::<JsBoolean, _, _>(cx, key)?
+            .map(|value| value.value(cx)))
+    };
+
+    options.node_name = string_option(cx, "nodeName")?;
+    if let Some(prefix) = boolean_option(cx, "prefix")? {
+        options.prefix = prefix;
+    }
+    options.description = string_option(cx, "description")?;
+    options.rate = integer_option(cx, js_options, "rate", 1)?;
+    options.format = string_option(cx, "format")?;
+    options.channel_volumes = boolean_option(cx, "channelVolumes")?;
+    options.latency = string_option(cx, "latency")?;
+    options.icon_name = string_option(cx, "iconName")?;
+
+    if let Some(priority) = js_options.get_opt::<JsNumber, _, _>(cx, "priority")? {
+        let priority = priority.value(cx);
+        if priority.fract() != 0.0 || priority < i32::MIN as f64 || priority > i32::MAX as f64 {
+            return cx.throw_range_error("priority must be an integer");
+        }
+        options.priority = Some(priority as i32);
+    }
+
+    options.extra_props = props_option(cx, js_options, "props")?;
+
+    Ok(())
+}
+
+// Read an object of properties, their values are strings, numbers or booleans.
+fn props_option(
+    cx: &mut FunctionContext,
+    options: Handle<JsObject>,
+    key: &str,
+) -> NeonResult<Vec<(String, String)>> {
+    let mut props = Vec::new();
+
+    let Some(js_props) = options.get_opt::<JsObject, _, _>(cx, key)? else {
+        return Ok(props);
+    };
+    let keys = js_props.get_own_property_names(cx)?.to_vec(cx)?;
+    for key in keys {
+        let key = key.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+        let value = js_props.get_value(cx, key.as_str())?;
+
+        let value = if let Ok(value) = value.downcast::<JsString, _>(cx) {
+            value.value(cx)
+        } else if let Ok(value) = value.downcast::<JsNumber, _>(cx) {
+            value.value(cx).to_string()
+        } else if let Ok(value) = value.downcast::<JsBoolean, _>(cx) {
+            value.value(cx).to_string()
+        } else {
+            return cx.throw_type_error(format!(
+                "The property {:?} must be a string, a number or a boolean",
+                key
+            ));
+        };
+        props.push((key, value));
+    }
+
+    Ok(props)
+}
+
+fn destroy_object(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let destroy_target_id = cx.argument::<JsNumber>(0)?;
+
+    let destroy_target_id = destroy_target_id.value(&mut cx) as u32;
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::DeleteObject {
+        id: destroy_target_id,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+fn allow_client_disconnect(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let allow = cx.argument::<JsBoolean>(0)?;
+
+    let allow = allow.value(&mut cx);
+
+    CLIENT_DISCONNECT_ALLOWED.store(allow, Ordering::Relaxed);
+
+    Ok(cx.undefined())
+}
+
+fn disconnect_client(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let client_id = cx.argument::<JsNumber>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+
+    let client_id = client_id.value(&mut cx) as u32;
+
+    if !CLIENT_DISCONNECT_ALLOWED.load(Ordering::Relaxed) {
+        return cx.throw_error("Disconnecting clients is not allowed");
+    }
+
+    if !matches!(
+        ALL_DATA.lock().unwrap().get(&client_id),
+        Some(PipewireData::Client(_))
+    ) {
+        return cx.throw_error("No client found with that id");
+    }
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::DisconnectClient { id, client_id });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+// Destroy the owned objects and wait (at most timeoutMs) for the server, to be called when the process exits.
+// Returns whether the server answered in time.
+fn cleanup_owned_objects(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let include_lingering = cx.argument::<JsBoolean>(0)?;
+    let timeout_ms = cx.argument::<JsNumber>(1)?;
+
+    let include_lingering = include_lingering.value(&mut cx);
+    let timeout_ms = timeout_ms.value(&mut cx);
+
+    if !timeout_ms.is_finite() || timeout_ms < 0.0 {
+        return cx.throw_range_error("The timeout must be a positive number of milliseconds");
+    }
+
+    let (done, done_receiver) = mpsc::channel();
+
+    // the pipewire thread may not have been created, or may be closed already
+    let sent = PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow().as_ref().is_some_and(|pw_sender| {
+            pw_sender
+                .send(PipewireOptions::CleanupOwnedObjects {
+                    include_lingering,
+                    done,
+                })
+                .is_ok()
+        })
+    });
+
+    let cleaned_up = sent
+        && done_receiver
+            .recv_timeout(Duration::from_secs_f64(timeout_ms / 1000.0))
+            .is_ok();
+
+    Ok(cx.boolean(cleaned_up))
+}
+
+fn take_object_handle(mut cx: FunctionContext) -> JsResult<JsBox<ObjectHandle>> {
+    let id = cx.argument::<JsNumber>(0)?;
+
+    let id = id.value(&mut cx) as u32;
+
+    if !OWNED_PROXIES.lock().unwrap().contains(&id) {
+        return cx.throw_error("The object was not created by this connection");
+    }
+
+    Ok(cx.boxed(ObjectHandle {
+        id,
+        released: Cell::new(false),
+    }))
+}
+
+fn release_object_handle(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let handle = cx.argument::<JsBox<ObjectHandle>>(0)?;
+
+    handle.release();
+
+    Ok(cx.undefined())
+}
+
+fn can_destroy(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let id = cx.argument::<JsNumber>(0)?;
+
+    let id = id.value(&mut cx) as u32;
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let owned = permissions::owned_objects(&all_data, &OWNED_PROXIES.lock().unwrap());
+    let allowed = all_data
+        .get(&id)
+        .is_some_and(|target| permissions::can_destroy(target, &owned));
+    drop(all_data);
+
+    Ok(cx.boolean(allowed))
+}
+
+fn can_link(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let port_id = cx.argument::<JsNumber>(0)?;
+
+    let port_id = port_id.value(&mut cx) as u32;
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let allowed = permissions::can_link(&all_data, port_id);
+    drop(all_data);
+
+    Ok(cx.boolean(allowed))
+}
+
+fn set_latency_offset(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let target_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as u32;
+    let quantum = cx.argument::<JsNumber>(1)?.value(&mut cx);
+    let samples = cx.argument::<JsNumber>(2)?.value(&mut cx);
+    let ns = cx.argument::<JsNumber>(3)?.value(&mut cx);
+
+    if [quantum, samples, ns]
+        .iter()
+        .any(|value| !value.is_finite() || *value < 0.0)
+    {
+        return cx.throw_range_error("Latency offsets must be positive numbers");
+    }
+
+    // Ports can't be written by clients, the offset of a port is set on its node (returned to the caller).
+    let all_data = ALL_DATA.lock().unwrap();
+    let node_id = match all_data.get(&target_id) {
+        Some(PipewireData::Node(node)) => Some(node.id),
+        Some(PipewireData::Port(port)) => Some(port.node_id),
+        _ => None,
+    };
+    let permissions = match node_id.and_then(|id| all_data.get(&id)) {
+        Some(PipewireData::Node(node)) => Some(node.permissions),
+        _ => None,
+    };
+    drop(all_data);
+
+    let (node_id, permissions) = match (node_id, permissions) {
+        (Some(node_id), Some(permissions)) => (node_id, permissions),
+        _ => return cx.throw_error("No node or port found with that id"),
+    };
+
+    // Setting a param on a node requires write and execute permissions.
+    if !permissions.contains(PermissionFlags::W | PermissionFlags::X) {
+        return cx.throw_error("No permission to write the params of the node");
+    }
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::SetProcessLatency {
+        node_id,
+        latency: PipewireProcessLatency {
+            quantum: quantum as f32,
+            rate: samples as i32,
+            ns: ns as i64,
+        },
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.number(node_id))
+}
+
+fn permission_to_object<'a>(
+    cx: &mut Cx<'a>,
+    id: u32,
+    flags: PermissionFlags,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let js_id = cx.number(id);
+    let js_r = cx.boolean(flags.contains(PermissionFlags::R));
+    let js_w = cx.boolean(flags.contains(PermissionFlags::W));
+    let js_x = cx.boolean(flags.contains(PermissionFlags::X));
+    let js_m = cx.boolean(flags.contains(PermissionFlags::M));
+    let js_l = cx.boolean(flags.contains(permissions::LINK));
+
+    obj.set(cx, "id", js_id)?;
+    obj.set(cx, "r", js_r)?;
+    obj.set(cx, "w", js_w)?;
+    obj.set(cx, "x", js_x)?;
+    obj.set(cx, "m", js_m)?;
+    obj.set(cx, "l", js_l)?;
+
+    Ok(obj)
+}
+
+// Check that the permissions of a client can be managed.
+fn manageable_client(cx: &mut FunctionContext, client_id: u32) -> NeonResult<()> {
+    let all_data = ALL_DATA.lock().unwrap();
+    let permissions = match all_data.get(&client_id) {
+        Some(PipewireData::Client(client)) => client.permissions,
+        _ => {
+            drop(all_data);
+            return cx.throw_error("No client found with that id");
+        }
+    };
+    drop(all_data);
+
+    // Getting and updating the permissions of a client requires write and execute permissions on it.
+    if !permissions.contains(PermissionFlags::W | PermissionFlags::X) {
+        return cx.throw_error("No permission to manage the permissions of the client");
+    }
+
+    Ok(())
+}
+
+fn get_client_permissions(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let client_id = cx.argument::<JsNumber>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+
+    let client_id = client_id.value(&mut cx) as u32;
+
+    manageable_client(&mut cx, client_id)?;
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // The request is answered when the permissions of the client are received.
+    PERMISSION_REQUESTS.lock().unwrap().insert(id, client_id);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::GetClientPermissions { id, client_id });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+fn update_client_permissions(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let client_id = cx.argument::<JsNumber>(0)?;
+    let js_permissions = cx.argument::<JsArray>(1)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+
+    let client_id = client_id.value(&mut cx) as u32;
+
+    let mut permissions = Vec::new();
+    for js_permission in js_permissions.to_vec(&mut cx)? {
+        let js_permission = js_permission.downcast_or_throw::<JsObject, _>(&mut cx)?;
+
+        let id = js_permission
+            .get::<JsNumber, _, _>(&mut cx, "id")?
+            .value(&mut cx);
+        if id.fract() != 0.0 || id < 0.0 || id > u32::MAX as f64 {
+            return cx.throw_range_error("Permission ids must be global ids");
+        }
+
+        // missing flags are not granted
+        let mut flags = PermissionFlags::empty();
+        for (key, flag) in [
+            ("r", PermissionFlags::R),
+            ("w", PermissionFlags::W),
+            ("x", PermissionFlags::X),
+            ("m", PermissionFlags::M),
+            ("l", permissions::LINK),
+        ] {
+            let granted = js_permission
+                .get_opt::<JsBoolean, _, _>(&mut cx, key)?
+                .is_some_and(|granted| granted.value(&mut cx));
+            if granted {
+                flags |= flag;
+            }
+        }
+
+        permissions.push((id as u32, flags));
+    }
+
+    manageable_client(&mut cx, client_id)?;
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // The update is answered once the server processed it, or failed.
+    PERMISSION_UPDATES.lock().unwrap().insert(id, client_id);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::UpdateClientPermissions {
+        id,
+        client_id,
+        permissions,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+// Get a copy of the metadata object with the name `name`.
+fn find_metadata(name: &str) -> Option<PipewireMetadata> {
+    let all_data = ALL_DATA.lock().unwrap();
+
+    all_data.values().find_map(|data| match data {
+        PipewireData::Metadata(metadata) if metadata.name == name => Some(metadata.clone()),
+        _ => None,
+    })
+}
+
+// Read an optional string argument, null and undefined are None.
+fn string_argument_opt(cx: &mut FunctionContext, i: usize) -> NeonResult<Option<String>> {
+    match cx.argument_opt(i) {
+        Some(value) if !value.is_a::<JsNull, _>(cx) && !value.is_a::<JsUndefined, _>(cx) => {
+            Ok(Some(value.downcast_or_throw::<JsString, _>(cx)?.value(cx)))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Check a subject of metadata, a global id.
+fn metadata_subject(cx: &mut FunctionContext, subject: f64) -> NeonResult<u32> {
+    if subject.fract() != 0.0 || subject < 0.0 || subject > u32::MAX as f64 {
+        return cx.throw_range_error("The subject must be a global id");
+    }
+    Ok(subject as u32)
+}
+
+// Find the metadata `name` and check that the properties of `subject` can be written.
+// Setting a property requires write and execute permissions on the metadata and
+// the metadata permission on the subject (checked only if the subject is known).
+fn writable_metadata(
+    cx: &mut FunctionContext,
+    name: &str,
+    subject: Option<u32>,
+) -> NeonResult<u32> {
+    let metadata = match find_metadata(name) {
+        Some(metadata) => metadata,
+        None => return cx.throw_error(format!("No metadata named {:?} found", name)),
+    };
+
+    if !metadata
+        .permissions
+        .contains(PermissionFlags::W | PermissionFlags::X)
+    {
+        return cx.throw_error(format!("No permission to write the metadata {:?}", name));
+    }
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let subject_permissions = subject
+        .and_then(|subject| all_data.get(&subject))
+        .map(permissions::permissions_of);
+    drop(all_data);
+
+    if let Some(permissions) = subject_permissions {
+        if !permissions.contains(PermissionFlags::M) {
+            return cx.throw_error("No permission to set metadata on the subject");
+        }
+    }
+
+    Ok(metadata.id)
+}
+
+fn get_metadata(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let name = cx.argument::<JsString>(0)?;
+
+    let name = name.value(&mut cx);
+
+    // null if there is no metadata with that name
+    match find_metadata(&name) {
+        Some(metadata) => Ok(metadata.to_object(&mut cx)?.upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+fn set_metadata(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let name = cx.argument::<JsString>(0)?;
+    let subject = cx.argument::<JsNumber>(1)?;
+    let key = cx.argument::<JsString>(2)?;
+
+    let name = name.value(&mut cx);
+    let subject = subject.value(&mut cx);
+    let subject = metadata_subject(&mut cx, subject)?;
+    let key = key.value(&mut cx);
+    // Without value the key is removed.
+    let value = string_argument_opt(&mut cx, 3)?;
+    let type_ = string_argument_opt(&mut cx, 4)?;
+
+    if key.is_empty() || key.contains('\0') {
+        return cx.throw_error("Invalid metadata key");
+    }
+    let has_null = |text: &Option<String>| text.as_ref().is_some_and(|text| text.contains('\0'));
+    if has_null(&value) || has_null(&type_) {
+        return cx.throw_error("Metadata values and types can't contain null characters");
+    }
+
+    let metadata_id = writable_metadata(&mut cx, &name, Some(subject))?;
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::SetMetadataProperty {
+        id: metadata_id,
+        subject,
+        key,
+        type_,
+        value,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+fn clear_metadata(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let name = cx.argument::<JsString>(0)?;
+    let target = cx.argument::<JsValue>(1)?;
+
+    let name = name.value(&mut cx);
+    // Clearing all the metadata (every default device of the `default` metadata, ...) must be explicit.
+    let subject = if let Ok(subject) = target.downcast::<JsNumber, _>(&mut cx) {
+        let subject = subject.value(&mut cx);
+        Some(metadata_subject(&mut cx, subject)?)
+    } else {
+        let all = match target.downcast::<JsObject, _>(&mut cx) {
+            Ok(target) => target
+                .get_opt::<JsBoolean, _, _>(&mut cx, "all")?
+                .is_some_and(|all| all.value(&mut cx)),
+            Err(_) => false,
+        };
+        if !all {
+            return cx
+                .throw_type_error("Pass a subject, or { all: true } to clear all the metadata");
+        }
+        None
+    };
+
+    let metadata_id = writable_metadata(&mut cx, &name, subject)?;
+
+    // Keys of the subject to remove, all the metadata is cleared without subject.
+    let keys: Vec<String> = match subject {
+        Some(subject) => find_metadata(&name)
+            .map(|metadata| metadata.properties)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|property| property.subject == subject)
+            .map(|property| property.key)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    match subject {
+        Some(subject) => {
+            for key in keys {
+                let _ = temp_pw_sender.send(PipewireOptions::SetMetadataProperty {
+                    id: metadata_id,
+                    subject,
+                    key,
+                    type_: None,
+                    value: None,
+                });
+            }
+        }
+        None => {
+            let _ = temp_pw_sender.send(PipewireOptions::ClearMetadata { id: metadata_id });
+        }
+    }
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+// Read an optional integer option, it must be at least `min`.
+fn integer_option(
+    cx: &mut FunctionContext,
+    options: Handle<JsObject>,
+    key: &str,
+    min: u32,
+) -> NeonResult<Option<u32>> {
+    let value = match options.get_opt::<JsNumber, _, _>(cx, key)? {
+        Some(value) => value.value(cx),
+        None => return Ok(None),
+    };
+
+    if value.fract() != 0.0 || value < min as f64 || value > u32::MAX as f64 {
+        return cx.throw_range_error(format!("{} must be an integer of at least {}", key, min));
+    }
+
+    Ok(Some(value as u32))
+}
+
+fn get_settings(mut cx: FunctionContext) -> JsResult<JsValue> {
+    // null until the settings metadata is known
+    let settings = match find_metadata(metadata::SETTINGS) {
+        Some(settings) => metadata::clock_settings(&settings.properties),
+        None => return Ok(cx.null().upcast()),
+    };
+
+    let obj = cx.empty_object();
+
+    let js_rate = optional_number(&mut cx, settings.rate)?;
+    let js_allowed_rates = cx.empty_array();
+    for (i, rate) in settings.allowed_rates.iter().enumerate() {
+        let js_rate = cx.number(*rate);
+        js_allowed_rates.set(&mut cx, i as u32, js_rate)?;
+    }
+    let js_quantum = optional_number(&mut cx, settings.quantum)?;
+    let js_min_quantum = optional_number(&mut cx, settings.min_quantum)?;
+    let js_max_quantum = optional_number(&mut cx, settings.max_quantum)?;
+    let js_force_rate = optional_number(&mut cx, settings.force_rate)?;
+    let js_force_quantum = optional_number(&mut cx, settings.force_quantum)?;
+
+    obj.set(&mut cx, "rate", js_rate)?;
+    obj.set(&mut cx, "allowedRates", js_allowed_rates)?;
+    obj.set(&mut cx, "quantum", js_quantum)?;
+    obj.set(&mut cx, "minQuantum", js_min_quantum)?;
+    obj.set(&mut cx, "maxQuantum", js_max_quantum)?;
+    obj.set(&mut cx, "forceRate", js_force_rate)?;
+    obj.set(&mut cx, "forceQuantum", js_force_quantum)?;
+
+    Ok(obj.upcast())
+}
+
+fn set_settings(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let settings = cx.argument::<JsObject>(0)?;
+
+    // A forced rate or quantum of 0 stops forcing it.
+    let force_rate = integer_option(&mut cx, settings, "forceRate", 0)?;
+    let force_quantum = integer_option(&mut cx, settings, "forceQuantum", 0)?;
+    let min_quantum = integer_option(&mut cx, settings, "minQuantum", 1)?;
+    let max_quantum = integer_option(&mut cx, settings, "maxQuantum", 1)?;
+
+    if let (Some(min_quantum), Some(max_quantum)) = (min_quantum, max_quantum) {
+        if min_quantum > max_quantum {
+            return cx.throw_range_error("minQuantum must not be greater than maxQuantum");
+        }
+    }
+
+    let allowed_rates = match settings.get_opt::<JsArray, _, _>(&mut cx, "allowedRates")? {
+        Some(js_rates) => {
+            let mut rates = Vec::new();
+            for js_rate in js_rates.to_vec(&mut cx)? {
+                let rate = js_rate
+                    .downcast_or_throw::<JsNumber, _>(&mut cx)?
+                    .value(&mut cx);
+                if rate.fract() != 0.0 || rate < 1.0 || rate > u32::MAX as f64 {
+                    return cx
+                        .throw_range_error("allowedRates must only contain positive integers");
+                }
+                rates.push(rate as u32);
+            }
+            if rates.is_empty() {
+                return cx.throw_range_error("allowedRates must not be empty");
+            }
+            Some(rates)
+        }
+        None => None,
+    };
+
+    let metadata_id = match find_metadata(metadata::SETTINGS) {
+        Some(settings)
+            if settings
+                .permissions
+                .contains(PermissionFlags::W | PermissionFlags::X) =>
+        {
+            settings.id
+        }
+        Some(_) => return cx.throw_error("No permission to write the settings metadata"),
+        None => return cx.throw_error("No settings metadata found"),
+    };
+
+    let mut properties = Vec::new();
+    if let Some(force_rate) = force_rate {
+        properties.push((metadata::CLOCK_FORCE_RATE, force_rate.to_string()));
+    }
+    if let Some(force_quantum) = force_quantum {
+        properties.push((metadata::CLOCK_FORCE_QUANTUM, force_quantum.to_string()));
+    }
+    if let Some(min_quantum) = min_quantum {
+        properties.push((metadata::CLOCK_MIN_QUANTUM, min_quantum.to_string()));
+    }
+    if let Some(max_quantum) = max_quantum {
+        properties.push((metadata::CLOCK_MAX_QUANTUM, max_quantum.to_string()));
+    }
+    if let Some(allowed_rates) = allowed_rates {
+        properties.push((
+            metadata::CLOCK_ALLOWED_RATES,
+            metadata::format_rates(&allowed_rates),
+        ));
+    }
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    for (key, value) in properties {
+        let _ = temp_pw_sender.send(PipewireOptions::SetMetadataProperty {
+            id: metadata_id,
+            subject: 0,
+            key: key.to_string(),
+            type_: None,
+            value: Some(value),
+        });
+    }
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+fn move_stream(mut cx: FunctionContext) -> JsResult<JsString> {
+    let stream_id = cx.argument::<JsNumber>(0)?;
+    let target_id = cx.argument::<JsNumber>(1)?;
+
+    let stream_id = stream_id.value(&mut cx) as u32;
+    let target_id = target_id.value(&mut cx) as u32;
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let get_node = |id: u32| match all_data.get(&id) {
+        Some(PipewireData::Node(node)) => Some(node.clone()),
+        _ => None,
+    };
+    let stream = get_node(stream_id);
+    let target = get_node(target_id);
+    drop(all_data);
+
+    let stream = match stream {
+        Some(stream)
+            if stream
+                .props
+                .get("media.class")
+                .is_some_and(|class| class.starts_with("Stream/")) =>
+        {
+            stream
+        }
+        _ => return cx.throw_error("No stream found with that id"),
+    };
+    let target = match target {
+        Some(target) => target,
+        None => return cx.throw_error("No node found with that id"),
+    };
+
+    // Playback streams are linked to the input ports of the target (a sink),
+    // capture streams to its output ports (a source or the monitor of a sink).
+    let target_direction = if stream.kind == "playback" {
+        "Input"
+    } else {
+        "Output"
+    };
+    if !target
+        .ports
+        .iter()
+        .any(|port| port.direction == target_direction)
+    {
+        return cx.throw_error("The stream can't be linked to that node");
+    }
+
+    // With a session manager, the target is set in its metadata so it relinks the stream and remembers it.
+    // Setting the target requires the metadata permission on the stream.
+    let default = find_metadata(metadata::DEFAULT).filter(|default| {
+        default
+            .permissions
+            .contains(PermissionFlags::W | PermissionFlags::X)
+            && stream.permissions.contains(PermissionFlags::M)
+    });
+    let serial = target.props.get("object.serial").cloned();
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let method = match (default, serial) {
+        (Some(default), Some(serial)) => {
+            // The deprecated target.node would take precedence over target.object.
+            if default.properties.iter().any(|property| {
+                property.subject == stream_id && property.key == metadata::TARGET_NODE
+            }) {
+                let _ = temp_pw_sender.send(PipewireOptions::SetMetadataProperty {
+                    id: default.id,
+                    subject: stream_id,
+                    key: metadata::TARGET_NODE.to_string(),
+                    type_: None,
+                    value: None,
+                });
+            }
+
+            let _ = temp_pw_sender.send(PipewireOptions::SetMetadataProperty {
+                id: default.id,
+                subject: stream_id,
+                key: metadata::TARGET_OBJECT.to_string(),
+                type_: Some(String::from("Spa:Id")),
+                value: Some(serial),
+            });
+
+            "metadata"
+        }
+        _ => {
+            let _ = temp_pw_sender.send(PipewireOptions::MoveStreamLinks {
+                stream_id,
+                target_id,
+            });
+
+            "links"
+        }
+    };
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.string(method))
+}
+
+fn subscribe_metadata(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let name = cx.argument::<JsString>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+
+    let name = name.value(&mut cx);
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // The subscription follows the name, so it survives a restart of the owner of the metadata.
+    METADATA_SUBSCRIPTIONS.lock().unwrap().insert(id, name);
+
+    Ok(cx.number(id))
+}
+
+fn unsubscribe_metadata(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
+
+    let id = id.value(&mut cx) as u32;
+
+    METADATA_SUBSCRIPTIONS.lock().unwrap().remove(&id);
+    CALLBACKS.lock().unwrap().remove(&id);
+
+    Ok(cx.undefined())
+}
+
+// Read an optional array of strings from `options`.
+fn string_list_option(
+    cx: &mut FunctionContext,
+    options: Handle<JsObject>,
+    key: &str,
+) -> NeonResult<Vec<String>> {
+    let values = match options.get_opt::<JsArray, _, _>(cx, key)? {
+        Some(values) => values.to_vec(cx)?,
+        None => return Ok(Vec::new()),
+    };
+
+    values
+        .into_iter()
+        .map(|value| Ok(value.downcast_or_throw::<JsString, _>(cx)?.value(cx)))
+        .collect()
+}
+
+fn subscribe_events(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let filter = cx.argument::<JsObject>(0)?;
+    let coalesce_ms = cx.argument::<JsNumber>(1)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+
+    let coalesce_ms = coalesce_ms.value(&mut cx);
+    if !coalesce_ms.is_finite() || coalesce_ms < 0.0 {
+        return cx.throw_range_error("coalesceMs must be a finite number of at least 0");
+    }
+
+    let types = string_list_option(&mut cx, filter, "types")?;
+    if let Some(type_) = types.iter().find(|type_| {
+        !["node", "port", "link", "client", "metadata", "device"].contains(&type_.as_str())
+    }) {
+        return cx.throw_type_error(format!("Unknown object type: {}", type_));
+    }
+
+    let media_classes = string_list_option(&mut cx, filter, "mediaClasses")?;
+
+    let selector = match filter.get_opt::<JsString, _, _>(&mut cx, "selector")? {
+        Some(selector) => match query::Selector::parse(&selector.value(&mut cx)) {
+            Ok(selector) => Some(selector),
+            Err(error) => return cx.throw_error(format!("Invalid selector: {}", error)),
+        },
+        None => None,
+    };
+
+    let node_ids = match filter.get_opt::<JsArray, _, _>(&mut cx, "nodeIds")? {
+        Some(node_ids) => node_ids
+            .to_vec(&mut cx)?
+            .into_iter()
+            .map(|id| Ok(id.downcast_or_throw::<JsNumber, _>(&mut cx)?.value(&mut cx) as u32))
+            .collect::<NeonResult<Vec<u32>>>()?,
+        None => Vec::new(),
+    };
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    EVENT_SUBSCRIPTIONS.lock().unwrap().insert(
+        id,
+        events::EventSubscription {
+            filter: events::EventFilter {
+                types,
+                media_classes,
+                selector,
+                node_ids,
+            },
+            batch: events::EventBatch::new(Duration::from_secs_f64(coalesce_ms / 1000.0)),
+        },
+    );
+
+    Ok(cx.number(id))
+}
+
+fn unsubscribe_events(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
+
+    let id = id.value(&mut cx) as u32;
+
+    EVENT_SUBSCRIPTIONS.lock().unwrap().remove(&id);
+    CALLBACKS.lock().unwrap().remove(&id);
+
+    Ok(cx.undefined())
+}
+
+fn meter(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let target_id = cx.argument::<JsNumber>(0)?;
+    let interval_ms = cx.argument::<JsNumber>(1)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+
+    let target_id = target_id.value(&mut cx) as u32;
+    let interval_ms = interval_ms.value(&mut cx);
+
+    if !interval_ms.is_finite() || interval_ms < 0.0 {
+        return cx.throw_range_error("intervalMs must be a finite number of at least 0");
+    }
+    let interval_ms = interval_ms as u64;
+
+    // The target could be a node or a port, in that case only the channel of the port is metered.
+    let all_data = ALL_DATA.lock().unwrap();
+    let target = match all_data.get(&target_id) {
+        Some(PipewireData::Node(node)) => Some((node.id, None)),
+        Some(PipewireData::Port(port)) => Some((
+            port.node_id,
+            port.props
+                .get("port.id")
+                .and_then(|index| index.parse::<usize>().ok()),
+        )),
+        _ => None,
+    };
+    drop(all_data);
+
+    let (node_id, channel) = match target {
+        Some(target) => target,
+        None => return cx.throw_error("No node or port found with that id"),
+    };
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::CreateMeter {
+        id,
+        node_id,
+        channel,
+        interval_ms,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.number(id))
+}
+
+fn analyze(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let node_id = cx.argument::<JsNumber>(0)?;
+    let fft_size = cx.argument::<JsNumber>(1)?;
+    let bands = cx.argument::<JsNumber>(2)?;
+    let interval_ms = cx.argument::<JsNumber>(3)?;
+    let callback = cx.argument::<JsFunction>(4)?;
+
+    let node_id = node_id.value(&mut cx) as u32;
+    let fft_size = fft_size.value(&mut cx) as usize;
+    let bands = bands.value(&mut cx) as usize;
+    let interval_ms = interval_ms.value(&mut cx);
+
+    if !interval_ms.is_finite() || interval_ms < 0.0 {
+        return cx.throw_range_error("intervalMs must be a finite number of at least 0");
+    }
+    let interval_ms = interval_ms as u64;
+
+    if !fft_size.is_power_of_two() || fft_size < 32 || fft_size > 32768 {
+        return cx.throw_range_error("fftSize must be a power of two between 32 and 32768");
+    }
+    if bands == 0 || bands > fft_size / 2 {
+        return cx.throw_range_error("bands must be between 1 and fftSize / 2");
+    }
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let node_exists = matches!(all_data.get(&node_id), Some(PipewireData::Node(_)));
+    drop(all_data);
+
+    if !node_exists {
+        return cx.throw_error("No node found with that id");
+    }
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::CreateSpectrumAnalyzer {
+        id,
+        node_id,
+        fft_size,
+        bands,
+        interval_ms,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.number(id))
+}
+
+fn detect_activity(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let node_id = cx.argument::<JsNumber>(0)?;
+    let threshold_db = cx.argument::<JsNumber>(1)?;
+    let hold_ms = cx.argument::<JsNumber>(2)?;
+    let callback = cx.argument::<JsFunction>(3)?;
+
+    let node_id = node_id.value(&mut cx) as u32;
+    let threshold_db = threshold_db.value(&mut cx);
+    let hold_ms = hold_ms.value(&mut cx);
+
+    if !threshold_db.is_finite() {
+        return cx.throw_range_error("thresholdDb must be a finite number");
+    }
+    if !hold_ms.is_finite() || hold_ms < 0.0 {
+        return cx.throw_range_error("holdMs must be a finite number of at least 0");
+    }
+    let threshold_db = threshold_db as f32;
+    let hold_ms = hold_ms as u64;
+
+    let all_data = ALL_DATA.lock().unwrap();
+    let node_exists = matches!(all_data.get(&node_id), Some(PipewireData::Node(_)));
+    drop(all_data);
+
+    if !node_exists {
+        return cx.throw_error("No node found with that id");
+    }
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::CreateActivityDetector {
+        id,
+        node_id,
+        threshold_db,
+        hold_ms,
+    });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.number(id))
+}
+
+fn stop_capture(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
+
+    let id = id.value(&mut cx) as u32;
+
+    // Stop sending events to JS right away, the stream is closed in the pipewire thread.
+    CALLBACKS.lock().unwrap().remove(&id);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::StopCapture { id });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.undefined())
+}
+
+// Arguments: name, direction (Input to receive MIDI, Output to send it) and the callback of the events.
+fn create_midi_port(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let name = cx.argument::<JsString>(0)?;
+    let direction = cx.argument::<JsString>(1)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+
+    let name = name.value(&mut cx);
+    let direction = direction.value(&mut cx);
+
+    if name.is_empty() {
+        return cx.throw_error("The name of the MIDI port can't be empty");
+    }
+    let output = match direction.as_str() {
+        "Input" => false,
+        "Output" => true,
+        _ => {
+            return cx.throw_type_error(format!(
+                "The direction must be Input or Output, got {:?}",
+                direction
+            ))
+        }
+    };
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+    MIDI_PORTS.lock().unwrap().insert(id, output);
+
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::CreateMidiPort { id, name, output });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
+    Ok(cx.number(id))
+}
+
+// Arguments: the id of an output MIDI port and one MIDI message (a Buffer or an array of bytes).
+fn send_midi(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
+    let message = cx.argument::<JsValue>(1)?;
 
-    let input_nodes_name = input_nodes_name.value(&mut cx);
-    let output_node_id = output_node_id.value(&mut cx) as u32;
-    let permanent = permanent.value(&mut cx);
+    let id = id.value(&mut cx) as u32;
+
+    let message = if let Ok(buffer) = message.downcast::<JsBuffer, _>(&mut cx) {
+        buffer.as_slice(&cx).to_vec()
+    } else {
+        let bytes = message
+            .downcast_or_throw::<JsArray, _>(&mut cx)?
+            .to_vec(&mut cx)?;
+        let mut message = Vec::with_capacity(bytes.len());
+        for byte in bytes {
+            let byte = byte
+                .downcast_or_throw::<JsNumber, _>(&mut cx)?
+                .value(&mut cx);
+            if byte.fract() != 0.0 || !(0.0..=255.0).contains(&byte) {
+                return cx.throw_range_error(
+                    "The bytes of a MIDI message must be integers from 0 to 255",
+                );
+            }
+            message.push(byte as u8);
+        }
+        message
+    };
+
+    match MIDI_PORTS.lock().unwrap().get(&id) {
+        Some(true) => {}
+        Some(false) => return cx.throw_error("Not an output MIDI port"),
+        None => return cx.throw_error("No MIDI port found with that id"),
+    }
+    if let Err(error) = midi::check_message(&message) {
+        return cx.throw_error(error);
+    }
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -705,33 +4135,24 @@ fn link_nodes_name_to_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    // send the message to the pw thread
-    let result = temp_pw_sender.send(PipewireOptions::LinkNodesNameToId {
-        output_nodes_name: input_nodes_name,
-        input_node_id: output_node_id,
-        permanent: permanent,
-    });
+    let _ = temp_pw_sender.send(PipewireOptions::SendMidi { id, message });
 
     // put the pw_sender back in the context data
     PW_SENDER.with(|pw_sender| {
         pw_sender.borrow_mut().replace(temp_pw_sender);
     });
 
-    if let Err(_) = result {
-        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
-            println!("Error sending message to pw thread");
-        }
-    }
-
     Ok(cx.undefined())
 }
 
-fn unlink_nodes_name_to_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let output_nodes_name = cx.argument::<JsString>(0)?;
-    let input_node_id = cx.argument::<JsNumber>(1)?;
+fn close_midi_port(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
 
-    let output_nodes_name = output_nodes_name.value(&mut cx);
-    let input_node_id = input_node_id.value(&mut cx) as u32;
+    let id = id.value(&mut cx) as u32;
+
+    // Stop sending events to JS right away, the node is destroyed in the pipewire thread.
+    MIDI_PORTS.lock().unwrap().remove(&id);
+    CALLBACKS.lock().unwrap().remove(&id);
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -741,34 +4162,58 @@ fn unlink_nodes_name_to_id(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    // send the message to the pw thread
-    let result = temp_pw_sender.send(PipewireOptions::UnLinkNodesNameToId {
-        output_nodes_name,
-        input_node_id,
-    });
+    let _ = temp_pw_sender.send(PipewireOptions::CloseMidiPort { id });
 
     // put the pw_sender back in the context data
     PW_SENDER.with(|pw_sender| {
         pw_sender.borrow_mut().replace(temp_pw_sender);
     });
 
-    if let Err(_) = result {
-        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
-            println!("Error sending message to pw thread");
-        }
-    }
-
     Ok(cx.undefined())
 }
 
-fn link_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let input_port_id = cx.argument::<JsNumber>(0)?;
-    let output_port_id = cx.argument::<JsNumber>(1)?;
-    let permanent = cx.argument::<JsBoolean>(2)?;
+// Arguments: the id of a video node, the options (format, width and height, all optional) and the callback of the events.
+fn create_video_capture(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let node_id = cx.argument::<JsNumber>(0)?;
+    let options = cx.argument::<JsObject>(1)?;
+    let callback = cx.argument::<JsFunction>(2)?;
 
-    let input_port_id = input_port_id.value(&mut cx) as u32;
-    let output_port_id = output_port_id.value(&mut cx) as u32;
-    let permanent = permanent.value(&mut cx);
+    let node_id = node_id.value(&mut cx) as u32;
+
+    let format = match options.get_opt::<JsString, _, _>(&mut cx, "format")? {
+        Some(format) => Some(format.value(&mut cx)),
+        None => None,
+    };
+    let width = integer_option(&mut cx, options, "width", 1)?;
+    let height = integer_option(&mut cx, options, "height", 1)?;
+
+    let size = match (width, height) {
+        (Some(width), Some(height)) => Some((width, height)),
+        (None, None) => None,
+        _ => return cx.throw_type_error("width and height must be set together"),
+    };
+    // check the format and the size with the ones that are set, any format can have a size of 2x2
+    let check_format = format.as_deref().unwrap_or("RGBA");
+    let (check_width, check_height) = size.unwrap_or((2, 2));
+    if let Err(error) = video::frame_layout(check_format, check_width, check_height) {
+        return cx.throw_range_error(error);
+    }
+
+    let is_video_node = match ALL_DATA.lock().unwrap().get(&node_id) {
+        Some(PipewireData::Node(node)) => node
+            .props
+            .get("media.class")
+            .is_some_and(|class| class.contains("Video")),
+        _ => return cx.throw_error("No node found with that id"),
+    };
+    if !is_video_node {
+        return cx.throw_error("The node is not a video node");
+    }
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+    VIDEO_STREAMS.lock().unwrap().insert(id, None);
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -778,11 +4223,11 @@ fn link_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    // send the message to the pw thread
-    let result = temp_pw_sender.send(PipewireOptions::LinkPorts {
-        input_port: input_port_id,
-        output_port: output_port_id,
-        permanent: permanent,
+    let _ = temp_pw_sender.send(PipewireOptions::CreateVideoCapture {
+        id,
+        node_id,
+        format,
+        size,
     });
 
     // put the pw_sender back in the context data
@@ -790,21 +4235,46 @@ fn link_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
         pw_sender.borrow_mut().replace(temp_pw_sender);
     });
 
-    if let Err(_) = result {
-        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
-            println!("Error sending message to pw thread");
-        }
-    }
-
-    Ok(cx.undefined())
+    Ok(cx.number(id))
 }
 
-fn unlink_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let input_port_id = cx.argument::<JsNumber>(0)?;
-    let output_port_id = cx.argument::<JsNumber>(1)?;
+// Arguments: the options (name, format, width and height) and the callback of the events.
+fn create_virtual_camera(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let options = cx.argument::<JsObject>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+
+    let name = options
+        .get::<JsString, _, _>(&mut cx, "name")?
+        .value(&mut cx);
+    let format = options
+        .get::<JsString, _, _>(&mut cx, "format")?
+        .value(&mut cx);
+    let width = options
+        .get::<JsNumber, _, _>(&mut cx, "width")?
+        .value(&mut cx);
+    let height = options
+        .get::<JsNumber, _, _>(&mut cx, "height")?
+        .value(&mut cx);
+
+    if name.is_empty() {
+        return cx.throw_error("The name of the virtual camera can't be empty");
+    }
+    if width.fract() != 0.0 || height.fract() != 0.0 || width < 0.0 || height < 0.0 {
+        return cx.throw_range_error("width and height must be positive integers");
+    }
+    let (width, height) = (
+        width.min(u32::MAX as f64) as u32,
+        height.min(u32::MAX as f64) as u32,
+    );
+    let size = match video::frame_layout(&format, width, height) {
+        Ok((_, size)) => size,
+        Err(error) => return cx.throw_range_error(error),
+    };
 
-    let input_port_id = input_port_id.value(&mut cx) as u32;
-    let output_port_id = output_port_id.value(&mut cx) as u32;
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
+    VIDEO_STREAMS.lock().unwrap().insert(id, Some(size));
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -814,10 +4284,12 @@ fn unlink_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    // send the message to the pw thread
-    let result = temp_pw_sender.send(PipewireOptions::UnLinkPorts {
-        input_port: input_port_id,
-        output_port: output_port_id,
+    let _ = temp_pw_sender.send(PipewireOptions::CreateVirtualCamera {
+        id,
+        name,
+        format,
+        width,
+        height,
     });
 
     // put the pw_sender back in the context data
@@ -825,144 +4297,121 @@ fn unlink_ports(mut cx: FunctionContext) -> JsResult<JsUndefined> {
         pw_sender.borrow_mut().replace(temp_pw_sender);
     });
 
-    if let Err(_) = result {
-        if ENABLE_DEBUG.with(|debug| *debug.borrow()) {
-            println!("Error sending message to pw thread");
+    Ok(cx.number(id))
+}
+
+// Arguments: the id of a virtual camera and a frame, tightly packed in the format of the camera.
+fn send_video_frame(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
+    let frame = cx.argument::<JsBuffer>(1)?;
+
+    let id = id.value(&mut cx) as u32;
+    let frame = frame.as_slice(&cx).to_vec();
+
+    match VIDEO_STREAMS.lock().unwrap().get(&id) {
+        Some(Some(size)) if *size == frame.len() => {}
+        Some(Some(size)) => {
+            return cx.throw_range_error(format!(
+                "The frame must have {} bytes, got {}",
+                size,
+                frame.len()
+            ))
         }
+        _ => return cx.throw_error("No virtual camera found with that id"),
     }
 
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
+
+    let _ = temp_pw_sender.send(PipewireOptions::SendVideoFrame { id, frame });
+
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
+
     Ok(cx.undefined())
 }
 
-fn get_current_nodes(
-    node_name: String,
-    node_directions: Vec<String>,
-) -> Result<Vec<PipewireNode>, String> {
-    let all_data = ALL_DATA.lock().unwrap();
-    let mut new_nodes = Vec::new();
-    for data in all_data.iter() {
-        if let PipewireData::Node(node) = data.1 {
-            if node.name.contains(node_name.as_str())
-                && node_directions.contains(&node.node_direction)
-            {
-                new_nodes.push(node.clone());
-            }
-        }
-    }
-    drop(all_data);
+fn close_video_stream(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
 
-    Ok(new_nodes)
-}
+    let id = id.value(&mut cx) as u32;
 
-fn wait_for_new_node(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let rt = runtime(&mut cx)?;
-    let node_name = cx.argument::<JsString>(0)?;
-    let node_direction = cx.argument::<JsString>(1)?;
-    let timeout = cx.argument::<JsNumber>(2)?;
-    let channel = cx.channel();
-    let (deferred, promise) = cx.promise();
+    // Stop sending events to JS right away, the stream is destroyed in the pipewire thread.
+    VIDEO_STREAMS.lock().unwrap().remove(&id);
+    CALLBACKS.lock().unwrap().remove(&id);
 
-    let node_name = node_name.value(&mut cx);
-    let node_direction = node_direction.value(&mut cx);
-    let timeout = timeout.value(&mut cx) as u128;
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
 
-    let mut node_directions = Vec::new();
-    if node_direction == "Input" || node_direction == "Output" {
-        node_directions.push(node_direction);
-    } else {
-        node_directions.push("Input".to_string());
-        node_directions.push("Output".to_string());
-    }
+    let _ = temp_pw_sender.send(PipewireOptions::CloseVideoStream { id });
 
-    // get the actual nodes from the context data
-    let nodes = get_current_nodes(node_name.clone(), node_directions.clone());
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
 
-    match nodes {
-        Ok(nodes) => {
-            // get the actual time
-            let start_time = Instant::now();
-            // start async task
-            rt.spawn(async move {
-                let mut _node = None;
+    Ok(cx.undefined())
+}
 
-                loop {
-                    // get the actual nodes from the context data
-                    let new_nodes =
-                        get_current_nodes(node_name.clone(), node_directions.clone()).unwrap();
+// Arguments: the options (captureProps, playbackProps, latencyMs and channels) and the callback of the events.
+fn create_loopback(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let js_options = cx.argument::<JsObject>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
 
-                    // check if the length of the nodes is different
-                    if new_nodes.len() > 0 {
-                        ENABLE_DEBUG.with(|debug| {
-                            if *debug.borrow() {
-                                println!("Old nodes:");
-                                for node in nodes.iter() {
-                                    println!("{} - {}", node.name, node.id);
-                                }
-                                println!("");
-                                println!("New nodes:");
-                                for node in new_nodes.iter() {
-                                    println!("{} - {}", node.name, node.id);
-                                }
-                            }
-                        });
+    let latency_ms = match js_options.get_opt::<JsNumber, _, _>(&mut cx, "latencyMs")? {
+        Some(latency_ms) => Some(latency_ms.value(&mut cx)),
+        None => None,
+    };
+    let options = loopback::LoopbackOptions {
+        capture_props: props_option(&mut cx, js_options, "captureProps")?,
+        playback_props: props_option(&mut cx, js_options, "playbackProps")?,
+        latency_ms,
+        channels: integer_option(&mut cx, js_options, "channels", 1)?,
+    };
 
-                        // get the new node compared to the old nodes
-                        for new_node in new_nodes.iter() {
-                            let mut found = false;
-                            for node in nodes.iter() {
-                                if new_node.id == node.id {
-                                    found = true;
-                                    break;
-                                }
-                            }
-                            if !found {
-                                _node = Some(new_node.clone());
-                                break;
-                            }
-                        }
+    // check the options before registering the callback, the pipewire thread builds the arguments again
+    if let Err(error) = options.args(0) {
+        return cx.throw_error(error);
+    }
 
-                        if _node.is_some() {
-                            break;
-                        }
-                    }
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
 
-                    // check if the timeout is reached (default 5 seconds)
-                    if start_time.elapsed().as_millis() > timeout {
-                        break;
-                    }
+    // get the pw_sender from the context data
+    let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
+        pw_sender
+            .borrow_mut()
+            .take()
+            .expect("pw_sender not set in context data")
+    });
 
-                    // wait 500ms (Maybe this can be changed to a lower value)
-                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-                }
+    let _ = temp_pw_sender.send(PipewireOptions::CreateLoopback { id, options });
 
-                // defer the result
-                deferred.settle_with(&channel, move |mut cx_task| match _node {
-                    Some(node) => {
-                        let obj = node.to_object(&mut cx_task)?;
-                        Ok(obj)
-                    }
-                    None => cx_task.throw_error("No node found"),
-                });
-            });
-        }
-        Err(_) => {
-            return cx.throw_error("Error getting the nodes");
-        }
-    };
+    // put the pw_sender back in the context data
+    PW_SENDER.with(|pw_sender| {
+        pw_sender.borrow_mut().replace(temp_pw_sender);
+    });
 
-    Ok(promise)
+    Ok(cx.number(id))
 }
 
-fn create_source(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let new_source_name = cx.argument::<JsString>(0)?;
-    let new_audio_position = cx.argument::<JsString>(1)?;
-    let new_channel_count = cx.argument::<JsNumber>(2)?;
-    let permanent = cx.argument::<JsBoolean>(3)?;
-
-    let new_source_name = new_source_name.value(&mut cx);
-    let new_audio_position = new_audio_position.value(&mut cx);
-    let new_channel_count = new_channel_count.value(&mut cx) as u32;
-    let permanent = permanent.value(&mut cx);
+fn destroy_loopback(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
+
+    let id = id.value(&mut cx) as u32;
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -972,12 +4421,7 @@ fn create_source(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    let _ = temp_pw_sender.send(PipewireOptions::CreateSource {
-        source_name: new_source_name,
-        audio_position: new_audio_position,
-        channel_count: new_channel_count,
-        permanent: permanent,
-    });
+    let _ = temp_pw_sender.send(PipewireOptions::DestroyLoopback { id });
 
     // put the pw_sender back in the context data
     PW_SENDER.with(|pw_sender| {
@@ -987,16 +4431,21 @@ fn create_source(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     Ok(cx.undefined())
 }
 
-fn create_sink(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let new_sink_name = cx.argument::<JsString>(0)?;
-    let new_audio_position = cx.argument::<JsString>(1)?;
-    let new_channel_count = cx.argument::<JsNumber>(2)?;
-    let permanent = cx.argument::<JsBoolean>(3)?;
-
-    let new_sink_name = new_sink_name.value(&mut cx);
-    let new_audio_position = new_audio_position.value(&mut cx);
-    let new_channel_count = new_channel_count.value(&mut cx) as u32;
-    let permanent = permanent.value(&mut cx);
+fn subscribe_profiler(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let interval_ms = cx.argument::<JsNumber>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+
+    let interval_ms = interval_ms.value(&mut cx);
+
+    // the aggregator would emit on every profile otherwise
+    if !interval_ms.is_finite() || interval_ms < 1.0 {
+        return cx.throw_range_error("intervalMs must be a finite number of at least 1");
+    }
+    let interval_ms = interval_ms as u64;
+
+    let callback = callback.root(&mut cx);
+    let js_channel = cx.channel();
+    let id = register_callback(js_channel, callback);
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -1006,25 +4455,22 @@ fn create_sink(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    let _ = temp_pw_sender.send(PipewireOptions::CreateSink {
-        sink_name: new_sink_name,
-        audio_position: new_audio_position,
-        channel_count: new_channel_count,
-        permanent: permanent,
-    });
+    let _ = temp_pw_sender.send(PipewireOptions::SubscribeProfiler { id, interval_ms });
 
     // put the pw_sender back in the context data
     PW_SENDER.with(|pw_sender| {
         pw_sender.borrow_mut().replace(temp_pw_sender);
     });
 
-    Ok(cx.undefined())
+    Ok(cx.number(id))
 }
 
-fn destroy_object(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-    let destroy_target_id = cx.argument::<JsNumber>(0)?;
+fn unsubscribe_profiler(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?;
 
-    let destroy_target_id = destroy_target_id.value(&mut cx) as u32;
+    let id = id.value(&mut cx) as u32;
+
+    CALLBACKS.lock().unwrap().remove(&id);
 
     // get the pw_sender from the context data
     let temp_pw_sender: pipewire::channel::Sender<PipewireOptions> = PW_SENDER.with(|pw_sender| {
@@ -1034,9 +4480,7 @@ fn destroy_object(mut cx: FunctionContext) -> JsResult<JsUndefined> {
             .expect("pw_sender not set in context data")
     });
 
-    let _ = temp_pw_sender.send(PipewireOptions::DeleteObject {
-        id: destroy_target_id,
-    });
+    let _ = temp_pw_sender.send(PipewireOptions::UnsubscribeProfiler { id });
 
     // put the pw_sender back in the context data
     PW_SENDER.with(|pw_sender| {
@@ -1054,8 +4498,20 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("getPorts", get_ports)?;
     cx.export_function("getNodes", get_nodes)?;
     cx.export_function("getClients", get_clients)?;
+    cx.export_function("getObjectsOfClient", get_objects_of_client)?;
+    cx.export_function("getOwnedObjects", get_owned_objects)?;
+    cx.export_function("cleanupOwnedObjects", cleanup_owned_objects)?;
+    cx.export_function("takeObjectHandle", take_object_handle)?;
+    cx.export_function("releaseObjectHandle", release_object_handle)?;
+    cx.export_function("getClientPermissions", get_client_permissions)?;
+    cx.export_function("updateClientPermissions", update_client_permissions)?;
+    cx.export_function("allowClientDisconnect", allow_client_disconnect)?;
+    cx.export_function("disconnectClient", disconnect_client)?;
+    cx.export_function("getNodeStats", get_node_stats)?;
+    cx.export_function("query", query_graph)?;
     cx.export_function("getOutputNodes", get_output_nodes)?;
     cx.export_function("getInputNodes", get_input_nodes)?;
+    cx.export_function("getPathLatency", get_path_latency)?;
     cx.export_function("linkNodesNameToId", link_nodes_name_to_id)?;
     cx.export_function("unlinkNodesNameToId", unlink_nodes_name_to_id)?;
     cx.export_function("linkPorts", link_ports)?;
@@ -1063,6 +4519,35 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("waitForNewNode", wait_for_new_node)?;
     cx.export_function("createSource", create_source)?;
     cx.export_function("createSink", create_sink)?;
+    cx.export_function("createVirtualDevice", create_virtual_device)?;
     cx.export_function("destroyObject", destroy_object)?;
+    cx.export_function("canDestroy", can_destroy)?;
+    cx.export_function("canLink", can_link)?;
+    cx.export_function("setLatencyOffset", set_latency_offset)?;
+    cx.export_function("getSettings", get_settings)?;
+    cx.export_function("setSettings", set_settings)?;
+    cx.export_function("getMetadata", get_metadata)?;
+    cx.export_function("setMetadata", set_metadata)?;
+    cx.export_function("clearMetadata", clear_metadata)?;
+    cx.export_function("subscribeMetadata", subscribe_metadata)?;
+    cx.export_function("unsubscribeMetadata", unsubscribe_metadata)?;
+    cx.export_function("moveStream", move_stream)?;
+    cx.export_function("subscribeEvents", subscribe_events)?;
+    cx.export_function("unsubscribeEvents", unsubscribe_events)?;
+    cx.export_function("meter", meter)?;
+    cx.export_function("analyze", analyze)?;
+    cx.export_function("detectActivity", detect_activity)?;
+    cx.export_function("stopCapture", stop_capture)?;
+    cx.export_function("createMidiPort", create_midi_port)?;
+    cx.export_function("sendMidi", send_midi)?;
+    cx.export_function("closeMidiPort", close_midi_port)?;
+    cx.export_function("createVideoCapture", create_video_capture)?;
+    cx.export_function("createVirtualCamera", create_virtual_camera)?;
+    cx.export_function("sendVideoFrame", send_video_frame)?;
+    cx.export_function("closeVideoStream", close_video_stream)?;
+    cx.export_function("createLoopback", create_loopback)?;
+    cx.export_function("destroyLoopback", destroy_loopback)?;
+    cx.export_function("subscribeProfiler", subscribe_profiler)?;
+    cx.export_function("unsubscribeProfiler", unsubscribe_profiler)?;
     Ok(())
 }
@@ -0,0 +1,317 @@
+use pipewire::{context::ContextRc, sys as pw_sys};
+use std::{
+    ffi::CString,
+    fmt::Write,
+    ptr,
+    time::{Duration, Instant},
+};
+
+use crate::device::NAME_PREFIX;
+
+pub(crate) const MODULE_NAME: &str = "libpipewire-module-loopback";
+
+// Max number of channels (SPA_AUDIO_MAX_CHANNELS) and max delay of a loopback.
+const MAX_CHANNELS: u32 = 64;
+const MAX_LATENCY_MS: f64 = 10_000.0;
+
+// How long the server has to announce the nodes of a loaded loopback.
+// The module creates them on its own connection, so a sync of our core doesn't wait for them.
+pub(crate) const NODES_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Options of a loopback, the props of its capture and playback streams are passed as is to the module.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct LoopbackOptions {
+    pub capture_props: Vec<(String, String)>,
+    pub playback_props: Vec<(String, String)>,
+    pub latency_ms: Option<f64>,
+    pub channels: Option<u32>,
+}
+
+// The arguments of a loaded module-loopback, with the node.name of its capture and playback nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LoopbackArgs {
+    pub args: String,
+    pub capture_name: String,
+    pub playback_name: String,
+}
+
+// A loaded loopback waiting for the globals of its nodes.
+#[derive(Clone, Debug)]
+pub(crate) struct PendingLoopback {
+    capture_name: String,
+    playback_name: String,
+    capture_id: Option<u32>,
+    playback_id: Option<u32>,
+    deadline: Instant,
+}
+
+impl PendingLoopback {
+    pub fn new(args: &LoopbackArgs, now: Instant) -> Self {
+        Self {
+            capture_name: args.capture_name.clone(),
+            playback_name: args.playback_name.clone(),
+            capture_id: None,
+            playback_id: None,
+            deadline: now + NODES_TIMEOUT,
+        }
+    }
+
+    // Record a node announced by the registry, returns the ids of both nodes once they are known.
+    pub fn node_added(&mut self, id: u32, name: &str) -> Option<(u32, u32)> {
+        if name == self.capture_name {
+            self.capture_id = Some(id);
+        } else if name == self.playback_name {
+            self.playback_id = Some(id);
+        }
+        self.capture_id.zip(self.playback_id)
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.deadline <= now
+    }
+}
+
+// A string of SPA JSON, quoted and escaped.
+fn json_string(value: &str) -> String {
+    let mut string = String::with_capacity(value.len() + 2);
+    string.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\t' => string.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(string, "\\u{:04x}", c as u32);
+            }
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+fn json_props(props: &[(String, String)]) -> String {
+    let props: Vec<String> = props
+        .iter()
+        .map(|(key, value)| format!("{} = {}", json_string(key), json_string(value)))
+        .collect();
+    format!("{{ {} }}", props.join(" "))
+}
+
+impl LoopbackOptions {
+    // Check the options and build the arguments of the module, the nodes are named after `id`
+    // unless their props have a node.name.
+    pub fn args(&self, id: u32) -> Result<LoopbackArgs, String> {
+        if let Some(latency_ms) = self.latency_ms {
+            if !latency_ms.is_finite() || !(0.0..=MAX_LATENCY_MS).contains(&latency_ms) {
+                return Err(format!(
+                    "latencyMs must be from 0 to {}, got {}",
+                    MAX_LATENCY_MS, latency_ms
+                ));
+            }
+        }
+        if let Some(channels) = self.channels {
+            if !(1..=MAX_CHANNELS).contains(&channels) {
+                return Err(format!(
+                    "channels must be from 1 to {}, got {}",
+                    MAX_CHANNELS, channels
+                ));
+            }
+        }
+
+        let props = self.capture_props.iter().chain(&self.playback_props);
+        for (key, value) in props {
+            if key.is_empty() {
+                return Err(String::from("The keys of the props can't be empty"));
+            }
+            // the arguments are passed as a C string
+            if key.contains('\0') || value.contains('\0') {
+                return Err(format!("The property {:?} can't contain a NUL byte", key));
+            }
+        }
+
+        let with_name = |props: &[(String, String)], suffix: &str| {
+            let mut props = props.to_vec();
+            let name = match props.iter().find(|(key, _)| key == "node.name") {
+                Some((_, name)) => name.clone(),
+                None => {
+                    let name = format!("{}loopback-{}.{}", NAME_PREFIX, id, suffix);
+                    props.push((String::from("node.name"), name.clone()));
+                    name
+                }
+            };
+            (props, name)
+        };
+        let (capture_props, capture_name) = with_name(&self.capture_props, "capture");
+        let (playback_props, playback_name) = with_name(&self.playback_props, "playback");
+
+        if capture_name == playback_name {
+            return Err(String::from(
+                "The capture and playback nodes can't have the same node.name",
+            ));
+        }
+
+        let mut args = String::from("{");
+        if let Some(channels) = self.channels {
+            let _ = write!(args, " audio.channels = {}", channels);
+        }
+        if let Some(latency_ms) = self.latency_ms {
+            let _ = write!(args, " target.delay.sec = {}", latency_ms / 1000.0);
+        }
+        let _ = write!(
+            args,
+            " capture.props = {} playback.props = {} }}",
+            json_props(&capture_props),
+            json_props(&playback_props)
+        );
+
+        Ok(LoopbackArgs {
+            args,
+            capture_name,
+            playback_name,
+        })
+    }
+}
+
+// A module loaded in the context of the pipewire thread, its objects live in this process.
+// pipewire-rs doesn't wrap pw_impl_module, the module is unloaded when this struct is dropped.
+pub(crate) struct LoadedModule {
+    module: ptr::NonNull<pw_sys::pw_impl_module>,
+    // the context destroys its modules, it must outlive this one
+    _context: ContextRc,
+}
+
+impl LoadedModule {
+    pub fn load(context: &ContextRc, name: &str, args: &str) -> Option<Self> {
+        let name = CString::new(name).ok()?;
+        let args = CString::new(args).ok()?;
+
+        let module = unsafe {
+            pw_sys::pw_context_load_module(
+                context.as_raw_ptr(),
+                name.as_ptr(),
+                args.as_ptr(),
+                ptr::null_mut(),
+            )
+        };
+
+        ptr::NonNull::new(module).map(|module| Self {
+            module,
+            _context: context.clone(),
+        })
+    }
+}
+
+impl Drop for LoadedModule {
+    fn drop(&mut self) {
+        unsafe {
+            pw_sys::pw_impl_module_destroy(self.module.as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(props: &[(&str, &str)]) -> Vec<(String, String)> {
+        props
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn builds_the_module_arguments() {
+        let args = LoopbackOptions::default().args(7).unwrap();
+        assert_eq!(args.capture_name, "node-pipewire:loopback-7.capture");
+        assert_eq!(args.playback_name, "node-pipewire:loopback-7.playback");
+        assert_eq!(
+            args.args,
+            "{ capture.props = { \"node.name\" = \"node-pipewire:loopback-7.capture\" } \
+             playback.props = { \"node.name\" = \"node-pipewire:loopback-7.playback\" } }"
+        );
+
+        let options = LoopbackOptions {
+            capture_props: props(&[("node.name", "mic"), ("target.object", "a \"b\"\\c")]),
+            playback_props: props(&[("media.class", "Audio/Source")]),
+            latency_ms: Some(50.0),
+            channels: Some(2),
+        };
+        let args = options.args(7).unwrap();
+        assert_eq!(args.capture_name, "mic");
+        assert_eq!(args.playback_name, "node-pipewire:loopback-7.playback");
+        assert_eq!(
+            args.args,
+            "{ audio.channels = 2 target.delay.sec = 0.05 \
+             capture.props = { \"node.name\" = \"mic\" \"target.object\" = \"a \\\"b\\\"\\\\c\" } \
+             playback.props = { \"media.class\" = \"Audio/Source\" \
+             \"node.name\" = \"node-pipewire:loopback-7.playback\" } }"
+        );
+
+        assert_eq!(json_string("a\nb\u{1}"), "\"a\\nb\\u0001\"");
+    }
+
+    #[test]
+    fn waits_for_both_nodes() {
+        let start = Instant::now();
+        let args = LoopbackOptions::default().args(3).unwrap();
+        let mut pending = PendingLoopback::new(&args, start);
+
+        assert_eq!(pending.node_added(40, "other"), None);
+        assert_eq!(
+            pending.node_added(41, "node-pipewire:loopback-3.playback"),
+            None
+        );
+        assert_eq!(
+            pending.node_added(42, "node-pipewire:loopback-3.capture"),
+            Some((42, 41))
+        );
+
+        assert!(!pending.is_expired(start + NODES_TIMEOUT - Duration::from_millis(1)));
+        assert!(pending.is_expired(start + NODES_TIMEOUT));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let invalid = |options: LoopbackOptions| options.args(1).is_err();
+
+        assert!(invalid(LoopbackOptions {
+            latency_ms: Some(-1.0),
+            ..Default::default()
+        }));
+        assert!(invalid(LoopbackOptions {
+            latency_ms: Some(f64::NAN),
+            ..Default::default()
+        }));
+        assert!(invalid(LoopbackOptions {
+            channels: Some(0),
+            ..Default::default()
+        }));
+        assert!(invalid(LoopbackOptions {
+            channels: Some(65),
+            ..Default::default()
+        }));
+        assert!(invalid(LoopbackOptions {
+            capture_props: props(&[("", "x")]),
+            ..Default::default()
+        }));
+        assert!(invalid(LoopbackOptions {
+            playback_props: props(&[("node.description", "a\0b")]),
+            ..Default::default()
+        }));
+        assert!(invalid(LoopbackOptions {
+            capture_props: props(&[("node.name", "same")]),
+            playback_props: props(&[("node.name", "same")]),
+            ..Default::default()
+        }));
+
+        assert!(!invalid(LoopbackOptions {
+            latency_ms: Some(0.0),
+            channels: Some(64),
+            ..Default::default()
+        }));
+    }
+}
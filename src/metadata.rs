@@ -0,0 +1,163 @@
+use crate::PipewireMetadataProperty;
+
+// Name of the metadata holding the settings of the server (clock, log level...).
+pub(crate) const SETTINGS: &str = "settings";
+
+// Name of the metadata of the session manager (default nodes, targets of the streams).
+// It is created by the session manager, so it only exists while one is running.
+pub(crate) const DEFAULT: &str = "default";
+
+// Keys of the default metadata, set on the subject of a stream to choose the node it is linked to.
+// target.node (the id of the node) is deprecated in favor of target.object (its serial).
+pub(crate) const TARGET_OBJECT: &str = "target.object";
+pub(crate) const TARGET_NODE: &str = "target.node";
+
+// Keys of the clock settings, set on the subject 0 (the core) of the settings metadata.
+pub(crate) const CLOCK_RATE: &str = "clock.rate";
+pub(crate) const CLOCK_ALLOWED_RATES: &str = "clock.allowed-rates";
+pub(crate) const CLOCK_QUANTUM: &str = "clock.quantum";
+pub(crate) const CLOCK_MIN_QUANTUM: &str = "clock.min-quantum";
+pub(crate) const CLOCK_MAX_QUANTUM: &str = "clock.max-quantum";
+pub(crate) const CLOCK_FORCE_RATE: &str = "clock.force-rate";
+pub(crate) const CLOCK_FORCE_QUANTUM: &str = "clock.force-quantum";
+
+// Clock of the graph. A forced rate or quantum of 0 means it isn't forced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ClockSettings {
+    pub rate: Option<u32>,
+    pub allowed_rates: Vec<u32>,
+    pub quantum: Option<u32>,
+    pub min_quantum: Option<u32>,
+    pub max_quantum: Option<u32>,
+    pub force_rate: Option<u32>,
+    pub force_quantum: Option<u32>,
+}
+
+// Apply a property event of a metadata object to its properties.
+pub(crate) fn apply_property(
+    properties: &mut Vec<PipewireMetadataProperty>,
+    subject: u32,
+    key: Option<String>,
+    type_: Option<String>,
+    value: Option<String>,
+) {
+    let Some(key) = key else {
+        // No key, all the properties of the subject were removed.
+        properties.retain(|property| property.subject != subject);
+        return;
+    };
+
+    let position = properties
+        .iter()
+        .position(|property| property.subject == subject && property.key == key);
+
+    match (position, value) {
+        (Some(position), None) => {
+            properties.remove(position);
+        }
+        (Some(position), Some(value)) => {
+            properties[position].type_ = type_;
+            properties[position].value = value;
+        }
+        (None, Some(value)) => properties.push(PipewireMetadataProperty {
+            subject,
+            key,
+            type_,
+            value,
+        }),
+        (None, None) => {}
+    }
+}
+
+// Parse a list of rates, like "[ 44100 48000 ]" or "[ 44100, 48000 ]".
+fn parse_rates(value: &str) -> Vec<u32> {
+    value
+        .split(|c: char| c == '[' || c == ']' || c == ',' || c.is_whitespace())
+        .filter_map(|rate| rate.parse().ok())
+        .collect()
+}
+
+// Format a list of rates the way PipeWire writes clock.allowed-rates.
+pub(crate) fn format_rates(rates: &[u32]) -> String {
+    let rates: Vec<String> = rates.iter().map(|rate| rate.to_string()).collect();
+    format!("[ {} ]", rates.join(" "))
+}
+
+// Read the clock settings from the properties of the settings metadata.
+pub(crate) fn clock_settings(properties: &[PipewireMetadataProperty]) -> ClockSettings {
+    let get = |key: &str| {
+        properties
+            .iter()
+            .find(|property| property.subject == 0 && property.key == key)
+            .map(|property| property.value.as_str())
+    };
+    let get_number = |key: &str| get(key).and_then(|value| value.trim().parse::<u32>().ok());
+
+    ClockSettings {
+        rate: get_number(CLOCK_RATE),
+        allowed_rates: get(CLOCK_ALLOWED_RATES).map_or(Vec::new(), parse_rates),
+        quantum: get_number(CLOCK_QUANTUM),
+        min_quantum: get_number(CLOCK_MIN_QUANTUM),
+        max_quantum: get_number(CLOCK_MAX_QUANTUM),
+        force_rate: get_number(CLOCK_FORCE_RATE),
+        force_quantum: get_number(CLOCK_FORCE_QUANTUM),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(properties: &mut Vec<PipewireMetadataProperty>, subject: u32, key: &str, value: &str) {
+        apply_property(
+            properties,
+            subject,
+            Some(key.to_string()),
+            None,
+            Some(value.to_string()),
+        );
+    }
+
+    #[test]
+    fn applies_the_property_events() {
+        let mut properties = Vec::new();
+
+        set(&mut properties, 0, "a", "1");
+        set(&mut properties, 0, "b", "2");
+        set(&mut properties, 5, "a", "3");
+        set(&mut properties, 0, "a", "4");
+        assert_eq!(properties.len(), 3);
+        assert_eq!(properties[0].value, "4");
+
+        // no value removes the key
+        apply_property(&mut properties, 0, Some("b".to_string()), None, None);
+        assert_eq!(properties.len(), 2);
+
+        // no key removes the subject
+        apply_property(&mut properties, 0, None, None, None);
+        assert_eq!(properties.len(), 1);
+        assert_eq!(properties[0].subject, 5);
+    }
+
+    #[test]
+    fn reads_the_clock_settings() {
+        let mut properties = Vec::new();
+
+        set(&mut properties, 0, CLOCK_RATE, "48000");
+        set(&mut properties, 0, CLOCK_ALLOWED_RATES, "[ 44100, 48000 ]");
+        set(&mut properties, 0, CLOCK_QUANTUM, "1024");
+        set(&mut properties, 0, CLOCK_FORCE_QUANTUM, "0");
+        // only the subject 0 holds the settings
+        set(&mut properties, 3, CLOCK_MIN_QUANTUM, "32");
+
+        let settings = clock_settings(&properties);
+        assert_eq!(settings.rate, Some(48000));
+        assert_eq!(settings.allowed_rates, vec![44100, 48000]);
+        assert_eq!(settings.quantum, Some(1024));
+        assert_eq!(settings.min_quantum, None);
+        assert_eq!(settings.force_quantum, Some(0));
+        assert_eq!(settings.force_rate, None);
+
+        assert_eq!(format_rates(&settings.allowed_rates), "[ 44100 48000 ]");
+    }
+}
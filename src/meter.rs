@@ -0,0 +1,305 @@
+use crate::{
+    capture::{to_dbfs, CaptureProcessor},
+    MainOptions,
+};
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// Computes per-channel peak and RMS levels and sends them to the front every `interval`.
+pub(crate) struct LevelMeter {
+    id: u32,
+    // Only report this channel (used when metering a single port).
+    channel: Option<usize>,
+    interval: Duration,
+    last_emit: Instant,
+    peak: Vec<f32>,
+    sum_squares: Vec<f64>,
+    frames: u64,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+impl LevelMeter {
+    pub fn new(
+        id: u32,
+        channel: Option<usize>,
+        interval: Duration,
+        sender: mpsc::Sender<MainOptions>,
+    ) -> Self {
+        Self {
+            id,
+            channel,
+            interval,
+            last_emit: Instant::now(),
+            peak: Vec::new(),
+            sum_squares: Vec::new(),
+            frames: 0,
+            sender,
+        }
+    }
+
+    // Returns the peak and RMS levels (in dBFS) accumulated since the last call.
+    fn take_levels(&mut self) -> (Vec<f32>, Vec<f32>) {
+        let frames = self.frames.max(1) as f64;
+
+        let mut peak: Vec<f32> = self.peak.iter().map(|peak| to_dbfs(*peak)).collect();
+        let mut rms: Vec<f32> = self
+            .sum_squares
+            .iter()
+            .map(|sum| to_dbfs((sum / frames).sqrt() as f32))
+            .collect();
+
+        if let Some(channel) = self.channel {
+            peak = peak.get(channel).copied().into_iter().collect();
+            rms = rms.get(channel).copied().into_iter().collect();
+        }
+
+        self.peak.iter_mut().for_each(|peak| *peak = 0.0);
+        self.sum_squares.iter_mut().for_each(|sum| *sum = 0.0);
+        self.frames = 0;
+
+        (peak, rms)
+    }
+}
+
+impl CaptureProcessor for LevelMeter {
+    fn process(&mut self, samples: &[f32], channels: usize) {
+        // Reset the accumulators if the channel count changed.
+        if self.peak.len() != channels {
+            self.peak = vec![0.0; channels];
+            self.sum_squares = vec![0.0; channels];
+            self.frames = 0;
+        }
+
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let sample = sample.abs();
+                if sample > self.peak[channel] {
+                    self.peak[channel] = sample;
+                }
+                self.sum_squares[channel] += (sample * sample) as f64;
+            }
+            self.frames += 1;
+        }
+
+        if self.frames > 0 && self.last_emit.elapsed() >= self.interval {
+            self.last_emit = Instant::now();
+            let (peak, rms) = self.take_levels();
+
+            // The front could be gone, there is nothing to do in that case.
+            let _ = self.sender.send(MainOptions::MeterLevels {
+                id: self.id,
+                peak,
+                rms,
+            });
+        }
+    }
+}
+
+// Sends an event to the front when the signal crosses `threshold_db`. The signal has to stay
+// below the threshold for `hold` before being reported as silent, which also happens when the
+// node stops sending buffers (see `tick`).
+pub(crate) struct ActivityDetector {
+    id: u32,
+    threshold_db: f32,
+    hold: Duration,
+    active: bool,
+    last_active: Instant,
+    // Level of the last buffer, -inf once no buffer arrived for `hold`.
+    level: f32,
+    last_buffer: Instant,
+    sum_squares: Vec<f64>,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+impl ActivityDetector {
+    pub fn new(
+        id: u32,
+        threshold_db: f32,
+        hold: Duration,
+        sender: mpsc::Sender<MainOptions>,
+    ) -> Self {
+        Self {
+            id,
+            threshold_db,
+            hold,
+            active: false,
+            last_active: Instant::now(),
+            level: f32::NEG_INFINITY,
+            last_buffer: Instant::now(),
+            sum_squares: Vec::new(),
+            sender,
+        }
+    }
+
+    fn set_active(&mut self, active: bool) {
+        if active == self.active {
+            return;
+        }
+        self.active = active;
+
+        // The front could be gone, there is nothing to do in that case.
+        let _ = self.sender.send(MainOptions::ActivityChanged {
+            id: self.id,
+            active,
+            level: self.level,
+        });
+    }
+}
+
+impl CaptureProcessor for ActivityDetector {
+    fn process(&mut self, samples: &[f32], channels: usize) {
+        // RMS of the buffer, of the loudest channel.
+        self.sum_squares.clear();
+        self.sum_squares.resize(channels, 0.0);
+        let mut frames = 0;
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.sum_squares[channel] += (sample * sample) as f64;
+            }
+            frames += 1;
+        }
+        if frames == 0 {
+            return;
+        }
+
+        self.level = self
+            .sum_squares
+            .iter()
+            .map(|sum| to_dbfs((sum / frames as f64).sqrt() as f32))
+            .fold(f32::NEG_INFINITY, f32::max);
+        self.last_buffer = Instant::now();
+
+        let active = if self.level >= self.threshold_db {
+            self.last_active = Instant::now();
+            true
+        } else {
+            self.active && self.last_active.elapsed() < self.hold
+        };
+        self.set_active(active);
+    }
+
+    // The node may be suspended or paused, report the silence once `hold` passed without a loud buffer.
+    fn tick(&mut self) {
+        if !self.active || self.last_active.elapsed() < self.hold {
+            return;
+        }
+        if self.last_buffer.elapsed() >= self.hold {
+            self.level = f32::NEG_INFINITY;
+        }
+        self.set_active(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_peak_and_rms_per_channel() {
+        let (sender, receiver) = mpsc::channel();
+        let mut meter = LevelMeter::new(1, None, Duration::from_millis(0), sender);
+
+        // Left channel at full scale, right channel at half scale.
+        meter.process(&[1.0, 0.5, -1.0, -0.5, 1.0, 0.5, -1.0, -0.5], 2);
+
+        match receiver
+            .try_recv()
+            .expect("ERROR: meter did not emit levels")
+        {
+            MainOptions::MeterLevels { id, peak, rms } => {
+                assert_eq!(id, 1);
+                assert_eq!(peak.len(), 2);
+                assert!(peak[0].abs() < 0.001);
+                assert!((peak[1] + 6.0206).abs() < 0.001);
+                assert!((rms[1] + 6.0206).abs() < 0.001);
+            }
+            _ => panic!("ERROR: unexpected option sent by meter"),
+        }
+    }
+
+    #[test]
+    fn reports_only_the_selected_channel() {
+        let (sender, receiver) = mpsc::channel();
+        let mut meter = LevelMeter::new(1, Some(1), Duration::from_millis(0), sender);
+
+        meter.process(&[1.0, 0.0, 1.0, 0.0], 2);
+
+        match receiver
+            .try_recv()
+            .expect("ERROR: meter did not emit levels")
+        {
+            MainOptions::MeterLevels { peak, rms, .. } => {
+                assert_eq!(peak, vec![f32::NEG_INFINITY]);
+                assert_eq!(rms, vec![f32::NEG_INFINITY]);
+            }
+            _ => panic!("ERROR: unexpected option sent by meter"),
+        }
+    }
+
+    #[test]
+    fn detects_activity_and_silence() {
+        let (sender, receiver) = mpsc::channel();
+        let mut detector = ActivityDetector::new(1, -40.0, Duration::from_millis(0), sender);
+
+        // Silence at the start is not reported.
+        detector.process(&[0.0, 0.0, 0.0, 0.0], 2);
+        assert!(receiver.try_recv().is_err());
+
+        detector.process(&[0.5, 0.0, -0.5, 0.0], 2);
+        match receiver.try_recv() {
+            Ok(MainOptions::ActivityChanged { active, .. }) => assert!(active),
+            _ => panic!("ERROR: detector did not report activity"),
+        }
+
+        detector.process(&[0.0, 0.0, 0.0, 0.0], 2);
+        match receiver.try_recv() {
+            Ok(MainOptions::ActivityChanged { active, .. }) => assert!(!active),
+            _ => panic!("ERROR: detector did not report silence"),
+        }
+    }
+
+    #[test]
+    fn holds_activity_during_short_pauses() {
+        let (sender, receiver) = mpsc::channel();
+        let mut detector = ActivityDetector::new(1, -40.0, Duration::from_secs(60), sender);
+
+        detector.process(&[0.5, -0.5], 1);
+        detector.process(&[0.0, 0.0], 1);
+
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(MainOptions::ActivityChanged { active: true, .. })
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn reports_silence_when_buffers_stop() {
+        let (sender, receiver) = mpsc::channel();
+        let mut detector = ActivityDetector::new(1, -40.0, Duration::from_millis(0), sender);
+
+        // Nothing to report before the first active buffer.
+        detector.tick();
+        assert!(receiver.try_recv().is_err());
+
+        detector.process(&[0.5, -0.5], 1);
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(MainOptions::ActivityChanged { active: true, .. })
+        ));
+
+        // No more buffers, the timer of the pipewire thread reports the silence.
+        detector.tick();
+        match receiver.try_recv() {
+            Ok(MainOptions::ActivityChanged { active, level, .. }) => {
+                assert!(!active);
+                assert_eq!(level, f32::NEG_INFINITY);
+            }
+            _ => panic!("ERROR: detector did not report silence"),
+        }
+        detector.tick();
+        assert!(receiver.try_recv().is_err());
+    }
+}
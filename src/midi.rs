@@ -0,0 +1,366 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc, sync::mpsc};
+
+use pipewire::{
+    core::CoreRc,
+    properties::properties,
+    spa::{
+        param::{
+            format::{FormatProperties, MediaSubtype, MediaType},
+            ParamType,
+        },
+        pod::{object, property, serialize::PodSerializer, Pod, Value},
+        utils::{Direction, SpaTypes},
+    },
+    stream::{StreamFlags, StreamListener, StreamRc, StreamState},
+};
+
+use crate::{device::NAME_PREFIX, MainOptions};
+
+// Types of the pods of a sequence (spa/utils/type.h) and of its MIDI controls (spa/control/control.h).
+const SPA_TYPE_BYTES: u32 = 9;
+const SPA_TYPE_SEQUENCE: u32 = 16;
+const SPA_CONTROL_MIDI: u32 = 2;
+
+// Messages waiting for the graph to run the port are dropped past this.
+const MAX_PENDING: usize = 4096;
+
+// A MIDI message of a sequence, `offset` is the sample of the cycle it belongs to and `time` its
+// timestamp in ms on the monotonic clock of PipeWire.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MidiEvent {
+    pub offset: u32,
+    pub time: f64,
+    pub data: Vec<u8>,
+}
+
+// Check that `message` is one complete MIDI message (a status byte followed by its data bytes, or a SysEx).
+pub(crate) fn check_message(message: &[u8]) -> Result<(), String> {
+    let Some(&status) = message.first() else {
+        return Err(String::from("A MIDI message can't be empty"));
+    };
+
+    let length = match status {
+        0x00..=0x7f => return Err(format!("{:#04x} is not a status byte", status)),
+        0x80..=0xbf | 0xe0..=0xef | 0xf2 => Some(3),
+        0xc0..=0xdf | 0xf1 | 0xf3 => Some(2),
+        0xf6 | 0xf8..=0xff => Some(1),
+        0xf0 => None,
+        _ => return Err(format!("{:#04x} is not a valid status byte", status)),
+    };
+
+    let data = match length {
+        Some(length) if message.len() != length => {
+            return Err(format!(
+                "The message of status {:#04x} must have {} bytes, got {}",
+                status,
+                length,
+                message.len()
+            ))
+        }
+        Some(_) => &message[1..],
+        None if message.len() < 2 || message[message.len() - 1] != 0xf7 => {
+            return Err(String::from("A SysEx message must end with 0xf7"))
+        }
+        None => &message[1..message.len() - 1],
+    };
+
+    match data.iter().find(|byte| **byte >= 0x80) {
+        Some(byte) => Err(format!("{:#04x} is not a data byte", byte)),
+        None => Ok(()),
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+fn round_up(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+// The MIDI events of a sequence pod, the other controls are skipped.
+pub(crate) fn decode_sequence(bytes: &[u8]) -> Vec<MidiEvent> {
+    let mut events = Vec::new();
+
+    let (Some(size), Some(SPA_TYPE_SEQUENCE)) = (read_u32(bytes, 0), read_u32(bytes, 4)) else {
+        return events;
+    };
+    // the body starts with the unit and padding of the sequence
+    let end = (8 + size as usize).min(bytes.len());
+    let mut at = 16;
+
+    while at + 16 <= end {
+        let (Some(offset), Some(type_), Some(value_size), Some(value_type)) = (
+            read_u32(bytes, at),
+            read_u32(bytes, at + 4),
+            read_u32(bytes, at + 8),
+            read_u32(bytes, at + 12),
+        ) else {
+            break;
+        };
+        let Some(value) = bytes.get(at + 16..at + 16 + value_size as usize) else {
+            break;
+        };
+
+        if type_ == SPA_CONTROL_MIDI && value_type == SPA_TYPE_BYTES && !value.is_empty() {
+            events.push(MidiEvent {
+                offset,
+                time: 0.0,
+                data: value.to_vec(),
+            });
+        }
+
+        at += round_up(16 + value_size as usize);
+    }
+
+    events
+}
+
+// A sequence pod with the pending messages that fit in `capacity` bytes, all at the start of the cycle.
+pub(crate) fn encode_sequence(pending: &mut VecDeque<Vec<u8>>, capacity: usize) -> Vec<u8> {
+    let mut body = Vec::new();
+    // unit and padding
+    body.extend_from_slice(&[0; 8]);
+
+    while let Some(message) = pending.front() {
+        let size = round_up(16 + message.len());
+        if 8 + body.len() + size > capacity {
+            break;
+        }
+
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&SPA_CONTROL_MIDI.to_le_bytes());
+        body.extend_from_slice(&(message.len() as u32).to_le_bytes());
+        body.extend_from_slice(&SPA_TYPE_BYTES.to_le_bytes());
+        body.extend_from_slice(message);
+        body.resize(body.len() + size - 16 - message.len(), 0);
+
+        pending.pop_front();
+    }
+
+    let mut sequence = Vec::with_capacity(8 + body.len());
+    sequence.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    sequence.extend_from_slice(&SPA_TYPE_SEQUENCE.to_le_bytes());
+    sequence.extend_from_slice(&body);
+    sequence
+}
+
+struct MidiPortData {
+    id: u32,
+    ready: bool,
+    pending: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+// A Midi/Bridge node with one port, an output port sends the messages of JS and an input port
+// sends the events it receives to JS. The node is destroyed when this struct is dropped.
+pub(crate) struct MidiPort {
+    pending: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    _listener: StreamListener<MidiPortData>,
+    _stream: StreamRc,
+}
+
+impl MidiPort {
+    pub fn new(
+        core: &CoreRc,
+        id: u32,
+        name: &str,
+        output: bool,
+        sender: mpsc::Sender<MainOptions>,
+    ) -> Result<Self, pipewire::Error> {
+        let props = properties! {
+            "media.type" => "Midi",
+            "media.category" => if output { "Playback" } else { "Capture" },
+            "media.role" => "DSP",
+            "media.class" => "Midi/Bridge",
+            "format.dsp" => "8 bit raw midi",
+            "node.name" => format!("{}{}", NAME_PREFIX, name),
+            "node.nick" => name,
+        };
+
+        let stream = StreamRc::new(core.clone(), "node-pipewire-midi", props)?;
+        let pending = Rc::new(RefCell::new(VecDeque::new()));
+
+        let listener = stream
+            .add_local_listener_with_user_data(MidiPortData {
+                id,
+                ready: false,
+                pending: pending.clone(),
+                sender,
+            })
+            .state_changed(|stream, data, _, state| match state {
+                StreamState::Paused | StreamState::Streaming if !data.ready => {
+                    data.ready = true;
+                    data.sender
+                        .send(MainOptions::MidiPortReady {
+                            id: data.id,
+                            node_id: stream.node_id(),
+                        })
+                        .expect("ERROR: error at sending option to front");
+                }
+                StreamState::Error(_) => data
+                    .sender
+                    .send(MainOptions::MidiPortClosed { id: data.id })
+                    .expect("ERROR: error at sending option to front"),
+                _ => {}
+            })
+            .process(move |stream, data| {
+                let time = stream.time().ok();
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+
+                let datas = buffer.datas_mut();
+                if datas.is_empty() {
+                    return;
+                }
+
+                if output {
+                    let size = match datas[0].data() {
+                        Some(bytes) => {
+                            let sequence =
+                                encode_sequence(&mut data.pending.borrow_mut(), bytes.len());
+                            bytes[..sequence.len()].copy_from_slice(&sequence);
+                            sequence.len()
+                        }
+                        None => return,
+                    };
+
+                    let chunk = datas[0].chunk_mut();
+                    *chunk.offset_mut() = 0;
+                    *chunk.size_mut() = size as u32;
+                    *chunk.stride_mut() = 1;
+                    return;
+                }
+
+                let chunk_offset = datas[0].chunk().offset() as usize;
+                let chunk_size = datas[0].chunk().size() as usize;
+
+                let Some(bytes) = datas[0].data() else {
+                    return;
+                };
+                let start = chunk_offset.min(bytes.len());
+                let end = (start + chunk_size).min(bytes.len());
+
+                let mut events = decode_sequence(&bytes[start..end]);
+                if events.is_empty() {
+                    return;
+                }
+
+                // the offsets are samples from the start of the cycle
+                if let Some(time) = time {
+                    let rate = time.rate();
+                    let now_ms = time.now() as f64 / 1_000_000.0;
+                    let ms_per_sample = match rate.denom {
+                        0 => 0.0,
+                        denom => rate.num as f64 * 1000.0 / denom as f64,
+                    };
+                    for event in &mut events {
+                        event.time = now_ms + event.offset as f64 * ms_per_sample;
+                    }
+                }
+
+                data.sender
+                    .send(MainOptions::MidiEvents {
+                        id: data.id,
+                        events,
+                    })
+                    .expect("ERROR: error at sending option to front");
+            })
+            .register()?;
+
+        let format = object!(
+            SpaTypes::ObjectParamFormat,
+            ParamType::EnumFormat,
+            property!(FormatProperties::MediaType, Id, MediaType::Application),
+            property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Control),
+        );
+        let format: Vec<u8> =
+            PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(format))
+                .expect("ERROR: error at serializing MIDI format")
+                .0
+                .into_inner();
+        let mut params = [Pod::from_bytes(&format).expect("ERROR: error at reading MIDI format")];
+
+        // The port isn't linked automatically, like the ports of a bridge.
+        stream.connect(
+            if output {
+                Direction::Output
+            } else {
+                Direction::Input
+            },
+            None,
+            StreamFlags::MAP_BUFFERS,
+            &mut params,
+        )?;
+
+        Ok(Self {
+            pending,
+            _listener: listener,
+            _stream: stream,
+        })
+    }
+
+    // Queue a message, it's sent in the next cycle of the graph.
+    pub fn send(&self, message: Vec<u8>) {
+        let mut pending = self.pending.borrow_mut();
+        if pending.len() == MAX_PENDING {
+            pending.pop_front();
+        }
+        pending.push_back(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_the_messages() {
+        assert!(check_message(&[0x90, 60, 100]).is_ok());
+        assert!(check_message(&[0xc0, 5]).is_ok());
+        assert!(check_message(&[0xf8]).is_ok());
+        assert!(check_message(&[0xf0, 0x7e, 0x00, 0xf7]).is_ok());
+
+        assert!(check_message(&[]).is_err());
+        assert!(check_message(&[60, 100]).is_err());
+        assert!(check_message(&[0x90, 60]).is_err());
+        assert!(check_message(&[0x90, 60, 200]).is_err());
+        assert!(check_message(&[0xf4]).is_err());
+        assert!(check_message(&[0xf0, 0x7e]).is_err());
+        assert!(check_message(&[0xf0, 0x80, 0xf7]).is_err());
+    }
+
+    #[test]
+    fn encodes_and_decodes_sequences() {
+        let mut pending: VecDeque<Vec<u8>> = VecDeque::new();
+        pending.push_back(vec![0x90, 60, 100]);
+        pending.push_back(vec![0xf0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0xf7]);
+        pending.push_back(vec![0x80, 60, 0]);
+
+        // header, body header, 24 bytes for the first control and 32 for the second
+        let sequence = encode_sequence(&mut pending, 8 + 8 + 24 + 32);
+        assert_eq!(sequence.len(), 72);
+        assert_eq!(pending.len(), 1);
+
+        let events = decode_sequence(&sequence);
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| &event.data[..])
+                .collect::<Vec<_>>(),
+            vec![
+                &[0x90, 60, 100][..],
+                &[0xf0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0xf7][..]
+            ]
+        );
+        assert!(events.iter().all(|event| event.offset == 0));
+
+        // an empty sequence, and a truncated one
+        assert!(decode_sequence(&encode_sequence(&mut VecDeque::new(), 1024)).is_empty());
+        assert_eq!(decode_sequence(&sequence[..50]).len(), 1);
+        assert!(decode_sequence(&[]).is_empty());
+    }
+}
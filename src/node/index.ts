@@ -1,49 +1,491 @@
+import { EventEmitter } from "events";
+
 // Typescript definitions for node-pipewire
+// The values a field can take: an enumeration, or a range with an optional step.
+type FormatChoice<T> = { values: T[] } | { min: T; max: T; step?: T };
+
+interface PipewireFormatChoices {
+  sample_format?: FormatChoice<string>;
+  rate?: FormatChoice<number>;
+  channels?: FormatChoice<number>;
+  video_format?: FormatChoice<string>;
+  size?: FormatChoice<{ width: number; height: number }>;
+  framerate?: FormatChoice<number>;
+}
+
+interface PipewireFormat {
+  kind: "Audio" | "Video" | "Midi" | "";
+  media_type: string;
+  media_subtype: string;
+  sample_format?: string;
+  rate?: number;
+  channels?: number;
+  video_format?: string;
+  width?: number;
+  height?: number;
+  framerate?: number;
+  // Only in the supported formats, for the fields that are a choice (the fields above hold the default).
+  choices?: PipewireFormatChoices;
+}
+
+interface PipewireLatency {
+  direction: "Input" | "Output" | "";
+  min_quantum: number;
+  max_quantum: number;
+  min_rate: number;
+  max_rate: number;
+  min_ns: number;
+  max_ns: number;
+}
+
+interface PipewireProcessLatency {
+  quantum: number;
+  rate: number;
+  ns: number;
+}
+
+interface PathLatency {
+  nodes: number[];
+  links: number[];
+  min_ns: number;
+  max_ns: number;
+}
+
+// Permissions of this connection on an object, `permissions` holds the raw bits.
+interface PermissionFlags {
+  read: boolean;
+  write: boolean;
+  execute: boolean;
+  metadata: boolean;
+  link: boolean;
+}
+
 interface PipewirePort {
   id: number;
   permissions: number;
+  permission_flags: PermissionFlags;
   props: Record<string, string>;
   node_id: number;
   name: string;
   direction: string;
+  format: PipewireFormat | null;
+  formats: PipewireFormat[];
+  latency: PipewireLatency[];
+  process_latency: PipewireProcessLatency | null;
 }
 
+type NodeCategory = "device" | "stream" | "virtual" | "bridge" | "filter" | "";
+
+type NodeKind = "sink" | "source" | "duplex" | "playback" | "capture" | "";
+
 interface PipewireNode {
   id: number;
   permissions: number;
+  permission_flags: PermissionFlags;
   props: Record<string, string>;
   name: string;
-  node_direction: string;
+  node_direction: "Input" | "Output" | "Duplex" | "";
   node_type: string;
+  category: NodeCategory;
+  kind: NodeKind;
+  is_monitor: boolean;
   ports: PipewirePort[];
+  quantum: number | null;
+  rate: number | null;
+  process_latency: PipewireProcessLatency | null;
+  stats: PipewireNodeStats | null;
 }
 
 interface PipewireLink {
   id: number;
   permissions: number;
+  permission_flags: PermissionFlags;
   props: Record<string, string>;
   input_node_id: number;
   input_port_id: number;
   output_node_id: number;
   output_port_id: number;
+  format: PipewireFormat | null;
 }
 
 interface PipewireClient {
   id: number;
   permissions: number;
-  pid: number;
-  application_name: string;
+  permission_flags: PermissionFlags;
+  pid: number | null;
+  application_name: string | null;
   props: Record<string, string>;
+  process_binary: string | null;
+  process_user: string | null;
+  access: string | null;
+  is_flatpak: boolean;
+  app_id: string | null;
+  icon_name: string | null;
+}
+
+// Permissions of a client on a global, the id 0xffffffff holds the default permissions of the globals without their own.
+interface ClientPermission {
+  id: number;
+  r: boolean;
+  w: boolean;
+  x: boolean;
+  m: boolean;
+  l: boolean;
+}
+
+interface MeterLevels {
+  // Per-channel levels in dBFS (-Infinity for silence)
+  peak: number[];
+  rms: number[];
 }
 
+interface PipewireNodeStats {
+  id: number;
+  name: string;
+  driver_id: number;
+  quantum: number;
+  rate: number;
+  cycles: number;
+  wait_ns: number;
+  max_wait_ns: number;
+  busy_ns: number;
+  max_busy_ns: number;
+  dsp_load: number;
+  xruns: number;
+}
+
+interface PipewireDriverStats {
+  id: number;
+  name: string;
+  quantum: number;
+  rate: number;
+  cycles: number;
+  period_ns: number;
+  cpu_load: number[];
+  xruns: number;
+  nodes: PipewireNodeStats[];
+}
+
+interface ProfilerOptions {
+  intervalMs?: number;
+}
+
+interface LatencyOffset {
+  ns?: number;
+  samples?: number;
+  quantum?: number;
+}
+
+interface PipewireMetadataProperty {
+  subject: number;
+  key: string;
+  type: string | null;
+  value: string;
+}
+
+interface PipewireMetadata {
+  id: number;
+  permissions: number;
+  permission_flags: PermissionFlags;
+  props: Record<string, string>;
+  name: string;
+  properties: PipewireMetadataProperty[];
+}
+
+// A device (sound card, camera, Bluetooth headset...), its nodes have its id in their `device.id` prop.
+interface PipewireDevice {
+  id: number;
+  permissions: number;
+  permission_flags: PermissionFlags;
+  props: Record<string, string>;
+  name: string;
+}
+
+// A null key means all the properties of the subject were removed, a null value that the key was removed.
+interface MetadataChange {
+  subject: number;
+  key: string | null;
+  type: string | null;
+  value: string | null;
+}
+
+// Clock of the graph. `rate` and `quantum` are the defaults of the graph, a forced value of 0 means it isn't forced.
+interface ClockSettings {
+  rate: number | null;
+  allowedRates: number[];
+  quantum: number | null;
+  minQuantum: number | null;
+  maxQuantum: number | null;
+  forceRate: number | null;
+  forceQuantum: number | null;
+}
+
+type ClockSettingsUpdate = Partial<
+  Pick<ClockSettings, "forceRate" | "forceQuantum" | "minQuantum" | "maxQuantum" | "allowedRates">
+>;
+
+interface MeterOptions {
+  intervalMs?: number;
+}
+
+interface AnalyzeOptions {
+  fftSize?: number;
+  bands?: number;
+  intervalMs?: number;
+}
+
+interface ActivityOptions {
+  thresholdDb?: number;
+  holdMs?: number;
+}
+
+// An Input port receives MIDI from the graph, an Output port sends the messages of `send`.
+interface MidiPortOptions {
+  name: string;
+  direction: "Input" | "Output";
+}
+
+// `time` is in ms on the monotonic clock of PipeWire, `offset` is the sample of the cycle the event belongs to.
+interface MidiEvent {
+  time: number;
+  offset: number;
+  data: Buffer;
+}
+
+// Raw video formats of SPA. YUY2, UYVY and YVYU need an even width, I420 and NV12 an even width and height.
+type VideoFormat =
+  | "RGBA" | "BGRA" | "ARGB" | "ABGR" | "RGBx" | "BGRx" | "xRGB" | "xBGR" | "RGB" | "BGR" | "GRAY8"
+  | "YUY2" | "UYVY" | "YVYU" | "I420" | "NV12";
+
+// The format and the size of the node are used when they are not set, width and height go together.
+interface VideoCaptureOptions {
+  format?: VideoFormat;
+  width?: number;
+  height?: number;
+}
+
+interface VirtualCameraOptions {
+  name: string;
+  format: VideoFormat;
+  width: number;
+  height: number;
+}
+
+// `data` has the planes of the frame one after the other, `stride` is the number of bytes of a row of the first one.
+interface VideoFrame {
+  data: Buffer;
+  stride: number;
+  format: VideoFormat;
+  width: number;
+  height: number;
+}
+
+// The props are set on the capture and playback nodes of the loopback, `latencyMs` is the delay it adds.
+interface LoopbackOptions {
+  captureProps?: Record<string, string | number | boolean>;
+  playbackProps?: Record<string, string | number | boolean>;
+  latencyMs?: number;
+  channels?: number;
+}
+
+// Every field must match, a list matches any of its values.
+interface NodeFilter {
+  category?: NodeCategory | NodeCategory[];
+  kind?: NodeKind | NodeKind[];
+  type?: string | string[];
+  isMonitor?: boolean;
+}
+
+type QueryResult =
+  | (PipewireNode & { type: "node" })
+  | (PipewirePort & { type: "port" })
+  | (PipewireLink & { type: "link" })
+  | (PipewireClient & { type: "client" })
+  | (PipewireMetadata & { type: "metadata" })
+  | (PipewireDevice & { type: "device" });
+
+type ObjectType = QueryResult["type"];
+
+// Every field must match, a list matches any of its values.
+// `mediaClass` is a prefix of the media.class of the node, of the node of a port, or of a node at either end of a link,
+// `nodeIds` the ids of these nodes and `selector` a selector of `query()`.
+interface EventFilter {
+  type?: ObjectType | ObjectType[];
+  mediaClass?: string | string[];
+  selector?: string;
+  nodeIds?: number[];
+}
+
+interface EventOptions {
+  coalesceMs?: number;
+}
+
+// `object` is the object after the change, or before its removal.
+type GraphEvent = {
+  change: "added" | "changed" | "removed";
+  id: number;
+} & (
+  | { type: "node"; object: PipewireNode }
+  | { type: "port"; object: PipewirePort }
+  | { type: "link"; object: PipewireLink }
+  | { type: "client"; object: PipewireClient }
+  | { type: "metadata"; object: PipewireMetadata }
+  | { type: "device"; object: PipewireDevice }
+);
+
 type NodeDirection = "Input" | "Output" | "Both";
 
-// Surround is not yet implemented in the library
-type AudioPosition = "FL" | "FR";
+// `includeLingering` also destroys the objects created permanent, `timeout` (ms, 1000 by default) bounds the wait for the server.
+interface CleanupOptions {
+  includeLingering?: boolean;
+  timeout?: number;
+}
+
+// Channel positions of SPA, AUX0 to AUX63 are accepted too.
+type AudioPosition =
+  | "MONO" | "FL" | "FR" | "FC" | "LFE" | "SL" | "SR" | "FLC" | "FRC" | "RC" | "RL" | "RR"
+  | "TC" | "TFL" | "TFC" | "TFR" | "TRL" | "TRC" | "TRR" | "RLC" | "RRC" | "FLW" | "FRW" | "LFE2"
+  | "FLH" | "FCH" | "FRH" | "TFLC" | "TFRC" | "TSL" | "TSR" | "LLFE" | "RLFE" | "BC" | "BLC" | "BRC"
+  | `AUX${number}`;
+
+// Properties of a virtual device, checked before it's created. The node.name is `node-pipewire:` followed by
+// `nodeName` (or the name of the device), without the prefix if `prefix` is false.
+interface DeviceOptions {
+  nodeName?: string;
+  prefix?: boolean;
+  description?: string;
+  rate?: number;
+  format?: string;
+  channelVolumes?: boolean;
+  // node.latency as "quantum/rate", e.g. "256/48000"
+  latency?: string;
+  priority?: number;
+  iconName?: string;
+  // Extra properties, they can't override the ones set by the options.
+  props?: Record<string, string | number | boolean>;
+}
+
+// A sink has input ports and monitor output ports (unless `monitor` is false), a source is fed through its input
+// ports, a duplex device has input and output ports.
+interface VirtualDeviceOptions extends DeviceOptions {
+  kind: "sink" | "source" | "duplex";
+  name: string;
+  // FL and FR by default
+  audioPositions?: AudioPosition[];
+  permanent?: boolean;
+  monitor?: boolean;
+}
 
 // eslint-disable-next-line @typescript-eslint/no-var-requires
 const library = require("./binding/napi-v6/index.node");
 
+// A capture stream running in the pipewire thread. Emits "close" when the stream is torn down.
+export class Capture extends EventEmitter {
+  readonly id: number;
+
+  constructor(start: (callback: (event: string, payload: unknown) => void) => number) {
+    super();
+    this.id = start((event, payload) => this.emit(event, payload));
+  }
+
+  stop() {
+    library.stopCapture(this.id);
+  }
+}
+
+// Handle of an object created by this connection. Its proxy is released when the handle is released or
+// garbage collected, which destroys the object unless it was created permanent.
+export class ObjectHandle {
+  readonly id: number;
+  private readonly handle: unknown;
+
+  constructor(id: number) {
+    this.id = id;
+    this.handle = library.takeObjectHandle(id);
+  }
+
+  release() {
+    library.releaseObjectHandle(this.handle);
+  }
+}
+
+// A Midi/Bridge node with one port. Emits "ready" with the id of the node, "midi" with a `MidiEvent` for
+// every message received by an Input port, and "close" when the server closes it.
+export class MidiPort extends EventEmitter {
+  readonly id: number;
+
+  constructor(options: MidiPortOptions) {
+    super();
+    this.id = library.createMidiPort(options.name, options.direction, (event: string, payload: unknown) =>
+      this.emit(event, payload),
+    );
+  }
+
+  // Sends one MIDI message (status and data bytes, or a SysEx) in the next cycle of the graph, throws on an Input port.
+  send(message: number[] | Uint8Array) {
+    library.sendMidi(this.id, message instanceof Uint8Array ? Buffer.from(message) : message);
+  }
+
+  close() {
+    library.closeMidiPort(this.id);
+  }
+}
+
+// A stream receiving the raw frames of a video node. Emits "ready" with the id of its node, "frame" with a
+// `VideoFrame` for every frame, and "close" when the stream is torn down. Frames are dropped while JS is busy.
+export class VideoCapture extends EventEmitter {
+  readonly id: number;
+
+  constructor(nodeId: number, options?: VideoCaptureOptions) {
+    super();
+    this.id = library.createVideoCapture(nodeId, options ?? {}, (event: string, payload: unknown) =>
+      this.emit(event, payload),
+    );
+  }
+
+  close() {
+    library.closeVideoStream(this.id);
+  }
+}
+
+// A Video/Source node publishing the frames of `send`. Emits "ready" with the id of the node and "close" when
+// the server closes it.
+export class VirtualCamera extends EventEmitter {
+  readonly id: number;
+
+  constructor(options: VirtualCameraOptions) {
+    super();
+    this.id = library.createVirtualCamera(options, (event: string, payload: unknown) => this.emit(event, payload));
+  }
+
+  // Publishes a tightly packed frame (stride of width * bytes per pixel) in the format and size of the camera.
+  send(frame: Uint8Array) {
+    library.sendVideoFrame(this.id, Buffer.isBuffer(frame) ? frame : Buffer.from(frame));
+  }
+
+  close() {
+    library.closeVideoStream(this.id);
+  }
+}
+
+// A libpipewire-module-loopback loaded by this connection, with the ids of its nodes.
+export class Loopback {
+  readonly id: number;
+  readonly captureId: number;
+  readonly playbackId: number;
+
+  constructor(id: number, nodes: { captureId: number; playbackId: number }) {
+    this.id = id;
+    this.captureId = nodes.captureId;
+    this.playbackId = nodes.playbackId;
+  }
+
+  // Unloads the module, which destroys both nodes.
+  destroy() {
+    library.destroyLoopback(this.id);
+  }
+}
+
 export function createPwThread(enableDebug?: boolean) {
   library.createPwThread(enableDebug ?? false);
 }
@@ -73,14 +515,153 @@ export function getClients(): PipewireClient[] {
   return temp.filter(client => client.id);
 }
 
-export function getOutputNodes(): PipewireNode[] {
+// Returns the nodes, ports and links created by the client, with the ports of its nodes.
+export function getObjectsOfClient(clientId: number): QueryResult[] {
+  return library.getObjectsOfClient(clientId);
+}
+
+// Returns the objects created by this connection (links, virtual devices and their ports).
+export function getOwnedObjects(): QueryResult[] {
+  return library.getOwnedObjects();
+}
+
+// Throws if the object was not created by this connection.
+export function takeObjectHandle(id: number): ObjectHandle {
+  return new ObjectHandle(id);
+}
+
+// Destroys the owned links and nodes, waiting for the server. Returns false if it didn't answer in time.
+export function cleanupOwnedObjects(options?: CleanupOptions): boolean {
+  return library.cleanupOwnedObjects(options?.includeLingering ?? false, options?.timeout ?? 1000);
+}
+
+const exitSignals: NodeJS.Signals[] = ["SIGINT", "SIGTERM", "SIGHUP"];
+
+// Cleans up the owned objects when the process exits, or is interrupted by a signal (raised again afterwards
+// unless the application listens it). Returns a function removing the hook.
+export function onExit(options?: CleanupOptions): () => void {
+  let cleanedUp = false;
+
+  const cleanup = () => {
+    if (!cleanedUp) {
+      cleanedUp = true;
+      cleanupOwnedObjects(options);
+    }
+  };
+
+  const onSignal = (signal: NodeJS.Signals) => {
+    cleanup();
+    remove();
+    if (process.listenerCount(signal) === 0) {
+      process.kill(process.pid, signal);
+    }
+  };
+
+  const remove = () => {
+    process.removeListener("exit", cleanup);
+    for (const signal of exitSignals) {
+      process.removeListener(signal, onSignal);
+    }
+  };
+
+  process.on("exit", cleanup);
+  for (const signal of exitSignals) {
+    process.on(signal, onSignal);
+  }
+
+  return remove;
+}
+
+// Rejects if the client disappears before answering, or if the server refuses the request.
+export function getClientPermissions(clientId: number): Promise<ClientPermission[]> {
+  return new Promise((resolve, reject) => {
+    library.getClientPermissions(clientId, (event: string, payload: unknown) => {
+      if (event === "permissions") {
+        resolve(payload as ClientPermission[]);
+      } else if (event === "error") {
+        reject(new Error(payload as string));
+      } else if (event === "close") {
+        reject(new Error("The client was removed"));
+      }
+    });
+  });
+}
+
+// Missing flags are not granted, e.g. `{ id: micId }` hides the node `micId` from the client.
+// Resolves once the server applied the permissions, rejects like `getClientPermissions()`.
+export function updateClientPermissions(clientId: number, permissions: Partial<ClientPermission>[]): Promise<void> {
+  return new Promise((resolve, reject) => {
+    library.updateClientPermissions(clientId, permissions, (event: string, payload: unknown) => {
+      if (event === "updated") {
+        resolve();
+      } else if (event === "error") {
+        reject(new Error(payload as string));
+      } else if (event === "close") {
+        reject(new Error("The client was removed"));
+      }
+    });
+  });
+}
+
+// Must be called with true before `disconnectClient()`.
+export function allowClientDisconnect(allow: boolean) {
+  library.allowClientDisconnect(allow);
+}
+
+// Resolves with whether the server allowed to disconnect the client.
+export function disconnectClient(clientId: number): Promise<boolean> {
+  return new Promise(resolve => {
+    library.disconnectClient(clientId, (event: string, payload: unknown) => {
+      if (event === "disconnected") {
+        resolve(payload as boolean);
+      }
+    });
+  });
+}
+
+// The first call starts the profiler and resolves once its first interval ended, the stats are then updated every second.
+export function getNodeStats(): Promise<PipewireNodeStats[]> {
+  return new Promise(resolve => {
+    const stats: PipewireNodeStats[] | undefined = library.getNodeStats((event: string, payload: unknown) => {
+      if (event === "stats") {
+        resolve(payload as PipewireNodeStats[]);
+      }
+    });
+    if (stats !== undefined) {
+      resolve(stats);
+    }
+  });
+}
+
+// Returns the objects matching `selector`, e.g. `client[application.name=Firefox] > node > port[direction=Output]`.
+export function query(selector: string): QueryResult[] {
+  return library.query(selector);
+}
+
+function matchesFilter(node: PipewireNode, filter?: NodeFilter): boolean {
+  const matches = <T>(value: T, expected?: T | T[]) =>
+    expected === undefined || (Array.isArray(expected) ? expected.includes(value) : expected === value);
+
+  return (
+    matches(node.category, filter?.category) &&
+    matches(node.kind, filter?.kind) &&
+    matches(node.node_type, filter?.type) &&
+    matches(node.is_monitor, filter?.isMonitor)
+  );
+}
+
+export function getOutputNodes(filter?: NodeFilter): PipewireNode[] {
   const temp: PipewireNode[] = library.getOutputNodes();
-  return temp.filter(output => output.id);
+  return temp.filter(output => output.id && matchesFilter(output, filter));
+}
+
+export function getInputNodes(filter?: NodeFilter): PipewireNode[] {
+  const temp: PipewireNode[] = library.getInputNodes();
+  return temp.filter(input => input.id && matchesFilter(input, filter));
 }
 
-export function getInputNodes(): PipewireNode[] {
-  const temp = library.getInputNodes();
-  return temp.filter(input => input.id);
+export function getPathLatency(fromNodeId: number, toNodeId: number): PathLatency | null {
+  return library.getPathLatency(fromNodeId, toNodeId);
 }
 
 export function linkNodesNameToId(nodeName: string, nodeId: number, permanent = true) {
@@ -111,20 +692,191 @@ export function waitForNewNode(nodeName: string, direction?: NodeDirection, time
   return library.waitForNewNode(nodeName, direction ?? "Both", timeout ?? 5000);
 }
 
-export function createSource(newSourceName: string, audioPositions: AudioPosition[], permanent = false) {
+export function createSource(
+  newSourceName: string,
+  audioPositions: AudioPosition[],
+  permanent = false,
+  options?: DeviceOptions
+) {
   if (audioPositions.length == 0) {
     throw new Error("Cannot create a source with no audio positions");
   }
-  return library.createSource(newSourceName, audioPositions.join(","), audioPositions.length, permanent);
+  return library.createSource(newSourceName, audioPositions, permanent, options);
 }
 
-export function createSink(newSinkName: string, audioPositions: AudioPosition[], permanent = false) {
+export function createSink(
+  newSinkName: string,
+  audioPositions: AudioPosition[],
+  permanent = false,
+  options?: DeviceOptions
+) {
   if (audioPositions.length == 0) {
     throw new Error("Cannot create a sink with no audio positions");
   }
-  return library.createSink(newSinkName, audioPositions.join(","), audioPositions.length, permanent);
+  return library.createSink(newSinkName, audioPositions, permanent, options);
+}
+
+// Returns the node.name of the device.
+export function createVirtualDevice(options: VirtualDeviceOptions): string {
+  return library.createVirtualDevice(options);
 }
 
 export function destroyObject(id: number) {
   return library.destroyObject(id);
-}
\ No newline at end of file
+}
+
+// Whether `destroyObject(id)` would destroy the object. Links of other clients are accepted too, objects need
+// to be owned (see `getOwnedObjects()`) otherwise.
+export function canDestroy(id: number): boolean {
+  return library.canDestroy(id);
+}
+
+// Whether the server would accept a link from or to the port.
+export function canLink(portId: number): boolean {
+  return library.canLink(portId);
+}
+
+// The values are added together, missing values are 0.
+// Ports can't be written, for a port id the latency of its node is set. Returns the id of the node.
+export function setLatencyOffset(id: number, offset: LatencyOffset): number {
+  return library.setLatencyOffset(id, offset.quantum ?? 0, offset.samples ?? 0, offset.ns ?? 0);
+}
+
+// Returns null until the settings metadata is known.
+export function getSettings(): ClockSettings | null {
+  return library.getSettings();
+}
+
+// Missing (or null) values are left unchanged, so the result of `getSettings()` can be passed to restore the settings.
+export function setSettings(settings: ClockSettingsUpdate) {
+  const update: ClockSettingsUpdate = {};
+  for (const key of Object.keys(settings) as (keyof ClockSettingsUpdate)[]) {
+    if (settings[key] !== null && settings[key] !== undefined) {
+      Object.assign(update, { [key]: settings[key] });
+    }
+  }
+  library.setSettings(update);
+}
+
+// Calls `callback` with the new settings every time a setting changes, returns a function to unsubscribe.
+export function subscribeSettings(callback: (settings: ClockSettings) => void): () => void {
+  return subscribeMetadata("settings", () => {
+    const settings = getSettings();
+    if (settings !== null) {
+      callback(settings);
+    }
+  });
+}
+
+// Returns null if there is no metadata named `name`.
+export function getMetadata(name: string): PipewireMetadata | null {
+  return library.getMetadata(name);
+}
+
+// A null value removes the key.
+export function setMetadata(name: string, subject: number, key: string, value: string | null, type?: string) {
+  library.setMetadata(name, subject, key, value, type ?? null);
+}
+
+// Removes the properties of `subject`, or all the properties with `{ all: true }`.
+export function clearMetadata(name: string, subject: number | { all: true }) {
+  library.clearMetadata(name, subject);
+}
+
+// Calls `callback` on every change of the metadata named `name`, returns a function to unsubscribe.
+export function subscribeMetadata(name: string, callback: (change: MetadataChange) => void): () => void {
+  const id: number = library.subscribeMetadata(name, (event: string, payload: unknown) => {
+    if (event === "change") {
+      callback(payload as MetadataChange);
+    }
+  });
+  return () => library.unsubscribeMetadata(id);
+}
+
+// Returns "metadata" when the move was left to the session manager, "links" when the links were replaced directly.
+export function moveStream(streamNodeId: number, targetNodeId: number): "metadata" | "links" {
+  return library.moveStream(streamNodeId, targetNodeId);
+}
+
+// Calls `callback` with the changes of the objects matching `filter`. The changes happening within `coalesceMs` (default 0)
+// of the first one are sent as one batch. Returns a function to unsubscribe.
+export function subscribeEvents(
+  callback: (events: GraphEvent[]) => void,
+  filter?: EventFilter,
+  options?: EventOptions,
+): () => void {
+  const list = <T>(value?: T | T[]) => (value === undefined ? undefined : ([] as T[]).concat(value));
+
+  const id: number = library.subscribeEvents(
+    {
+      types: list(filter?.type),
+      mediaClasses: list(filter?.mediaClass),
+      selector: filter?.selector,
+      nodeIds: filter?.nodeIds,
+    },
+    options?.coalesceMs ?? 0,
+    (event: string, payload: unknown) => {
+      if (event === "events") {
+        callback(payload as GraphEvent[]);
+      }
+    },
+  );
+  return () => library.unsubscribeEvents(id);
+}
+
+// Emits "levels" with a `MeterLevels` object every `intervalMs`.
+export function meter(id: number, options?: MeterOptions): Capture {
+  return new Capture(callback => library.meter(id, options?.intervalMs ?? 50, callback));
+}
+
+// Emits "bands" with a Float32Array holding the magnitude in dBFS of `bands` logarithmically spaced frequency bands.
+export function analyze(nodeId: number, options?: AnalyzeOptions): Capture {
+  return new Capture(callback =>
+    library.analyze(nodeId, options?.fftSize ?? 2048, options?.bands ?? 32, options?.intervalMs ?? 50, callback),
+  );
+}
+
+export function createMidiPort(options: MidiPortOptions): MidiPort {
+  return new MidiPort(options);
+}
+
+export function createVideoCapture(nodeId: number, options?: VideoCaptureOptions): VideoCapture {
+  return new VideoCapture(nodeId, options);
+}
+
+export function createVirtualCamera(options: VirtualCameraOptions): VirtualCamera {
+  return new VirtualCamera(options);
+}
+
+// Rejects if the module could not be loaded or if its nodes were not created within 5 seconds.
+export function createLoopback(options?: LoopbackOptions): Promise<Loopback> {
+  return new Promise((resolve, reject) => {
+    const id: number = library.createLoopback(options ?? {}, (event: string, payload: unknown) => {
+      if (event === "loaded") {
+        resolve(new Loopback(id, payload as { captureId: number; playbackId: number }));
+      } else if (event === "error") {
+        reject(new Error(payload as string));
+      }
+    });
+  });
+}
+
+// Emits "active" and "silent" (with the `level` in dBFS) when the signal of the node crosses `thresholdDb`.
+export function detectActivity(nodeId: number, options?: ActivityOptions): Capture {
+  return new Capture(callback =>
+    library.detectActivity(nodeId, options?.thresholdDb ?? -50, options?.holdMs ?? 500, callback),
+  );
+}
+
+// Calls `callback` every `intervalMs` (default 1000) with the stats of every driver, returns a function to unsubscribe.
+export function subscribeProfiler(
+  callback: (drivers: PipewireDriverStats[]) => void,
+  options?: ProfilerOptions,
+): () => void {
+  const id: number = library.subscribeProfiler(options?.intervalMs ?? 1000, (event: string, payload: unknown) => {
+    if (event === "stats") {
+      callback(payload as PipewireDriverStats[]);
+    }
+  });
+  return () => library.unsubscribeProfiler(id);
+}
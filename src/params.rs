@@ -0,0 +1,303 @@
+use crate::{FormatChoice, PipewireFormat, PipewireLatency, PipewireProcessLatency};
+use pipewire::spa::{
+    param::{
+        audio::AudioFormat,
+        format::{FormatProperties, MediaSubtype, MediaType},
+        video::VideoFormat,
+        ParamType,
+    },
+    pod::{
+        deserialize::PodDeserializer, serialize::PodSerializer, CanonicalFixedSizedPod,
+        ChoiceValue, Object, Pod, Property, Value,
+    },
+    sys,
+    utils::{Choice, ChoiceEnum, Fraction, Id, Rectangle, SpaTypes},
+};
+
+// Parse a pod into its properties, only objects are supported.
+fn parse_object(pod: &Pod) -> Option<Vec<(u32, Value)>> {
+    match PodDeserializer::deserialize_any_from(pod.as_bytes()) {
+        Ok((_, Value::Object(object))) => Some(
+            object
+                .properties
+                .into_iter()
+                .map(|property| (property.key, property.value))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+// For choices (used in EnumFormat) the default value is used.
+fn choice_default<T: CanonicalFixedSizedPod + Copy>(choice: &Choice<T>) -> T {
+    match &choice.1 {
+        ChoiceEnum::None(value) => *value,
+        ChoiceEnum::Range { default, .. }
+        | ChoiceEnum::Step { default, .. }
+        | ChoiceEnum::Enum { default, .. }
+        | ChoiceEnum::Flags { default, .. } => *default,
+    }
+}
+
+// The values of a choice, None if it isn't one (a fixed value or flags).
+fn choice_values<T: CanonicalFixedSizedPod + Copy, U: PartialEq>(
+    choice: &Choice<T>,
+    f: impl Fn(T) -> U,
+) -> Option<FormatChoice<U>> {
+    match &choice.1 {
+        ChoiceEnum::None(_) | ChoiceEnum::Flags { .. } => None,
+        ChoiceEnum::Range { min, max, .. } => Some(FormatChoice::Range {
+            min: f(*min),
+            max: f(*max),
+            step: None,
+        }),
+        ChoiceEnum::Step { min, max, step, .. } => Some(FormatChoice::Range {
+            min: f(*min),
+            max: f(*max),
+            step: Some(f(*step)),
+        }),
+        // the alternatives usually repeat the default
+        ChoiceEnum::Enum {
+            default,
+            alternatives,
+        } => {
+            let mut values: Vec<U> = Vec::new();
+            for value in std::iter::once(default).chain(alternatives) {
+                let value = f(*value);
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            Some(FormatChoice::Values(values))
+        }
+    }
+}
+
+fn id_choice(value: &Value) -> Option<FormatChoice<u32>> {
+    match value {
+        Value::Choice(ChoiceValue::Id(choice)) => choice_values(choice, |id| id.0),
+        _ => None,
+    }
+}
+
+fn int_choice(value: &Value) -> Option<FormatChoice<u32>> {
+    match value {
+        Value::Choice(ChoiceValue::Int(choice)) => choice_values(choice, |int| int.max(0) as u32),
+        _ => None,
+    }
+}
+
+fn rectangle_choice(value: &Value) -> Option<FormatChoice<(u32, u32)>> {
+    match value {
+        Value::Choice(ChoiceValue::Rectangle(choice)) => {
+            choice_values(choice, |size| (size.width, size.height))
+        }
+        _ => None,
+    }
+}
+
+fn fraction_choice(value: &Value) -> Option<FormatChoice<f64>> {
+    match value {
+        Value::Choice(ChoiceValue::Fraction(choice)) => choice_values(choice, fraction_value),
+        _ => None,
+    }
+}
+
+fn fraction_value(fraction: Fraction) -> f64 {
+    match fraction.denom {
+        0 => 0.0,
+        denom => fraction.num as f64 / denom as f64,
+    }
+}
+
+fn value_as_id(value: &Value) -> Option<u32> {
+    match value {
+        Value::Id(Id(id)) => Some(*id),
+        Value::Choice(ChoiceValue::Id(choice)) => Some(choice_default(choice).0),
+        _ => None,
+    }
+}
+
+fn value_as_int(value: &Value) -> Option<i32> {
+    match value {
+        Value::Int(int) => Some(*int),
+        Value::Choice(ChoiceValue::Int(choice)) => Some(choice_default(choice)),
+        _ => None,
+    }
+}
+
+fn value_as_long(value: &Value) -> Option<i64> {
+    match value {
+        Value::Long(long) => Some(*long),
+        Value::Choice(ChoiceValue::Long(choice)) => Some(choice_default(choice)),
+        _ => None,
+    }
+}
+
+fn value_as_float(value: &Value) -> Option<f32> {
+    match value {
+        Value::Float(float) => Some(*float),
+        Value::Choice(ChoiceValue::Float(choice)) => Some(choice_default(choice)),
+        _ => None,
+    }
+}
+
+fn value_as_rectangle(value: &Value) -> Option<Rectangle> {
+    match value {
+        Value::Rectangle(rectangle) => Some(*rectangle),
+        Value::Choice(ChoiceValue::Rectangle(choice)) => Some(choice_default(choice)),
+        _ => None,
+    }
+}
+
+fn value_as_fraction(value: &Value) -> Option<Fraction> {
+    match value {
+        Value::Fraction(fraction) => Some(*fraction),
+        Value::Choice(ChoiceValue::Fraction(choice)) => Some(choice_default(choice)),
+        _ => None,
+    }
+}
+
+// Remove the type prefix of the debug representation ("MediaType::Audio" -> "Audio").
+fn debug_name(value: impl std::fmt::Debug) -> String {
+    let name = format!("{:?}", value);
+    match name.split_once("::") {
+        Some((_, name)) => name.to_string(),
+        None => name,
+    }
+}
+
+// Parse a Format or EnumFormat param.
+pub(crate) fn parse_format(pod: &Pod) -> Option<PipewireFormat> {
+    let properties = parse_object(pod)?;
+    let mut format = PipewireFormat::default();
+
+    let mut media_type = MediaType::Unknown;
+    let mut media_subtype = MediaSubtype::Unknown;
+
+    for (key, value) in properties.iter() {
+        let key = *key;
+        if key == FormatProperties::MediaType.0 {
+            media_type = MediaType::from_raw(value_as_id(value)?);
+        } else if key == FormatProperties::MediaSubtype.0 {
+            media_subtype = MediaSubtype::from_raw(value_as_id(value)?);
+        } else if key == FormatProperties::AudioFormat.0 {
+            format.sample_format = value_as_id(value).map(|id| debug_name(AudioFormat(id)));
+            format.choices.sample_format =
+                id_choice(value).map(|choice| choice.map(|id| debug_name(AudioFormat(id))));
+        } else if key == FormatProperties::AudioRate.0 {
+            format.rate = value_as_int(value).map(|rate| rate as u32);
+            format.choices.rate = int_choice(value);
+        } else if key == FormatProperties::AudioChannels.0 {
+            format.channels = value_as_int(value).map(|channels| channels as u32);
+            format.choices.channels = int_choice(value);
+        } else if key == FormatProperties::VideoFormat.0 {
+            format.video_format = value_as_id(value).map(|id| debug_name(VideoFormat(id)));
+            format.choices.video_format =
+                id_choice(value).map(|choice| choice.map(|id| debug_name(VideoFormat(id))));
+        } else if key == FormatProperties::VideoSize.0 {
+            if let Some(size) = value_as_rectangle(value) {
+                format.width = Some(size.width);
+                format.height = Some(size.height);
+            }
+            format.choices.size = rectangle_choice(value);
+        } else if key == FormatProperties::VideoFramerate.0 {
+            format.framerate = value_as_fraction(value)
+                .filter(|framerate| framerate.denom != 0)
+                .map(fraction_value);
+            format.choices.framerate = fraction_choice(value);
+        }
+    }
+
+    format.media_type = debug_name(media_type);
+    format.media_subtype = debug_name(media_subtype);
+
+    // MIDI is carried in control sequences (application/control).
+    format.kind = if media_type == MediaType::Audio {
+        "Audio"
+    } else if media_type == MediaType::Video {
+        "Video"
+    } else if media_type == MediaType::Application && media_subtype == MediaSubtype::Control {
+        "Midi"
+    } else {
+        ""
+    }
+    .to_string();
+
+    Some(format)
+}
+
+// Parse a Latency param, the latency of the graph up to the port in one direction.
+pub(crate) fn parse_latency(pod: &Pod) -> Option<PipewireLatency> {
+    let properties = parse_object(pod)?;
+    let mut latency = PipewireLatency::default();
+
+    for (key, value) in properties.iter() {
+        let key = *key;
+        if key == sys::SPA_PARAM_LATENCY_direction {
+            latency.direction = match value_as_id(value)? {
+                sys::SPA_DIRECTION_INPUT => "Input",
+                sys::SPA_DIRECTION_OUTPUT => "Output",
+                _ => "",
+            }
+            .to_string();
+        } else if key == sys::SPA_PARAM_LATENCY_minQuantum {
+            latency.min_quantum = value_as_float(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_LATENCY_maxQuantum {
+            latency.max_quantum = value_as_float(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_LATENCY_minRate {
+            latency.min_rate = value_as_int(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_LATENCY_maxRate {
+            latency.max_rate = value_as_int(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_LATENCY_minNs {
+            latency.min_ns = value_as_long(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_LATENCY_maxNs {
+            latency.max_ns = value_as_long(value).unwrap_or_default();
+        }
+    }
+
+    Some(latency)
+}
+
+// Parse a ProcessLatency param, the latency added by a node (or port) itself.
+pub(crate) fn parse_process_latency(pod: &Pod) -> Option<PipewireProcessLatency> {
+    let properties = parse_object(pod)?;
+    let mut latency = PipewireProcessLatency::default();
+
+    for (key, value) in properties.iter() {
+        let key = *key;
+        if key == sys::SPA_PARAM_PROCESS_LATENCY_quantum {
+            latency.quantum = value_as_float(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_PROCESS_LATENCY_rate {
+            latency.rate = value_as_int(value).unwrap_or_default();
+        } else if key == sys::SPA_PARAM_PROCESS_LATENCY_ns {
+            latency.ns = value_as_long(value).unwrap_or_default();
+        }
+    }
+
+    Some(latency)
+}
+
+// Serialize a ProcessLatency param, to be set on a node.
+pub(crate) fn serialize_process_latency(latency: &PipewireProcessLatency) -> Vec<u8> {
+    let object = Object {
+        type_: SpaTypes::ObjectParamProcessLatency.as_raw(),
+        id: ParamType::ProcessLatency.as_raw(),
+        properties: vec![
+            Property::new(
+                sys::SPA_PARAM_PROCESS_LATENCY_quantum,
+                Value::Float(latency.quantum),
+            ),
+            Property::new(
+                sys::SPA_PARAM_PROCESS_LATENCY_rate,
+                Value::Int(latency.rate),
+            ),
+            Property::new(sys::SPA_PARAM_PROCESS_LATENCY_ns, Value::Long(latency.ns)),
+        ],
+    };
+
+    PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(object))
+        .expect("ERROR: error at serializing process latency")
+        .0
+        .into_inner()
+}
@@ -0,0 +1,348 @@
+use crate::{
+    query::{id_of, props_of},
+    PipewireData,
+};
+use pipewire::permissions::PermissionFlags;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+// Permission to link a node that can't be read (PW_PERM_L, since PipeWire 0.3.77).
+// PermissionFlags only knows it with the v0_3_77 feature of pipewire, but keeps the bit.
+pub(crate) const LINK: PermissionFlags = PermissionFlags::from_bits_retain(0o020);
+
+pub(crate) fn permissions_of(data: &PipewireData) -> PermissionFlags {
+    match data {
+        PipewireData::Node(node) => node.permissions,
+        PipewireData::Port(port) => port.permissions,
+        PipewireData::Link(link) => link.permissions,
+        PipewireData::Client(client) => client.permissions,
+        PipewireData::Metadata(metadata) => metadata.permissions,
+        PipewireData::Device(device) => device.permissions,
+    }
+}
+
+// Objects created by this connection: the globals of its proxies (`proxy_ids`), the other objects created by
+// its client (the client.id of these globals), and the ports of its nodes.
+pub(crate) fn owned_objects(
+    all_data: &HashMap<u32, PipewireData>,
+    proxy_ids: &HashSet<u32>,
+) -> BTreeSet<u32> {
+    let client_id = |data: &PipewireData| match data {
+        PipewireData::Client(_) => None,
+        _ => props_of(data).get("client.id").cloned(),
+    };
+
+    let client_ids: HashSet<String> = proxy_ids
+        .iter()
+        .filter_map(|id| all_data.get(id).and_then(client_id))
+        .collect();
+
+    let mut owned: BTreeSet<u32> = all_data
+        .values()
+        .filter(|data| {
+            proxy_ids.contains(&id_of(data))
+                || client_id(data).is_some_and(|id| client_ids.contains(&id))
+        })
+        .map(id_of)
+        .collect();
+
+    let ports: Vec<u32> = all_data
+        .values()
+        .filter_map(|data| match data {
+            PipewireData::Port(port) if owned.contains(&port.node_id) => Some(port.id),
+            _ => None,
+        })
+        .collect();
+    owned.extend(ports);
+
+    owned
+}
+
+// Owned nodes and links to destroy at cleanup, links first. Lingering objects (created permanent, or with
+// object.linger) are only destroyed with `include_lingering`. Ports go with their node.
+pub(crate) fn cleanup_targets(
+    all_data: &HashMap<u32, PipewireData>,
+    owned: &BTreeSet<u32>,
+    lingering_proxies: &HashSet<u32>,
+    include_lingering: bool,
+) -> Vec<u32> {
+    let lingering = |data: &PipewireData| {
+        lingering_proxies.contains(&id_of(data))
+            || props_of(data)
+                .get("object.linger")
+                .is_some_and(|linger| linger == "true" || linger == "1")
+    };
+
+    let targets = |is_link: bool| {
+        owned
+            .iter()
+            .filter_map(|id| all_data.get(id))
+            .filter(move |data| match data {
+                PipewireData::Link(_) => is_link,
+                PipewireData::Node(_) => !is_link,
+                _ => false,
+            })
+            .filter(|data| include_lingering || !lingering(data))
+            .map(id_of)
+    };
+
+    targets(true).chain(targets(false)).collect()
+}
+
+// Objects destroyObject accepts to destroy: the links and the objects created by this connection.
+// Any link can be destroyed (as before the ownership tracking), a link only connects the ports of
+// two nodes and unlinking them is what a patchbay does. The server still checks the permissions.
+pub(crate) fn destroy_allowed(target: &PipewireData, owned: &BTreeSet<u32>) -> bool {
+    match target {
+        PipewireData::Link(_) => true,
+        _ => owned.contains(&id_of(target)),
+    }
+}
+
+// Whether destroying the object would be accepted, by destroyObject and by the server
+// (destroying a global requires write and execute permissions on it).
+pub(crate) fn can_destroy(target: &PipewireData, owned: &BTreeSet<u32>) -> bool {
+    permissions_of(target).contains(PermissionFlags::W | PermissionFlags::X)
+        && destroy_allowed(target, owned)
+}
+
+// Whether the server would accept a link from or to the port: its node must be readable, or linkable.
+pub(crate) fn can_link(all_data: &HashMap<u32, PipewireData>, port_id: u32) -> bool {
+    let port = match all_data.get(&port_id) {
+        Some(PipewireData::Port(port)) => port,
+        _ => return false,
+    };
+
+    match all_data.get(&port.node_id) {
+        Some(PipewireData::Node(node)) => {
+            node.permissions.contains(PermissionFlags::R) || node.permissions.contains(LINK)
+        }
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PermissionRequestKind {
+    Get,
+    Update,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PermissionRequest {
+    // id of the callback of the request
+    pub id: u32,
+    pub client_id: u32,
+    pub kind: PermissionRequestKind,
+    // sequence number of the sync sent after the request
+    seq: i32,
+}
+
+// Permissions requests and updates sent to the client proxies, in the order they were sent.
+// Every request is followed by a sync: the server processes them in order, so when a client
+// proxy gets a permissions event or an error, the earlier requests were answered already and
+// it belongs to the oldest pending request of the client.
+#[derive(Debug, Default)]
+pub(crate) struct PendingPermissionRequests {
+    requests: VecDeque<PermissionRequest>,
+}
+
+impl PendingPermissionRequests {
+    pub fn push(&mut self, seq: i32, id: u32, client_id: u32, kind: PermissionRequestKind) {
+        self.requests.push_back(PermissionRequest {
+            id,
+            client_id,
+            kind,
+            seq,
+        });
+    }
+
+    // The oldest pending request of the client, of the kind `kind` if set.
+    pub fn take_oldest(
+        &mut self,
+        client_id: u32,
+        kind: Option<PermissionRequestKind>,
+    ) -> Option<PermissionRequest> {
+        let position = self.requests.iter().position(|request| {
+            request.client_id == client_id && kind.is_none_or(|kind| request.kind == kind)
+        })?;
+        self.requests.remove(position)
+    }
+
+    // The request answered by the sync `seq`, None if it was answered (or failed) before.
+    pub fn take_synced(&mut self, seq: i32) -> Option<PermissionRequest> {
+        let position = self
+            .requests
+            .iter()
+            .position(|request| request.seq == seq)?;
+        self.requests.remove(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipewireLink, PipewireNode, PipewirePort};
+
+    fn node(id: u32, name: &str, permissions: PermissionFlags) -> PipewireData {
+        PipewireData::Node(PipewireNode {
+            id,
+            permissions,
+            props: [
+                ("node.name".to_string(), name.to_string()),
+                ("client.id".to_string(), format!("{}", 100 + id % 2)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            name: name.to_string(),
+            node_direction: String::new(),
+            node_type: String::from("Audio"),
+            category: String::new(),
+            kind: String::new(),
+            is_monitor: false,
+            ports: Vec::new(),
+            quantum: None,
+            rate: None,
+            process_latency: None,
+            stats: None,
+        })
+    }
+
+    fn port(id: u32, node_id: u32) -> PipewireData {
+        PipewireData::Port(PipewirePort {
+            id,
+            permissions: PermissionFlags::R,
+            props: HashMap::new(),
+            node_id,
+            name: format!("port {}", id),
+            direction: String::from("Input"),
+            format: None,
+            formats: Vec::new(),
+            latency: Vec::new(),
+            process_latency: None,
+        })
+    }
+
+    #[test]
+    fn finds_the_owned_objects() {
+        let mut all_data = HashMap::new();
+        // the nodes 1 and 3 have the same client
+        for id in 1..=3 {
+            all_data.insert(id, node(id, "node", PermissionFlags::all()));
+            all_data.insert(10 + id, port(10 + id, id));
+        }
+
+        let proxy_ids: HashSet<u32> = std::iter::once(1).collect();
+        let owned: Vec<u32> = owned_objects(&all_data, &proxy_ids).into_iter().collect();
+        assert_eq!(owned, vec![1, 3, 11, 13]);
+
+        assert!(owned_objects(&all_data, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn lists_the_cleanup_targets() {
+        let mut all_data = HashMap::new();
+        for id in 1..=3 {
+            all_data.insert(id, node(id, "node", PermissionFlags::all()));
+            all_data.insert(10 + id, port(10 + id, id));
+        }
+        all_data.insert(
+            20,
+            PipewireData::Link(PipewireLink {
+                id: 20,
+                permissions: PermissionFlags::all(),
+                props: std::iter::once(("object.linger".to_string(), "false".to_string()))
+                    .collect(),
+                input_node_id: 1,
+                input_port_id: 11,
+                output_node_id: 2,
+                output_port_id: 12,
+                format: None,
+            }),
+        );
+
+        let owned: BTreeSet<u32> = [1, 3, 11, 13, 20].iter().cloned().collect();
+        let lingering: HashSet<u32> = std::iter::once(3).collect();
+
+        assert_eq!(
+            cleanup_targets(&all_data, &owned, &lingering, false),
+            vec![20, 1]
+        );
+        assert_eq!(
+            cleanup_targets(&all_data, &owned, &lingering, true),
+            vec![20, 1, 3]
+        );
+    }
+
+    #[test]
+    fn checks_the_destroy_policy() {
+        let all = PermissionFlags::all();
+        let owned: BTreeSet<u32> = std::iter::once(1).collect();
+
+        assert!(can_destroy(&node(1, "sink", all), &owned));
+        assert!(!can_destroy(&node(1, "sink", PermissionFlags::R), &owned));
+        assert!(!can_destroy(&node(2, "node-pipewire:sink", all), &owned));
+        // links are exempt from the ownership check
+        assert!(can_destroy(
+            &PipewireData::Link(PipewireLink {
+                id: 2,
+                permissions: all,
+                props: HashMap::new(),
+                input_node_id: 0,
+                input_port_id: 0,
+                output_node_id: 0,
+                output_port_id: 0,
+                format: None,
+            }),
+            &owned
+        ));
+    }
+
+    #[test]
+    fn checks_the_link_permissions() {
+        let mut all_data = HashMap::new();
+        all_data.insert(1, node(1, "readable", PermissionFlags::R));
+        all_data.insert(2, node(2, "linkable", LINK));
+        all_data.insert(3, node(3, "hidden", PermissionFlags::empty()));
+        for id in 1..=3 {
+            all_data.insert(10 + id, port(10 + id, id));
+        }
+
+        assert!(can_link(&all_data, 11));
+        assert!(can_link(&all_data, 12));
+        assert!(!can_link(&all_data, 13));
+        assert!(!can_link(&all_data, 1));
+    }
+
+    #[test]
+    fn fails_only_the_oldest_request_of_the_client() {
+        let mut requests = PendingPermissionRequests::default();
+        requests.push(1, 10, 5, PermissionRequestKind::Update);
+        requests.push(2, 11, 6, PermissionRequestKind::Update);
+        requests.push(3, 12, 5, PermissionRequestKind::Get);
+        requests.push(4, 13, 5, PermissionRequestKind::Update);
+
+        // an error of the proxy of the client 5
+        assert_eq!(
+            requests.take_oldest(5, None).map(|request| request.id),
+            Some(10)
+        );
+        assert_eq!(requests.take_synced(1), None);
+
+        // the permissions event answers the get, not the update sent before it
+        requests.push(5, 14, 5, PermissionRequestKind::Update);
+        assert_eq!(
+            requests
+                .take_oldest(5, Some(PermissionRequestKind::Get))
+                .map(|request| request.id),
+            Some(12)
+        );
+
+        for (seq, id) in [(2, 11), (4, 13), (5, 14)] {
+            assert_eq!(
+                requests.take_synced(seq).map(|request| request.id),
+                Some(id)
+            );
+        }
+        assert_eq!(requests.take_synced(3), None);
+    }
+}
@@ -1,23 +1,64 @@
-use crate::{proxy::ProxyWrapper, MainOptions, PipewireData, PipewireOptions, ALL_DATA};
+use crate::{
+    capture::{Capture, CaptureProcessor},
+    latency::node_clock,
+    loopback::{LoadedModule, PendingLoopback, MODULE_NAME, NODES_TIMEOUT},
+    meter::{ActivityDetector, LevelMeter},
+    midi::MidiPort,
+    params::{parse_format, parse_latency, parse_process_latency, serialize_process_latency},
+    permissions::{self, PendingPermissionRequests, PermissionRequestKind},
+    profiler::{
+        parse_profile, NodeStatsEvent, NodeStatsRequests, Profiler, ProfilerAggregator,
+        NODE_STATS_INTERVAL,
+    },
+    proxy::{BoundObject, ProxyWrapper},
+    spectrum::SpectrumAnalyzer,
+    video::{VideoCapture, VirtualCamera},
+    MainOptions, PipewireData, PipewireFormat, PipewireLatency, PipewireNode, PipewireOptions,
+    PipewirePort, PipewireProcessLatency, ALL_DATA, LINGERING_PROXIES, NODE_STATS_ID,
+    OWNED_PROXIES,
+};
 use pipewire::{
+    client::Client,
     context::ContextRc,
-    core::Core,
-    link::Link,
+    core::{Core, CoreRc, PW_ID_CORE},
+    link::{Link, LinkChangeMask},
     main_loop::MainLoopRc,
-    node::Node,
-    properties::properties,
+    metadata::Metadata,
+    node::{Node, NodeChangeMask},
+    permissions::{Permission, PermissionFlags},
+    port::{Port, PortChangeMask},
+    properties::{properties, PropertiesBox},
     proxy::ProxyT,
     registry::{GlobalObject, Registry},
-    spa::utils::dict::DictRef,
+    spa::{
+        param::{ParamInfoFlags, ParamType},
+        pod::Pod,
+        utils::dict::DictRef,
+    },
     types::ObjectType,
 };
 
-use std::{cell::RefCell, rc::Rc, sync::mpsc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
 
 thread_local! {
     static ENABLE_DEBUG: RefCell<bool> = RefCell::new(false);
 }
 
+// Period of the ticks of the captures (the precision of the hold of the activity detectors)
+const CAPTURE_TICK: Duration = Duration::from_millis(50);
+
+// Period of the checks of the loopbacks whose nodes were not announced yet
+const LOOPBACK_TICK: Duration = Duration::from_millis(100);
+
+// Period of the checks of the node stats (first interval, idle profiler)
+const NODE_STATS_TICK: Duration = Duration::from_millis(250);
+
 pub(super) fn pw_thread(
     front_sender: mpsc::Sender<MainOptions>,
     pw_receiver: pipewire::channel::Receiver<PipewireOptions>,
@@ -26,6 +67,43 @@ pub(super) fn pw_thread(
     // Proxy cache to prevent destruction of elements while thread is running
     let proxies: Rc<RefCell<Vec<ProxyWrapper>>> = Rc::new(RefCell::new(Vec::new()));
 
+    // Capture streams (meters, ...) by the id used to send their events to the front
+    let captures: Rc<RefCell<HashMap<u32, Capture>>> = Rc::new(RefCell::new(HashMap::new()));
+
+    // MIDI ports by the id used to send their events to the front
+    let midi_ports: Rc<RefCell<HashMap<u32, MidiPort>>> = Rc::new(RefCell::new(HashMap::new()));
+
+    // Video captures and virtual cameras by the id used to send their events to the front
+    let video_captures: Rc<RefCell<HashMap<u32, VideoCapture>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+    let virtual_cameras: Rc<RefCell<HashMap<u32, VirtualCamera>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+
+    // Modules of the loopbacks by the id used to send their events to the front
+    let loopbacks: Rc<RefCell<HashMap<u32, LoadedModule>>> = Rc::new(RefCell::new(HashMap::new()));
+    // Loopbacks whose nodes were not announced by the registry yet
+    let pending_loopbacks: Rc<RefCell<HashMap<u32, PendingLoopback>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+
+    // Proxies bound to the globals of the registry (ports, links) to follow their params
+    let bound: Rc<RefCell<HashMap<u32, BoundObject>>> = Rc::new(RefCell::new(HashMap::new()));
+
+    // Profiler global and its subscribers, the profiler is only bound while there are subscribers
+    let profiler_global: Rc<Cell<Option<u32>>> = Rc::new(Cell::new(None));
+    let profiler: Rc<RefCell<Option<Profiler>>> = Rc::new(RefCell::new(None));
+    let profiler_subscribers: Rc<RefCell<HashMap<u32, ProfilerAggregator>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+    // Calls to getNodeStats(), None while its profiler subscription (NODE_STATS_ID) is stopped
+    let node_stats: Rc<RefCell<Option<NodeStatsRequests>>> = Rc::new(RefCell::new(None));
+
+    // Permissions requests and updates of the client proxies waiting for their sync
+    let permission_requests: Rc<RefCell<PendingPermissionRequests>> =
+        Rc::new(RefCell::new(PendingPermissionRequests::default()));
+
+    // Options sent to the front once the server processed the methods called before a sync (by sequence number)
+    let pending_syncs: Rc<RefCell<HashMap<i32, MainOptions>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+
     // Basic setup of pipewire thread
     let mainloop = MainLoopRc::new(None).expect("ERROR: error at creating mainloop");
     let context = ContextRc::new(&mainloop, None).expect("ERROR: error at creating context");
@@ -40,9 +118,25 @@ pub(super) fn pw_thread(
     // Listen the pw_receiver the options from "PipewireOptions" struct
     let _receiver = pw_receiver.attach(&mainloop.loop_(), {
         let mainloop = mainloop.clone();
+        let context = context.clone();
+        let core = core.clone();
         let registry = registry.clone();
+        let sender = front_sender.clone();
 
         let proxies = proxies.clone();
+        let captures = captures.clone();
+        let midi_ports = midi_ports.clone();
+        let video_captures = video_captures.clone();
+        let virtual_cameras = virtual_cameras.clone();
+        let loopbacks = loopbacks.clone();
+        let pending_loopbacks = pending_loopbacks.clone();
+        let bound = bound.clone();
+        let profiler_global = profiler_global.clone();
+        let profiler = profiler.clone();
+        let profiler_subscribers = profiler_subscribers.clone();
+        let node_stats = node_stats.clone();
+        let permission_requests = permission_requests.clone();
+        let pending_syncs = pending_syncs.clone();
 
         move |msg| match msg {
             PipewireOptions::CloseThread => {
@@ -65,7 +159,9 @@ pub(super) fn pw_thread(
                 let links =
                     link_nodes_name_to_id(output_nodes_name, input_node_id, permanent, &core);
                 for link in links {
-                    proxies.borrow_mut().push(ProxyWrapper::new(link.upcast()));
+                    proxies
+                        .borrow_mut()
+                        .push(ProxyWrapper::new(link.upcast(), permanent, &sender));
                 }
             }
             PipewireOptions::LinkPorts {
@@ -77,7 +173,9 @@ pub(super) fn pw_thread(
                     println!("Linking ports: {:?} -> {:?}", input_port, output_port);
                 }
                 let link = link_ports(input_port, output_port, permanent, &core);
-                proxies.borrow_mut().push(ProxyWrapper::new(link.upcast()));
+                proxies
+                    .borrow_mut()
+                    .push(ProxyWrapper::new(link.upcast(), permanent, &sender));
             }
             PipewireOptions::UnLinkNodesNameToId {
                 output_nodes_name,
@@ -100,46 +198,471 @@ pub(super) fn pw_thread(
                 }
                 unlink_ports(input_port, output_port, &registry);
             }
-            PipewireOptions::CreateSource {
-                source_name,
-                audio_position,
-                channel_count,
-                permanent,
+            PipewireOptions::CreateDevice { props, permanent } => {
+                if enable_debug {
+                    println!("Creating virtual device with props {:?}", props);
+                }
+                let device = create_device(props, &core);
+
+                proxies
+                    .borrow_mut()
+                    .push(ProxyWrapper::new(device.upcast(), permanent, &sender));
+            }
+            PipewireOptions::DeleteObject { id } => {
+                if enable_debug {
+                    println!("Attempting to destroy object {:?}", id);
+                }
+                destroy_object(id, &registry);
+            }
+            PipewireOptions::SetProcessLatency { node_id, latency } => {
+                if enable_debug {
+                    println!(
+                        "Setting process latency of node {:?}: {:?}",
+                        node_id, latency
+                    );
+                }
+                match bound.borrow().get(&node_id) {
+                    Some(BoundObject::Node { proxy, .. }) => {
+                        let param = serialize_process_latency(&latency);
+                        let param = Pod::from_bytes(&param)
+                            .expect("ERROR: error at reading process latency");
+                        proxy.set_param(ParamType::ProcessLatency, 0, param);
+                    }
+                    _ => {
+                        if enable_debug {
+                            println!("Node {:?} not found", node_id);
+                        }
+                    }
+                }
+            }
+            PipewireOptions::SetMetadataProperty {
+                id,
+                subject,
+                key,
+                type_,
+                value,
             } => {
                 if enable_debug {
                     println!(
-                        "Creating virtual source named {:?} with position {:?}",
-                        source_name, audio_position
+                        "Setting property of metadata {:?}: subject: {:?}, key: {:?}, value: {:?}",
+                        id, subject, key, value
                     );
                 }
-                let source =
-                    create_source(source_name, audio_position, channel_count, permanent, &core);
+                match bound.borrow().get(&id) {
+                    Some(BoundObject::Metadata { proxy, .. }) => {
+                        proxy.set_property(subject, &key, type_.as_deref(), value.as_deref());
+                    }
+                    _ => {
+                        if enable_debug {
+                            println!("Metadata {:?} not found", id);
+                        }
+                    }
+                }
+            }
+            PipewireOptions::ClearMetadata { id } => {
+                if enable_debug {
+                    println!("Clearing metadata {:?}", id);
+                }
+                match bound.borrow().get(&id) {
+                    Some(BoundObject::Metadata { proxy, .. }) => proxy.clear(),
+                    _ => {
+                        if enable_debug {
+                            println!("Metadata {:?} not found", id);
+                        }
+                    }
+                }
+            }
+            PipewireOptions::MoveStreamLinks {
+                stream_id,
+                target_id,
+            } => {
+                if enable_debug {
+                    println!("Moving stream {:?} to node {:?}", stream_id, target_id);
+                }
+                let links = move_stream_links(stream_id, target_id, &core, &registry);
+                for link in links {
+                    proxies
+                        .borrow_mut()
+                        .push(ProxyWrapper::new(link.upcast(), true, &sender));
+                }
+            }
+            PipewireOptions::CleanupOwnedObjects {
+                include_lingering,
+                done,
+            } => {
+                let targets = cleanup_targets(include_lingering);
+                if enable_debug {
+                    println!("Cleaning up owned objects: {:?}", targets);
+                }
+                for id in targets {
+                    registry.destroy_global(id);
+                }
 
+                // The server removes the objects before answering the sync.
+                let pending = core
+                    .sync(0)
+                    .expect("ERROR: error at syncing with the server");
+                pending_syncs
+                    .borrow_mut()
+                    .insert(pending.seq(), MainOptions::ObjectsCleanedUp { done });
+            }
+            PipewireOptions::ReleaseProxy { id } => {
+                if enable_debug {
+                    println!("Releasing proxy of object {:?}", id);
+                }
+                // a non lingering object is destroyed with its proxy
                 proxies
                     .borrow_mut()
-                    .push(ProxyWrapper::new(source.upcast()));
+                    .retain(|proxy| proxy.get_global_id() != id);
             }
-            PipewireOptions::CreateSink {
-                sink_name,
-                audio_position,
-                channel_count,
-                permanent,
+            PipewireOptions::GetClientPermissions { id, client_id } => {
+                if enable_debug {
+                    println!("Getting permissions of client {:?}", client_id);
+                }
+                match bound.borrow().get(&client_id) {
+                    // The answer is a permissions event of the client, or an error of its proxy.
+                    Some(BoundObject::Client { proxy, .. }) => {
+                        proxy.get_permissions(0, u32::MAX);
+
+                        let pending = core
+                            .sync(0)
+                            .expect("ERROR: error at syncing with the server");
+                        permission_requests.borrow_mut().push(
+                            pending.seq(),
+                            id,
+                            client_id,
+                            PermissionRequestKind::Get,
+                        );
+                    }
+                    _ => {
+                        if enable_debug {
+                            println!("Client {:?} not found", client_id);
+                        }
+                        sender
+                            .send(MainOptions::ClientPermissionsFailed {
+                                id,
+                                error: String::from("The client is not bound"),
+                            })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
+            }
+            PipewireOptions::UpdateClientPermissions {
+                id,
+                client_id,
+                permissions,
             } => {
                 if enable_debug {
                     println!(
-                        "Creating virtual sink named {:?} with position {:?}",
-                        sink_name, audio_position
+                        "Updating permissions of client {:?}: {:?}",
+                        client_id, permissions
                     );
                 }
-                let sink = create_sink(sink_name, audio_position, channel_count, permanent, &core);
-
-                proxies.borrow_mut().push(ProxyWrapper::new(sink.upcast()));
+                match bound.borrow().get(&client_id) {
+                    Some(BoundObject::Client { proxy, .. }) => {
+                        let permissions: Vec<Permission> = permissions
+                            .iter()
+                            .map(|(id, flags)| Permission::new(*id, *flags))
+                            .collect();
+                        proxy.update_permissions(&permissions);
+
+                        // The server reports an error of the proxy before answering the sync.
+                        let pending = core
+                            .sync(0)
+                            .expect("ERROR: error at syncing with the server");
+                        permission_requests.borrow_mut().push(
+                            pending.seq(),
+                            id,
+                            client_id,
+                            PermissionRequestKind::Update,
+                        );
+                    }
+                    _ => {
+                        if enable_debug {
+                            println!("Client {:?} not found", client_id);
+                        }
+                        sender
+                            .send(MainOptions::ClientPermissionsFailed {
+                                id,
+                                error: String::from("The client is not bound"),
+                            })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
             }
-            PipewireOptions::DeleteObject { id } => {
+            PipewireOptions::DisconnectClient { id, client_id } => {
                 if enable_debug {
-                    println!("Attempting to destroy object {:?}", id);
+                    println!("Disconnecting client {:?}", client_id);
                 }
-                destroy_object(id, &registry);
+                // The server removes the client before answering the sync if it allowed it.
+                registry.destroy_global(client_id);
+                let pending = core
+                    .sync(0)
+                    .expect("ERROR: error at syncing with the server");
+                pending_syncs.borrow_mut().insert(
+                    pending.seq(),
+                    MainOptions::ClientDisconnected { id, client_id },
+                );
+            }
+            PipewireOptions::CreateMeter {
+                id,
+                node_id,
+                channel,
+                interval_ms,
+            } => {
+                if enable_debug {
+                    println!("Creating meter {:?} for node {:?}", id, node_id);
+                }
+                let meter = LevelMeter::new(
+                    id,
+                    channel,
+                    Duration::from_millis(interval_ms),
+                    sender.clone(),
+                );
+                start_capture(id, node_id, Box::new(meter), &core, &captures, &sender);
+            }
+            PipewireOptions::CreateSpectrumAnalyzer {
+                id,
+                node_id,
+                fft_size,
+                bands,
+                interval_ms,
+            } => {
+                if enable_debug {
+                    println!("Creating spectrum analyzer {:?} for node {:?}", id, node_id);
+                }
+                let analyzer = SpectrumAnalyzer::new(
+                    id,
+                    fft_size,
+                    bands,
+                    Duration::from_millis(interval_ms),
+                    sender.clone(),
+                );
+                start_capture(id, node_id, Box::new(analyzer), &core, &captures, &sender);
+            }
+            PipewireOptions::CreateActivityDetector {
+                id,
+                node_id,
+                threshold_db,
+                hold_ms,
+            } => {
+                if enable_debug {
+                    println!("Creating activity detector {:?} for node {:?}", id, node_id);
+                }
+                let detector = ActivityDetector::new(
+                    id,
+                    threshold_db,
+                    Duration::from_millis(hold_ms),
+                    sender.clone(),
+                );
+                start_capture(id, node_id, Box::new(detector), &core, &captures, &sender);
+            }
+            PipewireOptions::StopCapture { id } => {
+                if enable_debug {
+                    println!("Stopping capture {:?}", id);
+                }
+                captures.borrow_mut().remove(&id);
+            }
+            PipewireOptions::CreateMidiPort { id, name, output } => {
+                if enable_debug {
+                    println!("Creating MIDI port {:?} named {:?}", id, name);
+                }
+                match MidiPort::new(&core, id, &name, output, sender.clone()) {
+                    Ok(port) => {
+                        midi_ports.borrow_mut().insert(id, port);
+                    }
+                    Err(error) => {
+                        if enable_debug {
+                            println!("ERROR: error at creating MIDI port {}: {}", id, error);
+                        }
+                        sender
+                            .send(MainOptions::MidiPortClosed { id })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
+            }
+            PipewireOptions::SendMidi { id, message } => match midi_ports.borrow().get(&id) {
+                Some(port) => port.send(message),
+                None => {
+                    if enable_debug {
+                        println!("MIDI port {:?} not found", id);
+                    }
+                }
+            },
+            PipewireOptions::CloseMidiPort { id } => {
+                if enable_debug {
+                    println!("Closing MIDI port {:?}", id);
+                }
+                midi_ports.borrow_mut().remove(&id);
+            }
+            PipewireOptions::CreateVideoCapture {
+                id,
+                node_id,
+                format,
+                size,
+            } => {
+                if enable_debug {
+                    println!("Creating video capture {:?} for node {:?}", id, node_id);
+                }
+                let serial = match ALL_DATA.lock().unwrap().get(&node_id) {
+                    Some(PipewireData::Node(node)) => node.props.get("object.serial").cloned(),
+                    _ => None,
+                };
+                match VideoCapture::new(
+                    &core,
+                    id,
+                    node_id,
+                    serial,
+                    format.as_deref(),
+                    size,
+                    sender.clone(),
+                ) {
+                    Ok(capture) => {
+                        video_captures.borrow_mut().insert(id, capture);
+                    }
+                    Err(error) => {
+                        if enable_debug {
+                            println!("ERROR: error at creating video capture {}: {}", id, error);
+                        }
+                        sender
+                            .send(MainOptions::VideoStreamClosed { id })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
+            }
+            PipewireOptions::CreateVirtualCamera {
+                id,
+                name,
+                format,
+                width,
+                height,
+            } => {
+                if enable_debug {
+                    println!("Creating virtual camera {:?} named {:?}", id, name);
+                }
+                match VirtualCamera::new(&core, id, &name, &format, width, height, sender.clone()) {
+                    Ok(camera) => {
+                        virtual_cameras.borrow_mut().insert(id, camera);
+                    }
+                    Err(error) => {
+                        if enable_debug {
+                            println!("ERROR: error at creating virtual camera {}: {}", id, error);
+                        }
+                        sender
+                            .send(MainOptions::VideoStreamClosed { id })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
+            }
+            PipewireOptions::SendVideoFrame { id, frame } => {
+                match virtual_cameras
+                    .borrow()
+                    .get(&id)
+                    .map(|camera| camera.send(frame))
+                {
+                    Some(Ok(())) => {}
+                    Some(Err(error)) => {
+                        if enable_debug {
+                            println!("ERROR: error at publishing a frame of {}: {}", id, error);
+                        }
+                    }
+                    None => {
+                        if enable_debug {
+                            println!("Virtual camera {:?} not found", id);
+                        }
+                    }
+                }
+            }
+            PipewireOptions::CloseVideoStream { id } => {
+                if enable_debug {
+                    println!("Closing video stream {:?}", id);
+                }
+                video_captures.borrow_mut().remove(&id);
+                virtual_cameras.borrow_mut().remove(&id);
+            }
+            PipewireOptions::CreateLoopback { id, options } => {
+                if enable_debug {
+                    println!("Creating loopback {:?}", id);
+                }
+                let args = options.args(id).expect("ERROR: invalid loopback options");
+
+                // The module creates its nodes on its own connection, the registry tells when they exist.
+                match LoadedModule::load(&context, MODULE_NAME, &args.args) {
+                    Some(module) => {
+                        loopbacks.borrow_mut().insert(id, module);
+                        pending_loopbacks
+                            .borrow_mut()
+                            .insert(id, PendingLoopback::new(&args, Instant::now()));
+                    }
+                    None => {
+                        if enable_debug {
+                            println!("ERROR: error at loading {} for {}", MODULE_NAME, id);
+                        }
+                        sender
+                            .send(MainOptions::LoopbackFailed {
+                                id,
+                                error: format!("Could not load {}", MODULE_NAME),
+                            })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
+            }
+            PipewireOptions::DestroyLoopback { id } => {
+                if enable_debug {
+                    println!("Destroying loopback {:?}", id);
+                }
+                pending_loopbacks.borrow_mut().remove(&id);
+                loopbacks.borrow_mut().remove(&id);
+            }
+            PipewireOptions::SubscribeProfiler { id, interval_ms } => {
+                if enable_debug {
+                    println!("Subscribing {:?} to the profiler", id);
+                }
+                let aggregator =
+                    ProfilerAggregator::new(id, Duration::from_millis(interval_ms), sender.clone());
+                profiler_subscribers.borrow_mut().insert(id, aggregator);
+                update_profiler(
+                    &registry,
+                    profiler_global.get(),
+                    &profiler,
+                    &profiler_subscribers,
+                );
+            }
+            PipewireOptions::RequestNodeStats => {
+                // keep the stats gathered so far, the profiler runs as long as the stats are requested
+                if let Some(requests) = node_stats.borrow_mut().as_mut() {
+                    requests.request(Instant::now());
+                    return;
+                }
+                if enable_debug {
+                    println!("Starting the node stats");
+                }
+                node_stats
+                    .borrow_mut()
+                    .replace(NodeStatsRequests::new(Instant::now()));
+                let aggregator =
+                    ProfilerAggregator::new(NODE_STATS_ID, NODE_STATS_INTERVAL, sender.clone());
+                profiler_subscribers
+                    .borrow_mut()
+                    .insert(NODE_STATS_ID, aggregator);
+                update_profiler(
+                    &registry,
+                    profiler_global.get(),
+                    &profiler,
+                    &profiler_subscribers,
+                );
+            }
+            PipewireOptions::UnsubscribeProfiler { id } => {
+                if enable_debug {
+                    println!("Unsubscribing {:?} from the profiler", id);
+                }
+                profiler_subscribers.borrow_mut().remove(&id);
+                update_profiler(
+                    &registry,
+                    profiler_global.get(),
+                    &profiler,
+                    &profiler_subscribers,
+                );
             }
         }
     });
@@ -151,12 +674,79 @@ pub(super) fn pw_thread(
         .add_listener_local()
         .global({
             let sender = front_sender.clone();
+            let registry = registry.clone();
+            let bound = bound.clone();
+            let profiler_global = profiler_global.clone();
+            let profiler = profiler.clone();
+            let profiler_subscribers = profiler_subscribers.clone();
+            let pending_loopbacks = pending_loopbacks.clone();
+            let permission_requests = permission_requests.clone();
 
             move |object| match object.type_ {
-                ObjectType::Node => save_node(object, &sender),
-                ObjectType::Port => save_port(object, &sender),
-                ObjectType::Link => save_link(object, &sender),
-                ObjectType::Client => save_client(object, &sender),
+                ObjectType::Node => {
+                    save_node(object, &sender);
+                    if let Some(node) = bind_node(object, &registry, &sender) {
+                        bound.borrow_mut().insert(object.id, node);
+                    }
+
+                    // the node was sent to the front first, so it is known when the loopback resolves
+                    let Some(name) = object.props.and_then(|props| props.get("node.name")) else {
+                        return;
+                    };
+                    let mut pending_loopbacks = pending_loopbacks.borrow_mut();
+                    let loaded: Vec<(u32, (u32, u32))> = pending_loopbacks
+                        .iter_mut()
+                        .filter_map(|(id, pending)| {
+                            pending.node_added(object.id, name).map(|ids| (*id, ids))
+                        })
+                        .collect();
+                    for (id, (capture_id, playback_id)) in loaded {
+                        pending_loopbacks.remove(&id);
+                        sender
+                            .send(MainOptions::LoopbackLoaded {
+                                id,
+                                capture_id,
+                                playback_id,
+                            })
+                            .expect("ERROR: error at sending option to front");
+                    }
+                }
+                ObjectType::Port => {
+                    save_port(object, &sender);
+                    if let Some(port) = bind_port(object, &registry, &sender) {
+                        bound.borrow_mut().insert(object.id, port);
+                    }
+                }
+                ObjectType::Link => {
+                    save_link(object, &sender);
+                    if let Some(link) = bind_link(object, &registry, &sender) {
+                        bound.borrow_mut().insert(object.id, link);
+                    }
+                }
+                ObjectType::Client => {
+                    save_client(object, &sender);
+                    if let Some(client) =
+                        bind_client(object, &registry, &sender, &permission_requests)
+                    {
+                        bound.borrow_mut().insert(object.id, client);
+                    }
+                }
+                ObjectType::Metadata => {
+                    save_metadata(object, &sender);
+                    if let Some(metadata) = bind_metadata(object, &registry, &sender) {
+                        bound.borrow_mut().insert(object.id, metadata);
+                    }
+                }
+                ObjectType::Device => save_device(object, &sender),
+                ObjectType::Profiler => {
+                    profiler_global.set(Some(object.id));
+                    update_profiler(
+                        &registry,
+                        profiler_global.get(),
+                        &profiler,
+                        &profiler_subscribers,
+                    );
+                }
                 _ => {
                     // Ignore other types.
                 }
@@ -165,11 +755,34 @@ pub(super) fn pw_thread(
         .global_remove({
             let sender = front_sender.clone();
             let proxies = proxies.clone();
+            let captures = captures.clone();
+            let bound = bound.clone();
+            let profiler_global = profiler_global.clone();
+            let profiler = profiler.clone();
 
             move |id| {
                 if ENABLE_DEBUG.with(|f| *f.borrow()) {
                     println!("Object deleted: {}", id);
                 }
+
+                bound.borrow_mut().remove(&id);
+
+                // Release the profiler if its module was unloaded.
+                if profiler_global.get() == Some(id) {
+                    profiler_global.set(None);
+                    profiler.borrow_mut().take();
+                }
+
+                // Close the captures of the removed node.
+                captures.borrow_mut().retain(|capture_id, capture| {
+                    if capture.node_id != id {
+                        return true;
+                    }
+                    sender
+                        .send(MainOptions::CaptureClosed { id: *capture_id })
+                        .expect("ERROR: error at sending option to front");
+                    false
+                });
                 let mut borrowed_proxies = proxies.borrow_mut();
 
                 if let Some(proxy) = borrowed_proxies
@@ -196,6 +809,171 @@ pub(super) fn pw_thread(
         })
         .register();
 
+    // Send the options waiting for a sync once the server answered it.
+    let _core_listener = core
+        .add_listener_local()
+        .done({
+            let sender = front_sender.clone();
+            let pending_syncs = pending_syncs.clone();
+            let permission_requests = permission_requests.clone();
+
+            move |id, seq| {
+                if id != PW_ID_CORE {
+                    return;
+                }
+                // a request still pending at its sync didn't fail, a get was answered by a permissions event
+                let request = permission_requests.borrow_mut().take_synced(seq.seq());
+                if let Some(request) = request {
+                    let option = match request.kind {
+                        PermissionRequestKind::Update => {
+                            MainOptions::ClientPermissionsUpdated { id: request.id }
+                        }
+                        PermissionRequestKind::Get => MainOptions::ClientPermissionsFailed {
+                            id: request.id,
+                            error: String::from("The server sent no permissions"),
+                        },
+                    };
+                    sender
+                        .send(option)
+                        .expect("ERROR: error at sending option to front");
+                }
+                if let Some(option) = pending_syncs.borrow_mut().remove(&seq.seq()) {
+                    sender
+                        .send(option)
+                        .expect("ERROR: error at sending option to front");
+                }
+            }
+        })
+        .error({
+            let sender = front_sender.clone();
+            let bound = bound.clone();
+            let permission_requests = permission_requests.clone();
+
+            // Only the errors of the client proxies (permissions) are reported to the front,
+            // to the request that caused it.
+            move |id, _seq, _res, message| {
+                let client_id =
+                    bound
+                        .borrow()
+                        .iter()
+                        .find_map(|(client_id, object)| match object {
+                            BoundObject::Client { proxy, .. } if proxy.upcast_ref().id() == id => {
+                                Some(*client_id)
+                            }
+                            _ => None,
+                        });
+
+                let request = client_id.and_then(|client_id| {
+                    permission_requests
+                        .borrow_mut()
+                        .take_oldest(client_id, None)
+                });
+                if let Some(request) = request {
+                    sender
+                        .send(MainOptions::ClientPermissionsFailed {
+                            id: request.id,
+                            error: message.to_string(),
+                        })
+                        .expect("ERROR: error at sending option to front");
+                }
+            }
+        })
+        .register();
+
+    // Tick the captures, so that the activity detectors report silence when no buffers arrive.
+    let capture_timer = mainloop.loop_().add_timer({
+        let captures = captures.clone();
+        move |_| {
+            for capture in captures.borrow().values() {
+                capture.tick();
+            }
+        }
+    });
+    capture_timer
+        .update_timer(Some(CAPTURE_TICK), Some(CAPTURE_TICK))
+        .into_result()
+        .expect("ERROR: error at starting the capture timer");
+
+    // Unload the loopbacks whose nodes were not announced in time.
+    let loopback_timer = mainloop.loop_().add_timer({
+        let sender = front_sender.clone();
+        let loopbacks = loopbacks.clone();
+        let pending_loopbacks = pending_loopbacks.clone();
+        move |_| {
+            let now = Instant::now();
+            let expired: Vec<u32> = pending_loopbacks
+                .borrow()
+                .iter()
+                .filter(|(_, pending)| pending.is_expired(now))
+                .map(|(id, _)| *id)
+                .collect();
+            for id in expired {
+                if enable_debug {
+                    println!("ERROR: the nodes of loopback {} were not created", id);
+                }
+                pending_loopbacks.borrow_mut().remove(&id);
+                loopbacks.borrow_mut().remove(&id);
+                sender
+                    .send(MainOptions::LoopbackFailed {
+                        id,
+                        error: format!(
+                            "The nodes of {} were not created within {:?}",
+                            MODULE_NAME, NODES_TIMEOUT
+                        ),
+                    })
+                    .expect("ERROR: error at sending option to front");
+            }
+        }
+    });
+    loopback_timer
+        .update_timer(Some(LOOPBACK_TICK), Some(LOOPBACK_TICK))
+        .into_result()
+        .expect("ERROR: error at starting the loopback timer");
+
+    // Tell the front when the first stats of the nodes are ready, and stop the profiler when they aren't requested.
+    let node_stats_timer = mainloop.loop_().add_timer({
+        let sender = front_sender.clone();
+        let registry = registry.clone();
+        let profiler_global = profiler_global.clone();
+        let profiler = profiler.clone();
+        let profiler_subscribers = profiler_subscribers.clone();
+        let node_stats = node_stats.clone();
+        move |_| {
+            let event = match node_stats.borrow_mut().as_mut() {
+                Some(requests) => requests.poll(Instant::now()),
+                None => return,
+            };
+            match event {
+                Some(NodeStatsEvent::Ready) => {
+                    sender
+                        .send(MainOptions::NodeStatsReady)
+                        .expect("ERROR: error at sending option to front");
+                }
+                Some(NodeStatsEvent::Idle) => {
+                    if enable_debug {
+                        println!("Stopping the node stats");
+                    }
+                    node_stats.borrow_mut().take();
+                    profiler_subscribers.borrow_mut().remove(&NODE_STATS_ID);
+                    update_profiler(
+                        &registry,
+                        profiler_global.get(),
+                        &profiler,
+                        &profiler_subscribers,
+                    );
+                    sender
+                        .send(MainOptions::NodeStatsStopped)
+                        .expect("ERROR: error at sending option to front");
+                }
+                None => {}
+            }
+        }
+    });
+    node_stats_timer
+        .update_timer(Some(NODE_STATS_TICK), Some(NODE_STATS_TICK))
+        .into_result()
+        .expect("ERROR: error at starting the node stats timer");
+
     // save the enable_debug value in the thread local variable
     ENABLE_DEBUG.with(|e| *e.borrow_mut() = enable_debug);
 
@@ -249,12 +1027,14 @@ fn save_node(node: &GlobalObject<&DictRef>, sender: &mpsc::Sender<MainOptions>)
         .to_string();
 
     // Get the direction of the node.
-    // TODO: Search what could be in the media.class property.
+    // Duplex nodes (Audio/Duplex, Midi/Bridge) have both input and output ports.
     let node_direction = props
         .get("media.class")
         .and_then(|string| {
             let string = String::from(string);
-            if string.contains("Input") || string.contains("Sink") {
+            if string.contains("Duplex") || string.contains("Bridge") {
+                Some("Duplex")
+            } else if string.contains("Input") || string.contains("Sink") {
                 Some("Input")
             } else if string.contains("Output") || string.contains("Source") {
                 Some("Output")
@@ -411,6 +1191,428 @@ fn save_link(link: &GlobalObject<&DictRef>, sender: &mpsc::Sender<MainOptions>)
     }
 }
 
+// Bind the profiler while there are subscribers and release it otherwise,
+// the server only profiles the graph while the profiler is bound.
+fn update_profiler(
+    registry: &Registry,
+    global_id: Option<u32>,
+    profiler: &RefCell<Option<Profiler>>,
+    subscribers: &Rc<RefCell<HashMap<u32, ProfilerAggregator>>>,
+) {
+    let mut profiler = profiler.borrow_mut();
+
+    let Some(global_id) = global_id.filter(|_| !subscribers.borrow().is_empty()) else {
+        profiler.take();
+        return;
+    };
+    if profiler.is_some() {
+        return;
+    }
+
+    let subscribers = subscribers.clone();
+    *profiler = Profiler::bind(
+        registry,
+        global_id,
+        Box::new(move |pod| {
+            let cycles = parse_profile(pod);
+            for aggregator in subscribers.borrow_mut().values_mut() {
+                aggregator.add(&cycles);
+            }
+        }),
+    );
+
+    if profiler.is_none() && ENABLE_DEBUG.with(|f| *f.borrow()) {
+        println!("Error binding profiler {}", global_id);
+    }
+}
+
+// Params of a port followed by the pipewire thread, a copy is sent to the front on every change.
+#[derive(Clone, Default)]
+struct PortParams {
+    format: Option<PipewireFormat>,
+    formats: Vec<PipewireFormat>,
+    latency: Vec<PipewireLatency>,
+    process_latency: Option<PipewireProcessLatency>,
+}
+
+// Bind a port to follow its formats and latency and send them to the front.
+fn bind_port(
+    object: &GlobalObject<&DictRef>,
+    registry: &Registry,
+    sender: &mpsc::Sender<MainOptions>,
+) -> Option<BoundObject> {
+    let id = object.id;
+    let port: Port = match registry.bind(object) {
+        Ok(port) => port,
+        Err(error) => {
+            if ENABLE_DEBUG.with(|f| *f.borrow()) {
+                println!("Error binding port {}: {:?}", id, error);
+            }
+            return None;
+        }
+    };
+
+    let params: Rc<RefCell<PortParams>> = Rc::new(RefCell::new(PortParams::default()));
+
+    let send_params = {
+        let sender = sender.clone();
+        let params = params.clone();
+        move || {
+            let params = params.borrow().clone();
+            sender
+                .send(MainOptions::UpdatePortParams {
+                    id,
+                    format: params.format,
+                    formats: params.formats,
+                    latency: params.latency,
+                    process_latency: params.process_latency,
+                })
+                .expect("ERROR: error at sending option to front");
+        }
+    };
+
+    let listener = port
+        .add_listener_local()
+        .info({
+            let params = params.clone();
+            let send_params = send_params.clone();
+            move |info| {
+                if !info.change_mask().contains(PortChangeMask::PARAMS) {
+                    return;
+                }
+
+                // The format param can't be read when it was cleared (port unlinked).
+                let cleared = info.params().iter().any(|param| {
+                    param.id() == ParamType::Format && !param.flags().contains(ParamInfoFlags::READ)
+                });
+                if cleared && params.borrow().format.is_some() {
+                    params.borrow_mut().format = None;
+                    send_params();
+                }
+            }
+        })
+        .param(move |_, param_type, index, _, param| {
+            let Some(param) = param else {
+                return;
+            };
+
+            {
+                let mut params = params.borrow_mut();
+
+                // Lists are enumerated again from the start when they change.
+                if param_type == ParamType::Format {
+                    params.format = parse_format(param);
+                } else if param_type == ParamType::EnumFormat {
+                    if index == 0 {
+                        params.formats.clear();
+                    }
+                    params.formats.extend(parse_format(param));
+                } else if param_type == ParamType::Latency {
+                    if index == 0 {
+                        params.latency.clear();
+                    }
+                    params.latency.extend(parse_latency(param));
+                } else if param_type == ParamType::ProcessLatency {
+                    params.process_latency = parse_process_latency(param);
+                } else {
+                    return;
+                }
+            }
+
+            send_params();
+        })
+        .register();
+
+    port.subscribe_params(&[
+        ParamType::EnumFormat,
+        ParamType::Format,
+        ParamType::Latency,
+        ParamType::ProcessLatency,
+    ]);
+
+    Some(BoundObject::Port {
+        _proxy: port,
+        _listener: listener,
+    })
+}
+
+// Bind a node to follow its latency and send it to the front.
+fn bind_node(
+    object: &GlobalObject<&DictRef>,
+    registry: &Registry,
+    sender: &mpsc::Sender<MainOptions>,
+) -> Option<BoundObject> {
+    let id = object.id;
+    let node: Node = match registry.bind(object) {
+        Ok(node) => node,
+        Err(error) => {
+            if ENABLE_DEBUG.with(|f| *f.borrow()) {
+                println!("Error binding node {}: {:?}", id, error);
+            }
+            return None;
+        }
+    };
+
+    // Quantum, rate and process latency of the node.
+    let latency: Rc<RefCell<(Option<u32>, Option<u32>, Option<PipewireProcessLatency>)>> =
+        Rc::new(RefCell::new((None, None, None)));
+
+    let send_latency = {
+        let sender = sender.clone();
+        let latency = latency.clone();
+        move || {
+            let (quantum, rate, process_latency) = latency.borrow().clone();
+            sender
+                .send(MainOptions::UpdateNodeLatency {
+                    id,
+                    quantum,
+                    rate,
+                    process_latency,
+                })
+                .expect("ERROR: error at sending option to front");
+        }
+    };
+
+    let listener = node
+        .add_listener_local()
+        .info({
+            let latency = latency.clone();
+            let send_latency = send_latency.clone();
+            move |info| {
+                if !info.change_mask().contains(NodeChangeMask::PROPS) {
+                    return;
+                }
+                let Some(props) = info.props() else {
+                    return;
+                };
+
+                let props: HashMap<String, String> = props
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect();
+                let (quantum, rate) = node_clock(&props);
+
+                let changed = {
+                    let mut latency = latency.borrow_mut();
+                    let changed = latency.0 != quantum || latency.1 != rate;
+                    latency.0 = quantum;
+                    latency.1 = rate;
+                    changed
+                };
+                if changed {
+                    send_latency();
+                }
+            }
+        })
+        .param(move |_, param_type, _, _, param| {
+            if param_type != ParamType::ProcessLatency {
+                return;
+            }
+
+            latency.borrow_mut().2 = param.and_then(parse_process_latency);
+            send_latency();
+        })
+        .register();
+
+    node.subscribe_params(&[ParamType::ProcessLatency]);
+
+    Some(BoundObject::Node {
+        proxy: node,
+        _listener: listener,
+    })
+}
+
+// Bind a link to follow its negotiated format and send it to the front.
+fn bind_link(
+    object: &GlobalObject<&DictRef>,
+    registry: &Registry,
+    sender: &mpsc::Sender<MainOptions>,
+) -> Option<BoundObject> {
+    let id = object.id;
+    let link: Link = match registry.bind(object) {
+        Ok(link) => link,
+        Err(error) => {
+            if ENABLE_DEBUG.with(|f| *f.borrow()) {
+                println!("Error binding link {}: {:?}", id, error);
+            }
+            return None;
+        }
+    };
+
+    let listener = link
+        .add_listener_local()
+        .info({
+            let sender = sender.clone();
+            move |info| {
+                if !info.change_mask().contains(LinkChangeMask::FORMAT) {
+                    return;
+                }
+
+                sender
+                    .send(MainOptions::UpdateLinkFormat {
+                        id,
+                        format: info.format().and_then(parse_format),
+                    })
+                    .expect("ERROR: error at sending option to front");
+            }
+        })
+        .register();
+
+    Some(BoundObject::Link {
+        _proxy: link,
+        _listener: listener,
+    })
+}
+
+// Create a metadata object and send it to the front.
+fn save_metadata(metadata: &GlobalObject<&DictRef>, sender: &mpsc::Sender<MainOptions>) {
+    let props = metadata
+        .props
+        .as_ref()
+        .expect("ERROR: error at getting metadata properties");
+
+    let mut metadata_props = HashMap::new();
+    for (key, value) in props.iter() {
+        metadata_props.insert(key.to_string(), value.to_string());
+    }
+
+    let name = props.get("metadata.name").unwrap_or_default().to_string();
+
+    sender
+        .send(MainOptions::CreateMetadata {
+            id: metadata.id,
+            permissions: metadata.permissions,
+            props: metadata_props,
+            name,
+        })
+        .expect("ERROR: error at sending option to front");
+}
+
+fn save_device(device: &GlobalObject<&DictRef>, sender: &mpsc::Sender<MainOptions>) {
+    let props = device
+        .props
+        .as_ref()
+        .expect("ERROR: error at getting device properties");
+
+    let mut device_props = HashMap::new();
+    for (key, value) in props.iter() {
+        device_props.insert(key.to_string(), value.to_string());
+    }
+
+    // Like the nodes, prefer the short name of the device.
+    let name = props
+        .get("device.nick")
+        .or_else(|| props.get("device.description"))
+        .or_else(|| props.get("device.name"))
+        .unwrap_or_default()
+        .to_string();
+
+    sender
+        .send(MainOptions::CreateDevice {
+            id: device.id,
+            permissions: device.permissions,
+            props: device_props,
+            name,
+        })
+        .expect("ERROR: error at sending option to front");
+}
+
+// Bind a metadata object to follow its properties and send them to the front.
+// The current properties are received right after binding.
+fn bind_metadata(
+    object: &GlobalObject<&DictRef>,
+    registry: &Registry,
+    sender: &mpsc::Sender<MainOptions>,
+) -> Option<BoundObject> {
+    let id = object.id;
+    let metadata: Metadata = match registry.bind(object) {
+        Ok(metadata) => metadata,
+        Err(error) => {
+            if ENABLE_DEBUG.with(|f| *f.borrow()) {
+                println!("Error binding metadata {}: {:?}", id, error);
+            }
+            return None;
+        }
+    };
+
+    let listener = metadata
+        .add_listener_local()
+        .property({
+            let sender = sender.clone();
+            move |subject, key, type_, value| {
+                sender
+                    .send(MainOptions::MetadataProperty {
+                        id,
+                        subject,
+                        key: key.map(str::to_string),
+                        type_: type_.map(str::to_string),
+                        value: value.map(str::to_string),
+                    })
+                    .expect("ERROR: error at sending option to front");
+                0
+            }
+        })
+        .register();
+
+    Some(BoundObject::Metadata {
+        proxy: metadata,
+        _listener: listener,
+    })
+}
+
+// Bind a client to receive its permissions when they are requested.
+fn bind_client(
+    object: &GlobalObject<&DictRef>,
+    registry: &Registry,
+    sender: &mpsc::Sender<MainOptions>,
+    permission_requests: &Rc<RefCell<PendingPermissionRequests>>,
+) -> Option<BoundObject> {
+    let id = object.id;
+    let client: Client = match registry.bind(object) {
+        Ok(client) => client,
+        Err(error) => {
+            if ENABLE_DEBUG.with(|f| *f.borrow()) {
+                println!("Error binding client {}: {:?}", id, error);
+            }
+            return None;
+        }
+    };
+
+    let listener = client
+        .add_listener_local()
+        .permissions({
+            let sender = sender.clone();
+            let permission_requests = permission_requests.clone();
+            move |_index, permissions| {
+                // the answer of the oldest get of this client
+                let request = permission_requests
+                    .borrow_mut()
+                    .take_oldest(id, Some(PermissionRequestKind::Get));
+                let Some(request) = request else {
+                    return;
+                };
+
+                let permissions: Vec<(u32, PermissionFlags)> = permissions
+                    .iter()
+                    .map(|permission| (permission.id(), permission.permission_flags()))
+                    .collect();
+
+                sender
+                    .send(MainOptions::ClientPermissions {
+                        id: request.id,
+                        permissions,
+                    })
+                    .expect("ERROR: error at sending option to front");
+            }
+        })
+        .register();
+
+    Some(BoundObject::Client {
+        proxy: client,
+        _listener: listener,
+    })
+}
+
 // Create or modify client and send it to the front.
 fn save_client(client: &GlobalObject<&DictRef>, sender: &mpsc::Sender<MainOptions>) {
     // println!("Client: {:?}", client);
@@ -444,15 +1646,11 @@ fn save_client(client: &GlobalObject<&DictRef>, sender: &mpsc::Sender<MainOption
 
     // Check if the client exists, if not create it.
     if !clients.iter().any(|client| client.id == id) {
-        let pid: u32 = props
+        // Both are missing for some clients (portals, sandboxed or remote clients...)
+        let pid: Option<u32> = props
             .get("pipewire.sec.pid")
-            .expect("ERROR: error at getting pid")
-            .parse()
-            .expect("ERROR: error at parsing pid");
-        let application_name: String = props
-            .get("application.name")
-            .expect("ERROR: error at getting pid")
-            .to_string();
+            .and_then(|pid| pid.parse().ok());
+        let application_name = props.get("application.name").map(str::to_string);
 
         // Send the client to the front.
         sender
@@ -664,6 +1862,121 @@ fn link_nodes_name_to_id(
     return links;
 }
 
+// Ports of a node in one direction, in the order of their channels.
+fn sorted_ports(node: &PipewireNode, direction: &str) -> Vec<PipewirePort> {
+    let mut ports: Vec<PipewirePort> = node
+        .ports
+        .iter()
+        .filter(|port| port.direction == direction)
+        .cloned()
+        .collect();
+    ports.sort_by_key(|port| {
+        port.props
+            .get("port.id")
+            .and_then(|index| index.parse::<u32>().ok())
+            .unwrap_or(port.id)
+    });
+    ports
+}
+
+// Pair the ports of a stream with the ports of a node, by channel (FL, FR...) or by order.
+// A mono node receives every channel of the stream and a mono stream goes to every channel of the node.
+fn pair_ports(stream_ports: &[PipewirePort], target_ports: &[PipewirePort]) -> Vec<(u32, u32)> {
+    let channel = |port: &PipewirePort| port.props.get("audio.channel").cloned();
+
+    if target_ports.len() == 1 {
+        return stream_ports
+            .iter()
+            .map(|port| (port.id, target_ports[0].id))
+            .collect();
+    }
+    if stream_ports.len() == 1 {
+        return target_ports
+            .iter()
+            .map(|port| (stream_ports[0].id, port.id))
+            .collect();
+    }
+
+    stream_ports
+        .iter()
+        .enumerate()
+        .filter_map(|(i, port)| {
+            let target = target_ports
+                .iter()
+                .find(|target| channel(*target).is_some() && channel(*target) == channel(port))
+                .or_else(|| target_ports.get(i))?;
+            Some((port.id, target.id))
+        })
+        .collect()
+}
+
+// Move a stream to another node by replacing its links, when there is no session manager to do it.
+// Like the ones of a session manager, the new links stay after the PwThread closes.
+fn move_stream_links(
+    stream_id: u32,
+    target_id: u32,
+    core: &Core,
+    registry: &Registry,
+) -> Vec<Link> {
+    let all_data = ALL_DATA.lock().unwrap();
+
+    let get_node = |id: u32| match all_data.get(&id) {
+        Some(PipewireData::Node(node)) => Some(node.clone()),
+        _ => None,
+    };
+    let stream = get_node(stream_id);
+    let target = get_node(target_id);
+
+    // From enum all_data, get only the PipewireData::Link
+    let mut links = Vec::new();
+    for data in all_data.iter() {
+        if let PipewireData::Link(link) = data.1 {
+            links.push(link.clone());
+        }
+    }
+    drop(all_data);
+
+    let (Some(stream), Some(target)) = (stream, target) else {
+        if ENABLE_DEBUG.with(|f| *f.borrow()) {
+            println!("Stream {} or node {} not found", stream_id, target_id);
+        }
+        return Vec::new();
+    };
+
+    // Playback streams send their output to the target, capture streams receive its output.
+    let playback = stream.kind == "playback";
+
+    // Remove the current links of the stream.
+    for link in links.iter().filter(|link| {
+        if playback {
+            link.output_node_id == stream_id
+        } else {
+            link.input_node_id == stream_id
+        }
+    }) {
+        registry.destroy_global(link.id);
+    }
+
+    let (stream_direction, target_direction) = if playback {
+        ("Output", "Input")
+    } else {
+        ("Input", "Output")
+    };
+    let stream_ports = sorted_ports(&stream, stream_direction);
+    let target_ports = sorted_ports(&target, target_direction);
+
+    pair_ports(&stream_ports, &target_ports)
+        .into_iter()
+        .map(|(stream_port, target_port)| {
+            if playback {
+                link_ports(target_port, stream_port, true, core)
+            } else {
+                link_ports(stream_port, target_port, true, core)
+            }
+        })
+        .collect()
+}
+
 fn unlink_nodes_name_to_id(nodes_name: String, input_node_id: u32, registry: &Registry) {
     let all_data = ALL_DATA.lock().unwrap();
 
@@ -748,50 +2061,56 @@ fn unlink_nodes_name_to_id(nodes_name: String, input_node_id: u32, registry: &Re
     }
 }
 
-fn create_source(
-    source_name: String,
-    audio_position: String,
-    channel_count: u32,
-    permanent: bool,
-    core: &Core,
-) -> Node {
+// Create a virtual device from the properties checked by device::DeviceOptions.
+fn create_device(props: Vec<(String, String)>, core: &Core) -> Node {
     return core
-        .create_object::<Node>(
-            &"adapter",
-            &properties! {
-                "media.class" => "Audio/Source/Virtual",
-                "node.name" => "node-pipewire:".to_string() + &source_name,
-                "node.nick" => source_name,
-                "audio.position" => audio_position,
-                "audio.channels" => channel_count.to_string(),
-                "factory.name" => "support.null-audio-sink",
-                "object.linger" => permanent.to_string(),
-            },
-        )
-        .expect("error creating virtual source");
+        .create_object::<Node>(&"adapter", &props.into_iter().collect::<PropertiesBox>())
+        .expect("error creating virtual device");
 }
 
-fn create_sink(
-    sink_name: String,
-    audio_position: String,
-    channel_count: u32,
-    permanent: bool,
-    core: &Core,
-) -> Node {
-    return core
-        .create_object::<Node>(
-            &"adapter",
-            &properties! {
-                "media.class" => "Audio/Sink/Virtual",
-                "node.name" => "node-pipewire:".to_string() + &sink_name,
-                "node.nick" => sink_name,
-                "audio.position" => audio_position,
-                "audio.channels" => channel_count.to_string(),
-                "factory.name" => "support.null-audio-sink",
-                "object.linger" => permanent.to_string(),
-            },
-        )
-        .expect("error creating virtual sink");
+// Connect a capture stream to a node and store it in the captures cache.
+fn start_capture(
+    id: u32,
+    node_id: u32,
+    processor: Box<dyn CaptureProcessor>,
+    core: &CoreRc,
+    captures: &Rc<RefCell<HashMap<u32, Capture>>>,
+    sender: &mpsc::Sender<MainOptions>,
+) {
+    let all_data = ALL_DATA.lock().unwrap();
+
+    // Sinks are captured from their monitor ports.
+    let target = match all_data.get(&node_id) {
+        Some(PipewireData::Node(node)) => Some((
+            node.props.get("object.serial").cloned(),
+            node.props
+                .get("media.class")
+                .is_some_and(|class| class.contains("Sink")),
+        )),
+        _ => None,
+    };
+    drop(all_data);
+
+    let capture = target.and_then(|(serial, capture_sink)| {
+        match Capture::new(core, node_id, serial, capture_sink, processor) {
+            Ok(capture) => Some(capture),
+            Err(error) => {
+                if ENABLE_DEBUG.with(|f| *f.borrow()) {
+                    println!("ERROR: error at creating capture {}: {}", id, error);
+                }
+                None
+            }
+        }
+    });
+
+    match capture {
+        Some(capture) => {
+            captures.borrow_mut().insert(id, capture);
+        }
+        None => sender
+            .send(MainOptions::CaptureClosed { id })
+            .expect("ERROR: error at sending option to front"),
+    }
 }
 
 fn destroy_object(id: u32, registry: &Registry) {
@@ -805,6 +2124,8 @@ fn destroy_object(id: u32, registry: &Registry) {
             PipewireData::Port(port) => port.id == id,
             PipewireData::Node(node) => node.id == id,
             PipewireData::Client(client) => client.id == id,
+            PipewireData::Metadata(metadata) => metadata.id == id,
+            PipewireData::Device(device) => device.id == id,
         })
         .clone();
 
@@ -820,24 +2141,10 @@ fn destroy_object(id: u32, registry: &Registry) {
         .1
         .clone();
 
+    let owned = permissions::owned_objects(&all_data, &OWNED_PROXIES.lock().unwrap());
     drop(all_data);
-    let allow;
-
-    match target {
-        PipewireData::Node(node) => {
-            allow = node
-                .props
-                .get("node.name")
-                .expect("ERROR: Node did not have a name prop")
-                .starts_with("node-pipewire:");
-        }
-        PipewireData::Link(_) => allow = true,
-        _ => {
-            allow = false;
-        }
-    }
 
-    if allow {
+    if permissions::destroy_allowed(&target, &owned) {
         if ENABLE_DEBUG.with(|f| *f.borrow()) {
             println!("Allowing to destroy object with id {}", id);
         }
@@ -847,12 +2154,29 @@ fn destroy_object(id: u32, registry: &Registry) {
     }
 }
 
+// Owned objects to destroy at cleanup (see permissions::cleanup_targets).
+fn cleanup_targets(include_lingering: bool) -> Vec<u32> {
+    let all_data = ALL_DATA.lock().unwrap();
+
+    let owned = permissions::owned_objects(&all_data, &OWNED_PROXIES.lock().unwrap());
+
+    permissions::cleanup_targets(
+        &all_data,
+        &owned,
+        &LINGERING_PROXIES.lock().unwrap(),
+        include_lingering,
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{create_pw_thread_internal, PW_SENDER};
+    use crate::{
+        create_pw_thread_internal,
+        device::{DeviceKind, DeviceOptions},
+        PW_SENDER,
+    };
 
     use super::*;
-    use pipewire::core::PW_ID_CORE;
     use pipewire::registry::RegistryRc;
     use std::{cell::Cell, rc::Rc, thread, time::Duration};
 
@@ -887,10 +2211,14 @@ mod tests {
 
         let test_sink_name = "test-sink-exists".to_string();
 
-        let _ = temp_pw_sender.send(PipewireOptions::CreateSink {
-            sink_name: test_sink_name.clone(),
-            audio_position: "FL,FR".to_string(),
-            channel_count: 2,
+        let _ = temp_pw_sender.send(PipewireOptions::CreateDevice {
+            props: DeviceOptions::new(
+                DeviceKind::Sink,
+                test_sink_name.clone(),
+                vec!["FL".to_string(), "FR".to_string()],
+            )
+            .props()
+            .unwrap(),
             permanent: false,
         });
 
@@ -926,10 +2254,14 @@ mod tests {
 
         let test_source_name = "test-source-exists".to_string();
 
-        let _ = temp_pw_sender.send(PipewireOptions::CreateSource {
-            source_name: test_source_name.clone(),
-            audio_position: "FL,FR".to_string(),
-            channel_count: 2,
+        let _ = temp_pw_sender.send(PipewireOptions::CreateDevice {
+            props: DeviceOptions::new(
+                DeviceKind::Source,
+                test_source_name.clone(),
+                vec!["FL".to_string(), "FR".to_string()],
+            )
+            .props()
+            .unwrap(),
             permanent: false,
         });
 
@@ -967,10 +2299,14 @@ mod tests {
 
         let test_source_name = "test-source-exists".to_string();
 
-        let _ = temp_pw_sender.send(PipewireOptions::CreateSource {
-            source_name: test_source_name.clone(),
-            audio_position: "FL,FR".to_string(),
-            channel_count: 2,
+        let _ = temp_pw_sender.send(PipewireOptions::CreateDevice {
+            props: DeviceOptions::new(
+                DeviceKind::Source,
+                test_source_name.clone(),
+                vec!["FL".to_string(), "FR".to_string()],
+            )
+            .props()
+            .unwrap(),
             permanent: false,
         });
 
@@ -1018,19 +2354,27 @@ mod tests {
 
         let test_source_name = "test-source-exists".to_string();
 
-        let _ = temp_pw_sender.send(PipewireOptions::CreateSource {
-            source_name: test_source_name.clone(),
-            audio_position: "FL,FR".to_string(),
-            channel_count: 2,
+        let _ = temp_pw_sender.send(PipewireOptions::CreateDevice {
+            props: DeviceOptions::new(
+                DeviceKind::Source,
+                test_source_name.clone(),
+                vec!["FL".to_string(), "FR".to_string()],
+            )
+            .props()
+            .unwrap(),
             permanent: false,
         });
 
         let test_sink_name = "test-sink-exists".to_string();
 
-        let _ = temp_pw_sender.send(PipewireOptions::CreateSink {
-            sink_name: test_sink_name.clone(),
-            audio_position: "FL,FR".to_string(),
-            channel_count: 2,
+        let _ = temp_pw_sender.send(PipewireOptions::CreateDevice {
+            props: DeviceOptions::new(
+                DeviceKind::Sink,
+                test_sink_name.clone(),
+                vec!["FL".to_string(), "FR".to_string()],
+            )
+            .props()
+            .unwrap(),
             permanent: false,
         });
 
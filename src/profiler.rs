@@ -0,0 +1,572 @@
+use crate::{MainOptions, PipewireDriverStats, PipewireNodeStats};
+use pipewire::{
+    registry::Registry,
+    spa::{
+        self,
+        pod::{deserialize::PodDeserializer, Pod, Value},
+        spa_interface_call_method,
+        sys::{
+            spa_hook, spa_pod, SPA_PROFILER_clock, SPA_PROFILER_driverBlock,
+            SPA_PROFILER_followerBlock, SPA_PROFILER_info,
+        },
+    },
+    sys as pw_sys,
+    types::ObjectType,
+};
+use std::{
+    collections::BTreeMap,
+    ffi::{c_void, CString},
+    mem,
+    pin::Pin,
+    ptr,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// Status of a node that finished processing its cycle.
+const NODE_FINISHED: i32 = 3;
+
+// Timing of a node in one cycle of its driver.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Block {
+    pub id: u32,
+    pub name: String,
+    pub wait_ns: Option<i64>,
+    pub busy_ns: Option<i64>,
+    pub quantum: u32,
+    pub rate: u32,
+    pub xruns: Option<u32>,
+}
+
+// One cycle of a driver, as reported by the profiler.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cycle {
+    pub quantum: u32,
+    pub rate: u32,
+    pub cpu_load: [f32; 3],
+    pub xruns: u32,
+    pub driver: Block,
+    pub followers: Vec<Block>,
+}
+
+fn as_int(value: Option<&Value>) -> Option<i32> {
+    match value {
+        Some(Value::Int(int)) => Some(*int),
+        _ => None,
+    }
+}
+
+fn as_long(value: Option<&Value>) -> Option<i64> {
+    match value {
+        Some(Value::Long(long)) => Some(*long),
+        _ => None,
+    }
+}
+
+fn as_float(value: Option<&Value>) -> Option<f32> {
+    match value {
+        Some(Value::Float(float)) => Some(*float),
+        _ => None,
+    }
+}
+
+// Parse a driverBlock or followerBlock:
+// id, name, prev_signal, signal, awake, finish, status, latency and the optional xrun count.
+fn parse_block(fields: &[Value]) -> Option<Block> {
+    let name = match fields.get(1) {
+        Some(Value::String(name)) => name.clone(),
+        _ => String::new(),
+    };
+    let signal = as_long(fields.get(3))?;
+    let awake = as_long(fields.get(4))?;
+    let finish = as_long(fields.get(5))?;
+    let status = as_int(fields.get(6))?;
+    let (quantum, rate) = match fields.get(7) {
+        Some(Value::Fraction(latency)) => (latency.num, latency.denom),
+        _ => (0, 0),
+    };
+
+    // Timings are only meaningful once the node ran in this cycle.
+    let ran = signal > 0 && awake >= signal;
+    let finished = ran && status == NODE_FINISHED && finish >= awake;
+
+    Some(Block {
+        id: as_int(fields.first())? as u32,
+        name,
+        wait_ns: Some(awake - signal).filter(|_| ran),
+        busy_ns: Some(finish - awake).filter(|_| finished),
+        quantum,
+        rate,
+        xruns: as_int(fields.get(8)).map(|xruns| xruns as u32),
+    })
+}
+
+// Parse a profile event, a struct with one object per driver.
+pub(crate) fn parse_profile(pod: &Pod) -> Vec<Cycle> {
+    let objects = match PodDeserializer::deserialize_any_from(pod.as_bytes()) {
+        Ok((_, Value::Struct(objects))) => objects,
+        _ => return Vec::new(),
+    };
+
+    let mut cycles = Vec::new();
+    for object in objects {
+        let Value::Object(object) = object else {
+            continue;
+        };
+
+        let mut cycle = Cycle::default();
+        let mut has_driver = false;
+
+        for property in object.properties {
+            let Value::Struct(fields) = property.value else {
+                continue;
+            };
+
+            if property.key == SPA_PROFILER_info {
+                // counter, cpu load (fast, medium, slow), xrun count
+                for (i, load) in cycle.cpu_load.iter_mut().enumerate() {
+                    *load = as_float(fields.get(i + 1)).unwrap_or_default();
+                }
+                cycle.xruns = as_int(fields.get(4)).unwrap_or_default() as u32;
+            } else if property.key == SPA_PROFILER_clock {
+                // flags, id, name, nsec, rate, position, duration, ...
+                if let Some(Value::Fraction(rate)) = fields.get(4) {
+                    cycle.rate = rate.denom;
+                }
+                cycle.quantum = as_long(fields.get(6)).unwrap_or_default() as u32;
+            } else if property.key == SPA_PROFILER_driverBlock {
+                if let Some(block) = parse_block(&fields) {
+                    cycle.driver = block;
+                    has_driver = true;
+                }
+            } else if property.key == SPA_PROFILER_followerBlock {
+                cycle.followers.extend(parse_block(&fields));
+            }
+        }
+
+        if has_driver {
+            cycles.push(cycle);
+        }
+    }
+
+    cycles
+}
+
+// Proxy bound to the Profiler global (libpipewire-module-profiler).
+// pipewire-rs doesn't wrap this interface, the listener is added directly on the proxy.
+// The server only profiles the graph while someone listens, so this is only bound on demand.
+pub(crate) struct Profiler {
+    proxy: ptr::NonNull<pw_sys::pw_proxy>,
+    // Need to stay allocated while the listener is registered
+    _events: Pin<Box<pw_sys::pw_profiler_events>>,
+    listener: Pin<Box<spa_hook>>,
+    data: *mut Box<dyn Fn(&Pod)>,
+}
+
+impl Profiler {
+    pub fn bind(registry: &Registry, global_id: u32, callback: Box<dyn Fn(&Pod)>) -> Option<Self> {
+        unsafe extern "C" fn profiler_events_profile(data: *mut c_void, pod: *const spa_pod) {
+            if pod.is_null() {
+                return;
+            }
+            let callback = (data as *mut Box<dyn Fn(&Pod)>).as_ref().unwrap();
+            callback(Pod::from_raw(pod));
+        }
+
+        let type_ = CString::new(ObjectType::Profiler.to_str()).ok()?;
+
+        unsafe {
+            let proxy: *mut c_void = spa_interface_call_method!(
+                registry.as_raw_ptr(),
+                pw_sys::pw_registry_methods,
+                bind,
+                global_id,
+                type_.as_ptr(),
+                pw_sys::PW_VERSION_PROFILER,
+                0
+            );
+            let proxy = ptr::NonNull::new(proxy as *mut pw_sys::pw_proxy)?;
+
+            let mut events: Pin<Box<pw_sys::pw_profiler_events>> = Box::pin(mem::zeroed());
+            events.version = pw_sys::PW_VERSION_PROFILER_EVENTS;
+            events.profile = Some(profiler_events_profile);
+
+            let data = Box::into_raw(Box::new(callback));
+            let mut listener: Pin<Box<spa_hook>> = Box::pin(mem::zeroed());
+
+            pw_sys::pw_proxy_add_object_listener(
+                proxy.as_ptr(),
+                listener.as_mut().get_unchecked_mut(),
+                events.as_ref().get_ref() as *const _ as *const c_void,
+                data as *mut c_void,
+            );
+
+            Some(Self {
+                proxy,
+                _events: events,
+                listener,
+                data,
+            })
+        }
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        spa::utils::hook::remove(*self.listener);
+        unsafe {
+            pw_sys::pw_proxy_destroy(self.proxy.as_ptr());
+            drop(Box::from_raw(self.data));
+        }
+    }
+}
+
+#[derive(Default)]
+struct NodeAccumulator {
+    name: String,
+    driver_id: u32,
+    quantum: u32,
+    rate: u32,
+    cycles: u32,
+    wait_cycles: u32,
+    wait_sum: f64,
+    max_wait: f64,
+    busy_cycles: u32,
+    busy_sum: f64,
+    max_busy: f64,
+    load_sum: f64,
+    xruns: u32,
+}
+
+impl NodeAccumulator {
+    fn add(&mut self, block: &Block, driver_id: u32, period_ns: f64) {
+        self.name = block.name.clone();
+        self.driver_id = driver_id;
+        self.quantum = block.quantum;
+        self.rate = block.rate;
+        self.cycles += 1;
+
+        if let Some(wait) = block.wait_ns {
+            let wait = wait as f64;
+            self.wait_cycles += 1;
+            self.wait_sum += wait;
+            self.max_wait = self.max_wait.max(wait);
+        }
+        if let Some(busy) = block.busy_ns {
+            let busy = busy as f64;
+            self.busy_cycles += 1;
+            self.busy_sum += busy;
+            self.max_busy = self.max_busy.max(busy);
+            if period_ns > 0.0 {
+                self.load_sum += busy / period_ns * 100.0;
+            }
+        }
+        if let Some(xruns) = block.xruns {
+            self.xruns = xruns;
+        }
+    }
+
+    fn stats(&self, id: u32) -> PipewireNodeStats {
+        let average = |sum: f64, count: u32| if count > 0 { sum / count as f64 } else { 0.0 };
+
+        PipewireNodeStats {
+            id,
+            name: self.name.clone(),
+            driver_id: self.driver_id,
+            quantum: self.quantum,
+            rate: self.rate,
+            cycles: self.cycles,
+            wait_ns: average(self.wait_sum, self.wait_cycles),
+            max_wait_ns: self.max_wait,
+            busy_ns: average(self.busy_sum, self.busy_cycles),
+            max_busy_ns: self.max_busy,
+            dsp_load: average(self.load_sum, self.busy_cycles),
+            xruns: self.xruns,
+        }
+    }
+}
+
+#[derive(Default)]
+struct DriverAccumulator {
+    name: String,
+    quantum: u32,
+    rate: u32,
+    cycles: u32,
+    cpu_load: [f32; 3],
+    xruns: u32,
+    // The driver is stored with its followers.
+    nodes: BTreeMap<u32, NodeAccumulator>,
+}
+
+// Aggregates the cycles reported by the profiler and sends them to the front every `interval`.
+pub(crate) struct ProfilerAggregator {
+    id: u32,
+    interval: Duration,
+    last_emit: Instant,
+    drivers: BTreeMap<u32, DriverAccumulator>,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+impl ProfilerAggregator {
+    pub fn new(id: u32, interval: Duration, sender: mpsc::Sender<MainOptions>) -> Self {
+        Self {
+            id,
+            interval,
+            last_emit: Instant::now(),
+            drivers: BTreeMap::new(),
+            sender,
+        }
+    }
+
+    pub fn add(&mut self, cycles: &[Cycle]) {
+        for cycle in cycles {
+            let driver_id = cycle.driver.id;
+            let period_ns = if cycle.rate > 0 {
+                cycle.quantum as f64 * 1e9 / cycle.rate as f64
+            } else {
+                0.0
+            };
+
+            let driver = self.drivers.entry(driver_id).or_default();
+            driver.name = cycle.driver.name.clone();
+            driver.quantum = cycle.quantum;
+            driver.rate = cycle.rate;
+            driver.cycles += 1;
+            driver.cpu_load = cycle.cpu_load;
+            driver.xruns = cycle.xruns;
+
+            for block in std::iter::once(&cycle.driver).chain(cycle.followers.iter()) {
+                driver
+                    .nodes
+                    .entry(block.id)
+                    .or_default()
+                    .add(block, driver_id, period_ns);
+            }
+        }
+
+        if !self.drivers.is_empty() && self.last_emit.elapsed() >= self.interval {
+            self.last_emit = Instant::now();
+
+            // The front could be gone, there is nothing to do in that case.
+            let _ = self.sender.send(MainOptions::ProfilerStats {
+                id: self.id,
+                drivers: self.take_stats(),
+            });
+        }
+    }
+
+    // Returns the stats accumulated since the last call.
+    fn take_stats(&mut self) -> Vec<PipewireDriverStats> {
+        let drivers = mem::take(&mut self.drivers);
+
+        drivers
+            .into_iter()
+            .map(|(id, driver)| {
+                let period_ns = if driver.rate > 0 {
+                    driver.quantum as f64 * 1e9 / driver.rate as f64
+                } else {
+                    0.0
+                };
+
+                // the driver first, then its followers
+                let mut nodes: Vec<PipewireNodeStats> = driver
+                    .nodes
+                    .iter()
+                    .map(|(node_id, node)| node.stats(*node_id))
+                    .collect();
+                nodes.sort_by_key(|node| node.id != id);
+
+                PipewireDriverStats {
+                    id,
+                    name: driver.name,
+                    quantum: driver.quantum,
+                    rate: driver.rate,
+                    cycles: driver.cycles,
+                    period_ns,
+                    cpu_load: driver.cpu_load.to_vec(),
+                    xruns: driver.xruns,
+                    nodes,
+                }
+            })
+            .collect()
+    }
+}
+
+// Interval of the stats kept in the graph for getNodeStats().
+pub(crate) const NODE_STATS_INTERVAL: Duration = Duration::from_secs(1);
+// Time left to the profiler to send the first interval before the stats are ready anyway (no node ran).
+const NODE_STATS_GRACE: Duration = Duration::from_millis(500);
+// The profiler is stopped when getNodeStats() wasn't called for this long.
+pub(crate) const NODE_STATS_IDLE: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum NodeStatsEvent {
+    // The first interval ended, the stats of the graph are up to date.
+    Ready,
+    // getNodeStats() wasn't called for NODE_STATS_IDLE, the profiler can be stopped.
+    Idle,
+}
+
+// Calls to getNodeStats() while the profiler runs for it.
+#[derive(Debug)]
+pub(crate) struct NodeStatsRequests {
+    started: Instant,
+    last_request: Instant,
+    ready: bool,
+}
+
+impl NodeStatsRequests {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            last_request: now,
+            ready: false,
+        }
+    }
+
+    pub fn request(&mut self, now: Instant) {
+        self.last_request = now;
+    }
+
+    // Called periodically, Ready is returned once.
+    pub fn poll(&mut self, now: Instant) -> Option<NodeStatsEvent> {
+        if now.duration_since(self.last_request) >= NODE_STATS_IDLE {
+            Some(NodeStatsEvent::Idle)
+        } else if !self.ready
+            && now.duration_since(self.started) >= NODE_STATS_INTERVAL + NODE_STATS_GRACE
+        {
+            self.ready = true;
+            Some(NodeStatsEvent::Ready)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipewire::spa::{
+        pod::{serialize::PodSerializer, Object, Property},
+        utils::{Fraction, SpaTypes},
+    };
+
+    fn block(id: i32, name: &str, signal: i64, awake: i64, finish: i64) -> Value {
+        Value::Struct(vec![
+            Value::Int(id),
+            Value::String(name.to_string()),
+            Value::Long(0),
+            Value::Long(signal),
+            Value::Long(awake),
+            Value::Long(finish),
+            Value::Int(NODE_FINISHED),
+            Value::Fraction(Fraction {
+                num: 256,
+                denom: 48000,
+            }),
+            Value::Int(2),
+        ])
+    }
+
+    #[test]
+    fn parses_and_aggregates_a_profile() {
+        let object = Object {
+            type_: SpaTypes::ObjectProfiler.as_raw(),
+            id: 0,
+            properties: vec![
+                Property::new(
+                    SPA_PROFILER_info,
+                    Value::Struct(vec![
+                        Value::Long(1),
+                        Value::Float(0.1),
+                        Value::Float(0.2),
+                        Value::Float(0.3),
+                        Value::Int(5),
+                    ]),
+                ),
+                Property::new(
+                    SPA_PROFILER_clock,
+                    Value::Struct(vec![
+                        Value::Int(0),
+                        Value::Int(30),
+                        Value::String(String::from("driver")),
+                        Value::Long(0),
+                        Value::Fraction(Fraction {
+                            num: 1,
+                            denom: 48000,
+                        }),
+                        Value::Long(0),
+                        Value::Long(256),
+                    ]),
+                ),
+                Property::new(
+                    SPA_PROFILER_driverBlock,
+                    block(30, "driver", 1000, 2000, 12000),
+                ),
+                Property::new(
+                    SPA_PROFILER_followerBlock,
+                    block(40, "follower", 1000, 3000, 3000 + 533_333),
+                ),
+            ],
+        };
+        let bytes = PodSerializer::serialize(
+            std::io::Cursor::new(Vec::new()),
+            &Value::Struct(vec![Value::Object(object)]),
+        )
+        .expect("ERROR: error at serializing profile")
+        .0
+        .into_inner();
+        let pod = Pod::from_bytes(&bytes).expect("ERROR: error at reading profile");
+
+        let cycles = parse_profile(pod);
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].quantum, 256);
+        assert_eq!(cycles[0].rate, 48000);
+        assert_eq!(cycles[0].followers.len(), 1);
+
+        let (sender, receiver) = mpsc::channel();
+        let mut aggregator = ProfilerAggregator::new(1, Duration::from_millis(0), sender);
+        aggregator.add(&cycles);
+
+        match receiver
+            .try_recv()
+            .expect("ERROR: aggregator did not emit stats")
+        {
+            MainOptions::ProfilerStats { id, drivers } => {
+                assert_eq!(id, 1);
+                assert_eq!(drivers.len(), 1);
+                assert_eq!(drivers[0].id, 30);
+                assert_eq!(drivers[0].xruns, 5);
+
+                let nodes = &drivers[0].nodes;
+                assert_eq!(nodes[0].id, 30);
+                assert_eq!(nodes[0].busy_ns, 10000.0);
+
+                // 533333 ns of the 5333333 ns period
+                assert_eq!(nodes[1].driver_id, 30);
+                assert_eq!(nodes[1].wait_ns, 2000.0);
+                assert!((nodes[1].dsp_load - 10.0).abs() < 0.01);
+            }
+            _ => panic!("ERROR: unexpected option sent by aggregator"),
+        }
+    }
+
+    #[test]
+    fn stops_the_node_stats_when_idle() {
+        let start = Instant::now();
+        let mut requests = NodeStatsRequests::new(start);
+
+        assert_eq!(requests.poll(start + NODE_STATS_INTERVAL), None);
+        let ready = start + NODE_STATS_INTERVAL + NODE_STATS_GRACE;
+        assert_eq!(requests.poll(ready), Some(NodeStatsEvent::Ready));
+        assert_eq!(requests.poll(ready), None);
+
+        // every call postpones the stop
+        requests.request(start + Duration::from_secs(20));
+        assert_eq!(requests.poll(start + NODE_STATS_IDLE), None);
+        assert_eq!(
+            requests.poll(start + Duration::from_secs(20) + NODE_STATS_IDLE),
+            Some(NodeStatsEvent::Idle)
+        );
+    }
+}
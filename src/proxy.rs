@@ -1,15 +1,26 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, rc::Rc, sync::mpsc};
 
-use pipewire::proxy::{Proxy, ProxyListener};
+use crate::MainOptions;
+
+use pipewire::{
+    client::{Client, ClientListener},
+    link::{Link, LinkListener},
+    metadata::{Metadata, MetadataListener},
+    node::{Node, NodeListener},
+    port::{Port, PortListener},
+    proxy::{Proxy, ProxyListener},
+};
 
 pub(crate) struct ProxyWrapper {
     internal: Rc<RefCell<ProxyInternal>>,
 }
 
 impl ProxyWrapper {
-    pub fn new(proxy: Proxy) -> Self {
+    // The global id of the proxy is sent to the front once bound, to know the objects this connection owns.
+    // `lingering` tells whether the object outlives its proxy (object.linger).
+    pub fn new(proxy: Proxy, lingering: bool, sender: &mpsc::Sender<MainOptions>) -> Self {
         return Self {
-            internal: ProxyInternal::new(proxy),
+            internal: ProxyInternal::new(proxy, lingering, sender.clone()),
         };
     }
 
@@ -25,7 +36,7 @@ struct ProxyInternal {
 }
 
 impl ProxyInternal {
-    fn new(proxy: Proxy) -> Rc<RefCell<Self>> {
+    fn new(proxy: Proxy, lingering: bool, sender: mpsc::Sender<MainOptions>) -> Rc<RefCell<Self>> {
         let pxw = Rc::new(RefCell::new(Self {
             proxy: proxy,
             global_id: 0,
@@ -42,6 +53,10 @@ impl ProxyInternal {
                         let mut borrowed = pxw.borrow_mut();
                         borrowed.global_id = id;
                         borrowed.listener = None;
+
+                        sender
+                            .send(MainOptions::ObjectOwned { id, lingering })
+                            .expect("ERROR: error at sending option to front");
                     }
                 })
                 .register(),
@@ -52,3 +67,28 @@ impl ProxyInternal {
         return pxw;
     }
 }
+
+// Proxy bound to a global of the registry, to listen its info and params.
+// The listener stops when this is dropped (usually when the global is removed).
+pub(crate) enum BoundObject {
+    Node {
+        proxy: Node,
+        _listener: NodeListener,
+    },
+    Port {
+        _proxy: Port,
+        _listener: PortListener,
+    },
+    Link {
+        _proxy: Link,
+        _listener: LinkListener,
+    },
+    Metadata {
+        proxy: Metadata,
+        _listener: MetadataListener,
+    },
+    Client {
+        proxy: Client,
+        _listener: ClientListener,
+    },
+}
@@ -0,0 +1,633 @@
+use crate::PipewireData;
+use std::collections::{BTreeSet, HashMap};
+
+// Selectors over the objects of the graph, inspired by CSS:
+//
+//   selector  = chain ("," chain)*
+//   chain     = compound (">" compound)*
+//   compound  = type? ("#" id)? ("[" key (operator value)? "]")*
+//   type      = "node" | "port" | "link" | "client" | "metadata" | "device" | "*"
+//   operator  = "=" | "!=" | "^=" (starts with) | "$=" (ends with) | "*=" (contains)
+//
+// Values can be quoted with " or '. Keys are the fields of the objects (id, name, direction, category...)
+// or their props (media.class, node.name...). `a > b` selects the objects `b` related to an object `a`:
+// - client > object: the objects created by the client (client.id prop),
+// - device > node: the nodes of the device (device.id prop),
+// - node > port: the ports of the node, node > link: the links from the node,
+// - port > link: the links from the port,
+// - link > node, link > port: the node and the port the link goes to.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Node,
+    Port,
+    Link,
+    Client,
+    Metadata,
+    Device,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Exists,
+    Equals,
+    NotEquals,
+    StartsWith,
+    EndsWith,
+    Contains,
+}
+
+#[derive(Clone, Debug)]
+struct Predicate {
+    key: String,
+    operator: Operator,
+    value: String,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Compound {
+    // None matches every type.
+    kind: Option<Kind>,
+    predicates: Vec<Predicate>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at position {}", message, self.position))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() != Some(expected) {
+            return self.error(&format!("Expected {:?}", expected));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    // Read characters while `accept` returns true.
+    fn read_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&accept) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn parse(&mut self) -> Result<Vec<Vec<Compound>>, String> {
+        let mut chains = Vec::new();
+
+        loop {
+            chains.push(self.parse_chain()?);
+
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(chains),
+                Some(',') => self.position += 1,
+                Some(_) => return self.error("Unexpected character"),
+            }
+        }
+    }
+
+    fn parse_chain(&mut self) -> Result<Vec<Compound>, String> {
+        self.skip_whitespace();
+        let mut chain = vec![self.parse_compound()?];
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('>') {
+                return Ok(chain);
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            chain.push(self.parse_compound()?);
+        }
+    }
+
+    fn parse_compound(&mut self) -> Result<Compound, String> {
+        let mut compound = Compound::default();
+        let start = self.position;
+
+        if self.peek() == Some('*') {
+            self.position += 1;
+        } else {
+            let name = self.read_while(|c| c.is_ascii_alphabetic());
+            compound.kind = match name.as_str() {
+                "" => None,
+                "node" => Some(Kind::Node),
+                "port" => Some(Kind::Port),
+                "link" => Some(Kind::Link),
+                "client" => Some(Kind::Client),
+                "metadata" => Some(Kind::Metadata),
+                "device" => Some(Kind::Device),
+                _ => {
+                    self.position = start;
+                    return self.error(&format!("Unknown type {:?}", name));
+                }
+            };
+        }
+
+        if self.peek() == Some('#') {
+            self.position += 1;
+            let id = self.read_while(|c| c.is_ascii_digit());
+            if id.is_empty() {
+                return self.error("Expected an id");
+            }
+            compound.predicates.push(Predicate {
+                key: String::from("id"),
+                operator: Operator::Equals,
+                value: id,
+            });
+        }
+
+        while self.peek() == Some('[') {
+            compound.predicates.push(self.parse_predicate()?);
+        }
+
+        if self.position == start {
+            return self.error("Expected a selector");
+        }
+
+        Ok(compound)
+    }
+
+    fn parse_predicate(&mut self) -> Result<Predicate, String> {
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        let key = self.read_while(|c| !c.is_whitespace() && !"=!^$*]".contains(c));
+        if key.is_empty() {
+            return self.error("Expected a key");
+        }
+        self.skip_whitespace();
+
+        let operator = match self.peek() {
+            Some(']') => {
+                self.position += 1;
+                return Ok(Predicate {
+                    key,
+                    operator: Operator::Exists,
+                    value: String::new(),
+                });
+            }
+            Some('=') => Operator::Equals,
+            Some('!') => Operator::NotEquals,
+            Some('^') => Operator::StartsWith,
+            Some('$') => Operator::EndsWith,
+            Some('*') => Operator::Contains,
+            _ => return self.error("Expected an operator"),
+        };
+        if operator != Operator::Equals {
+            self.position += 1;
+        }
+        self.expect('=')?;
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                self.position += 1;
+                let mut value = String::new();
+                loop {
+                    match self.peek() {
+                        None => return self.error("Unterminated string"),
+                        Some(c) if c == quote => break,
+                        Some('\\') if self.position + 1 < self.chars.len() => {
+                            value.push(self.chars[self.position + 1]);
+                            self.position += 2;
+                        }
+                        Some(c) => {
+                            value.push(c);
+                            self.position += 1;
+                        }
+                    }
+                }
+                self.position += 1;
+                value
+            }
+            _ => self.read_while(|c| c != ']').trim_end().to_string(),
+        };
+
+        self.skip_whitespace();
+        self.expect(']')?;
+
+        Ok(Predicate {
+            key,
+            operator,
+            value,
+        })
+    }
+}
+
+fn kind_of(data: &PipewireData) -> Kind {
+    match data {
+        PipewireData::Node(_) => Kind::Node,
+        PipewireData::Port(_) => Kind::Port,
+        PipewireData::Link(_) => Kind::Link,
+        PipewireData::Client(_) => Kind::Client,
+        PipewireData::Metadata(_) => Kind::Metadata,
+        PipewireData::Device(_) => Kind::Device,
+    }
+}
+
+pub(crate) fn props_of(data: &PipewireData) -> &HashMap<String, String> {
+    match data {
+        PipewireData::Node(node) => &node.props,
+        PipewireData::Port(port) => &port.props,
+        PipewireData::Link(link) => &link.props,
+        PipewireData::Client(client) => &client.props,
+        PipewireData::Metadata(metadata) => &metadata.props,
+        PipewireData::Device(device) => &device.props,
+    }
+}
+
+// Value of a field of the object, or of one of its props.
+fn attribute(data: &PipewireData, key: &str) -> Option<String> {
+    let field = match (data, key) {
+        (PipewireData::Node(node), "id") => Some(node.id.to_string()),
+        (PipewireData::Node(node), "name") => Some(node.name.clone()),
+        (PipewireData::Node(node), "node_direction") => Some(node.node_direction.clone()),
+        (PipewireData::Node(node), "node_type") => Some(node.node_type.clone()),
+        (PipewireData::Node(node), "category") => Some(node.category.clone()),
+        (PipewireData::Node(node), "kind") => Some(node.kind.clone()),
+        (PipewireData::Node(node), "is_monitor") => Some(node.is_monitor.to_string()),
+        (PipewireData::Port(port), "id") => Some(port.id.to_string()),
+        (PipewireData::Port(port), "name") => Some(port.name.clone()),
+        (PipewireData::Port(port), "direction") => Some(port.direction.clone()),
+        (PipewireData::Port(port), "node_id") => Some(port.node_id.to_string()),
+        (PipewireData::Link(link), "id") => Some(link.id.to_string()),
+        (PipewireData::Link(link), "input_node_id") => Some(link.input_node_id.to_string()),
+        (PipewireData::Link(link), "input_port_id") => Some(link.input_port_id.to_string()),
+        (PipewireData::Link(link), "output_node_id") => Some(link.output_node_id.to_string()),
+        (PipewireData::Link(link), "output_port_id") => Some(link.output_port_id.to_string()),
+        (PipewireData::Client(client), "id") => Some(client.id.to_string()),
+        (PipewireData::Client(client), "pid") => client.pid.map(|pid| pid.to_string()),
+        (PipewireData::Client(client), "application_name") => client.application_name.clone(),
+        (PipewireData::Client(client), "app_id") => client.app_id.clone(),
+        (PipewireData::Metadata(metadata), "id") => Some(metadata.id.to_string()),
+        (PipewireData::Metadata(metadata), "name") => Some(metadata.name.clone()),
+        (PipewireData::Device(device), "id") => Some(device.id.to_string()),
+        (PipewireData::Device(device), "name") => Some(device.name.clone()),
+        _ => None,
+    };
+
+    field.or_else(|| props_of(data).get(key).cloned())
+}
+
+fn matches(data: &PipewireData, compound: &Compound) -> bool {
+    if compound.kind.is_some_and(|kind| kind != kind_of(data)) {
+        return false;
+    }
+
+    compound.predicates.iter().all(|predicate| {
+        let value = attribute(data, &predicate.key);
+        let expected = predicate.value.as_str();
+
+        match (predicate.operator, value) {
+            (Operator::Exists, value) => value.is_some(),
+            (Operator::NotEquals, value) => value.as_deref() != Some(expected),
+            (_, None) => false,
+            (Operator::Equals, Some(value)) => value == expected,
+            (Operator::StartsWith, Some(value)) => value.starts_with(expected),
+            (Operator::EndsWith, Some(value)) => value.ends_with(expected),
+            (Operator::Contains, Some(value)) => value.contains(expected),
+        }
+    })
+}
+
+// Whether `child` is related to `parent` (see the `>` relations above).
+fn related(parent: &PipewireData, child: &PipewireData) -> bool {
+    match (parent, child) {
+        (PipewireData::Client(client), _) => {
+            props_of(child).get("client.id") == Some(&client.id.to_string())
+        }
+        (PipewireData::Device(device), PipewireData::Node(node)) => {
+            node.props.get("device.id") == Some(&device.id.to_string())
+        }
+        (PipewireData::Node(node), PipewireData::Port(port)) => port.node_id == node.id,
+        (PipewireData::Node(node), PipewireData::Link(link)) => link.output_node_id == node.id,
+        (PipewireData::Port(port), PipewireData::Link(link)) => link.output_port_id == port.id,
+        (PipewireData::Link(link), PipewireData::Node(node)) => link.input_node_id == node.id,
+        (PipewireData::Link(link), PipewireData::Port(port)) => link.input_port_id == port.id,
+        _ => false,
+    }
+}
+
+// Name of the type of an object, as used in the selectors.
+pub(crate) fn type_name(data: &PipewireData) -> &'static str {
+    match kind_of(data) {
+        Kind::Node => "node",
+        Kind::Port => "port",
+        Kind::Link => "link",
+        Kind::Client => "client",
+        Kind::Metadata => "metadata",
+        Kind::Device => "device",
+    }
+}
+
+pub(crate) fn id_of(data: &PipewireData) -> u32 {
+    match data {
+        PipewireData::Node(node) => node.id,
+        PipewireData::Port(port) => port.id,
+        PipewireData::Link(link) => link.id,
+        PipewireData::Client(client) => client.id,
+        PipewireData::Metadata(metadata) => metadata.id,
+        PipewireData::Device(device) => device.id,
+    }
+}
+
+// A parsed selector, to match objects without parsing it again.
+#[derive(Clone, Debug)]
+pub(crate) struct Selector {
+    chains: Vec<Vec<Compound>>,
+}
+
+impl Selector {
+    pub(crate) fn parse(selector: &str) -> Result<Selector, String> {
+        let chains = Parser {
+            chars: selector.chars().collect(),
+            position: 0,
+        }
+        .parse()?;
+
+        Ok(Selector { chains })
+    }
+
+    // Whether `data` is selected. It doesn't need to be in `all_data`, but the objects it's related to do.
+    pub(crate) fn matches(
+        &self,
+        all_data: &HashMap<u32, PipewireData>,
+        data: &PipewireData,
+    ) -> bool {
+        self.chains
+            .iter()
+            .any(|chain| matches_chain(all_data, chain, data))
+    }
+}
+
+fn matches_chain(
+    all_data: &HashMap<u32, PipewireData>,
+    chain: &[Compound],
+    data: &PipewireData,
+) -> bool {
+    let (last, parents) = match chain.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    matches(data, last)
+        && (parents.is_empty()
+            || all_data
+                .values()
+                .any(|parent| related(parent, data) && matches_chain(all_data, parents, parent)))
+}
+
+// Ids of the nodes, ports and links created by a client (client.id prop), with the ports of its nodes, sorted.
+pub(crate) fn objects_of_client(all_data: &HashMap<u32, PipewireData>, client_id: u32) -> Vec<u32> {
+    let client_id = client_id.to_string();
+    let is_owned = |data: &PipewireData| props_of(data).get("client.id") == Some(&client_id);
+
+    let mut ids: Vec<u32> = all_data
+        .values()
+        .filter(|data| match data {
+            PipewireData::Node(_) | PipewireData::Link(_) => is_owned(data),
+            PipewireData::Port(port) => {
+                is_owned(data) || all_data.get(&port.node_id).is_some_and(is_owned)
+            }
+            PipewireData::Client(_) | PipewireData::Metadata(_) | PipewireData::Device(_) => false,
+        })
+        .map(id_of)
+        .collect();
+
+    ids.sort();
+    ids
+}
+
+// Ids of the objects matching the selector, sorted.
+pub(crate) fn query(
+    all_data: &HashMap<u32, PipewireData>,
+    selector: &str,
+) -> Result<Vec<u32>, String> {
+    let selector = Selector::parse(selector)?;
+
+    let mut result = BTreeSet::new();
+
+    for chain in selector.chains.iter() {
+        let mut current: Vec<&PipewireData> = all_data
+            .values()
+            .filter(|data| matches(data, &chain[0]))
+            .collect();
+
+        for compound in chain[1..].iter() {
+            current = all_data
+                .values()
+                .filter(|data| matches(data, compound))
+                .filter(|data| current.iter().any(|parent| related(parent, data)))
+                .collect();
+        }
+
+        result.extend(current.iter().map(|data| id_of(data)));
+    }
+
+    Ok(result.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipewireClient, PipewireDevice, PipewireLink, PipewireNode, PipewirePort};
+    use pipewire::permissions::PermissionFlags;
+
+    fn props(props: &[(&str, &str)]) -> HashMap<String, String> {
+        props
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn graph() -> HashMap<u32, PipewireData> {
+        let mut all_data = HashMap::new();
+
+        all_data.insert(
+            1,
+            PipewireData::Client(PipewireClient {
+                id: 1,
+                permissions: PermissionFlags::all(),
+                pid: Some(100),
+                application_name: Some(String::from("Firefox")),
+                props: props(&[("application.name", "Firefox")]),
+                process_binary: None,
+                process_user: None,
+                access: None,
+                is_flatpak: false,
+                app_id: None,
+                icon_name: None,
+            }),
+        );
+
+        let node = |id: u32, node_props: &[(&str, &str)]| {
+            PipewireData::Node(PipewireNode {
+                id,
+                permissions: PermissionFlags::all(),
+                props: props(node_props),
+                name: format!("node {}", id),
+                node_direction: String::new(),
+                node_type: String::from("Audio"),
+                category: String::new(),
+                kind: String::new(),
+                is_monitor: false,
+                ports: Vec::new(),
+                quantum: None,
+                rate: None,
+                process_latency: None,
+                stats: None,
+            })
+        };
+        all_data.insert(
+            2,
+            node(
+                2,
+                &[("media.class", "Stream/Output/Audio"), ("client.id", "1")],
+            ),
+        );
+        all_data.insert(
+            3,
+            node(
+                3,
+                &[
+                    ("media.class", "Audio/Sink"),
+                    ("node.name", "alsa_output.usb-1"),
+                    ("device.id", "50"),
+                ],
+            ),
+        );
+
+        let port = |id: u32, node_id: u32, direction: &str| {
+            PipewireData::Port(PipewirePort {
+                id,
+                permissions: PermissionFlags::all(),
+                props: HashMap::new(),
+                node_id,
+                name: format!("port {}", id),
+                direction: direction.to_string(),
+                format: None,
+                formats: Vec::new(),
+                latency: Vec::new(),
+                process_latency: None,
+            })
+        };
+        all_data.insert(20, port(20, 2, "Output"));
+        all_data.insert(30, port(30, 3, "Input"));
+        all_data.insert(31, port(31, 3, "Output"));
+
+        all_data.insert(
+            50,
+            PipewireData::Device(PipewireDevice {
+                id: 50,
+                permissions: PermissionFlags::all(),
+                props: props(&[("device.api", "alsa")]),
+                name: String::from("USB Audio"),
+            }),
+        );
+
+        all_data.insert(
+            40,
+            PipewireData::Link(PipewireLink {
+                id: 40,
+                permissions: PermissionFlags::all(),
+                props: HashMap::new(),
+                input_node_id: 3,
+                input_port_id: 30,
+                output_node_id: 2,
+                output_port_id: 20,
+                format: None,
+            }),
+        );
+
+        all_data
+    }
+
+    #[test]
+    fn matches_the_predicates() {
+        let all_data = graph();
+
+        assert_eq!(
+            query(
+                &all_data,
+                r#"node[media.class^="Audio/Sink"][node.name*=usb]"#
+            ),
+            Ok(vec![3])
+        );
+        assert_eq!(query(&all_data, "node[node.name]"), Ok(vec![3]));
+        assert_eq!(query(&all_data, "node[node.name!=x]"), Ok(vec![2, 3]));
+        assert_eq!(query(&all_data, "port[direction=Output]"), Ok(vec![20, 31]));
+        assert_eq!(query(&all_data, "#3, #40"), Ok(vec![3, 40]));
+        assert_eq!(query(&all_data, "*[ name $= '3' ]"), Ok(vec![3]));
+    }
+
+    #[test]
+    fn follows_the_relations() {
+        let all_data = graph();
+
+        assert_eq!(
+            query(
+                &all_data,
+                "client[application.name=Firefox] > node > port[direction=Output]"
+            ),
+            Ok(vec![20])
+        );
+        assert_eq!(query(&all_data, "node#2 > link > node"), Ok(vec![3]));
+        assert_eq!(query(&all_data, "node#3 > port"), Ok(vec![30, 31]));
+        assert_eq!(
+            query(
+                &all_data,
+                "device[device.api=alsa] > node > port[direction=Input]"
+            ),
+            Ok(vec![30])
+        );
+        assert_eq!(query(&all_data, "device#50 > node"), Ok(vec![3]));
+    }
+
+    #[test]
+    fn matches_single_objects() {
+        let all_data = graph();
+        let selector = Selector::parse("node#3 > port, client > node").unwrap();
+
+        assert!(selector.matches(&all_data, &all_data[&30]));
+        assert!(selector.matches(&all_data, &all_data[&2]));
+        assert!(!selector.matches(&all_data, &all_data[&20]));
+        assert!(!selector.matches(&all_data, &all_data[&3]));
+    }
+
+    #[test]
+    fn lists_the_objects_of_a_client() {
+        let all_data = graph();
+
+        assert_eq!(objects_of_client(&all_data, 1), vec![2, 20]);
+        assert_eq!(objects_of_client(&all_data, 2), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn rejects_invalid_selectors() {
+        let all_data = graph();
+
+        assert!(query(&all_data, "devices").is_err());
+        assert!(query(&all_data, "node[").is_err());
+        assert!(query(&all_data, "node[a=\"b]").is_err());
+        assert!(query(&all_data, "node >").is_err());
+        assert!(query(&all_data, "").is_err());
+    }
+}
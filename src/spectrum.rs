@@ -0,0 +1,234 @@
+use crate::{
+    capture::{to_dbfs, CaptureProcessor},
+    MainOptions,
+};
+use std::{
+    f64::consts::PI,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// Computes the magnitude (in dBFS) of `bands` logarithmically spaced frequency bands
+// of the last `fft_size` samples and sends them to the front every `interval`.
+pub(crate) struct SpectrumAnalyzer {
+    id: u32,
+    bands: usize,
+    interval: Duration,
+    last_emit: Instant,
+    // Ring buffer with the last `fft_size` samples, mixed down to mono.
+    history: Vec<f32>,
+    position: usize,
+    filled: usize,
+    window: Vec<f32>,
+    window_sum: f32,
+    twiddles: Vec<(f32, f32)>,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+impl SpectrumAnalyzer {
+    // `fft_size` must be a power of two and `bands` must be between 1 and `fft_size / 2`.
+    pub fn new(
+        id: u32,
+        fft_size: usize,
+        bands: usize,
+        interval: Duration,
+        sender: mpsc::Sender<MainOptions>,
+    ) -> Self {
+        // Hann window, to reduce the spectral leakage.
+        let window: Vec<f32> = (0..fft_size)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / fft_size as f64).cos()) as f32)
+            .collect();
+        let window_sum = window.iter().sum();
+
+        let twiddles = (0..fft_size / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f64 / fft_size as f64;
+                (angle.cos() as f32, angle.sin() as f32)
+            })
+            .collect();
+
+        Self {
+            id,
+            bands,
+            interval,
+            last_emit: Instant::now(),
+            history: vec![0.0; fft_size],
+            position: 0,
+            filled: 0,
+            window,
+            window_sum,
+            twiddles,
+            sender,
+        }
+    }
+
+    fn compute_bands(&self) -> Vec<f32> {
+        let fft_size = self.history.len();
+
+        // Unroll the ring buffer, oldest sample first.
+        let mut re: Vec<f32> = (0..fft_size)
+            .map(|i| self.history[(self.position + i) % fft_size] * self.window[i])
+            .collect();
+        let mut im = vec![0.0; fft_size];
+
+        fft(&mut re, &mut im, &self.twiddles);
+
+        // Scale so a full scale sine wave is 0 dBFS.
+        let half = fft_size / 2;
+        let magnitudes: Vec<f32> = (0..=half)
+            .map(|bin| 2.0 * (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() / self.window_sum)
+            .collect();
+
+        // Split bins 1..=half (DC is ignored) in logarithmically spaced bands,
+        // every band contains at least one bin.
+        let mut bands = Vec::with_capacity(self.bands);
+        let mut start = 1;
+        for band in 0..self.bands {
+            let end = if band + 1 == self.bands {
+                half + 1
+            } else {
+                let edge = (half as f64)
+                    .powf((band + 1) as f64 / self.bands as f64)
+                    .round() as usize;
+                edge.max(start + 1).min(half + 1)
+            };
+
+            let peak = magnitudes
+                .get(start..end)
+                .map_or(0.0, |bins| bins.iter().cloned().fold(0.0, f32::max));
+            bands.push(to_dbfs(peak));
+
+            start = end;
+        }
+
+        bands
+    }
+}
+
+impl CaptureProcessor for SpectrumAnalyzer {
+    fn process(&mut self, samples: &[f32], channels: usize) {
+        let fft_size = self.history.len();
+
+        for frame in samples.chunks_exact(channels) {
+            self.history[self.position] = frame.iter().sum::<f32>() / channels as f32;
+            self.position = (self.position + 1) % fft_size;
+            self.filled = (self.filled + 1).min(fft_size);
+        }
+
+        if self.filled == fft_size && self.last_emit.elapsed() >= self.interval {
+            self.last_emit = Instant::now();
+
+            // The front could be gone, there is nothing to do in that case.
+            let _ = self.sender.send(MainOptions::SpectrumBands {
+                id: self.id,
+                bands: self.compute_bands(),
+            });
+        }
+    }
+}
+
+// In-place iterative radix-2 FFT. The length of `re` and `im` must be a power of two
+// and `twiddles` must contain e^(-2πik/n) for k in 0..n/2.
+fn fft(re: &mut [f32], im: &mut [f32], twiddles: &[(f32, f32)]) {
+    let n = re.len();
+
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let stride = n / len;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_re, w_im) = twiddles[k * stride];
+                let a = start + k;
+                let b = a + len / 2;
+
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_band_of_a_sine_wave() {
+        let (sender, receiver) = mpsc::channel();
+        let fft_size = 1024;
+        let mut analyzer = SpectrumAnalyzer::new(1, fft_size, 10, Duration::from_millis(0), sender);
+
+        // Full scale sine wave centered on bin 100, in stereo.
+        let samples: Vec<f32> = (0..fft_size)
+            .flat_map(|i| {
+                let sample = (2.0 * PI * 100.0 * i as f64 / fft_size as f64).sin() as f32;
+                vec![sample, sample]
+            })
+            .collect();
+        analyzer.process(&samples, 2);
+
+        match receiver
+            .try_recv()
+            .expect("ERROR: analyzer did not emit bands")
+        {
+            MainOptions::SpectrumBands { id, bands } => {
+                assert_eq!(id, 1);
+                assert_eq!(bands.len(), 10);
+
+                // Bands edges for 512 bins: 2, 3, 6, 12, 23, 42, 79, 147, 274, 513
+                let loudest =
+                    bands
+                        .iter()
+                        .enumerate()
+                        .fold((0, f32::NEG_INFINITY), |max, (i, band)| {
+                            if *band > max.1 {
+                                (i, *band)
+                            } else {
+                                max
+                            }
+                        });
+                assert_eq!(loudest.0, 7);
+                assert!(loudest.1.abs() < 0.1);
+            }
+            _ => panic!("ERROR: unexpected option sent by analyzer"),
+        }
+    }
+
+    #[test]
+    fn gives_every_band_at_least_one_bin() {
+        let (sender, _receiver) = mpsc::channel();
+        let mut analyzer = SpectrumAnalyzer::new(1, 16, 8, Duration::from_millis(0), sender);
+
+        // Some noise, so no bin is empty.
+        let samples: Vec<f32> = (0..16)
+            .map(|i| ((i * 7919) % 17) as f32 / 17.0 - 0.5)
+            .collect();
+        analyzer.process(&samples, 1);
+
+        let bands = analyzer.compute_bands();
+        assert_eq!(bands.len(), 8);
+        assert!(bands.iter().all(|band| band.is_finite()));
+    }
+}
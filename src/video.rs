@@ -0,0 +1,559 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+};
+
+use pipewire::{
+    core::CoreRc,
+    properties::properties,
+    spa::{
+        param::{
+            format::{FormatProperties, MediaSubtype, MediaType},
+            format_utils,
+            video::{VideoFormat, VideoInfoRaw},
+            ParamType,
+        },
+        pod::{
+            object, property, serialize::PodSerializer, ChoiceValue, Object, Pod, Property, Value,
+        },
+        sys as spa_sys,
+        utils::{Choice, ChoiceEnum, ChoiceFlags, Direction, Fraction, Id, Rectangle, SpaTypes},
+    },
+    stream::{Stream, StreamFlags, StreamListener, StreamRc, StreamState},
+};
+
+use crate::{device::NAME_PREFIX, MainOptions};
+
+const MAX_SIZE: u32 = 16384;
+
+// How the pixels of a format are stored in memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Layout {
+    // all the pixels in one plane, with this number of bytes per pixel
+    Packed(u32),
+    // two pixels share their chroma, the width must be even
+    Packed422,
+    // a full resolution luma plane followed by chroma planes subsampled by 2 in both directions
+    Planar420,
+}
+
+// The raw formats that can be captured and published, the first one is the default.
+const FORMATS: &[(&str, Layout)] = &[
+    ("RGBA", Layout::Packed(4)),
+    ("BGRA", Layout::Packed(4)),
+    ("ARGB", Layout::Packed(4)),
+    ("ABGR", Layout::Packed(4)),
+    ("RGBx", Layout::Packed(4)),
+    ("BGRx", Layout::Packed(4)),
+    ("xRGB", Layout::Packed(4)),
+    ("xBGR", Layout::Packed(4)),
+    ("RGB", Layout::Packed(3)),
+    ("BGR", Layout::Packed(3)),
+    ("GRAY8", Layout::Packed(1)),
+    ("YUY2", Layout::Packed422),
+    ("UYVY", Layout::Packed422),
+    ("YVYU", Layout::Packed422),
+    ("I420", Layout::Planar420),
+    ("NV12", Layout::Planar420),
+];
+
+fn format_names() -> Vec<&'static str> {
+    FORMATS.iter().map(|(name, _)| *name).collect()
+}
+
+// The stride (of the first plane) and the size of a tightly packed frame.
+pub(crate) fn frame_layout(format: &str, width: u32, height: u32) -> Result<(u32, usize), String> {
+    let Some((_, layout)) = FORMATS.iter().find(|(name, _)| *name == format) else {
+        return Err(format!(
+            "Unknown video format {:?}, expected one of {}",
+            format,
+            format_names().join(", ")
+        ));
+    };
+
+    if !(1..=MAX_SIZE).contains(&width) || !(1..=MAX_SIZE).contains(&height) {
+        return Err(format!(
+            "The size of the frames must be from 1x1 to {}x{}, got {}x{}",
+            MAX_SIZE, MAX_SIZE, width, height
+        ));
+    }
+
+    let pixels = width as usize * height as usize;
+    match layout {
+        Layout::Packed(bytes) => Ok((width * bytes, pixels * *bytes as usize)),
+        Layout::Packed422 if !width.is_multiple_of(2) => {
+            Err(format!("The width of {} frames must be even", format))
+        }
+        Layout::Packed422 => Ok((width * 2, pixels * 2)),
+        Layout::Planar420 if !width.is_multiple_of(2) || !height.is_multiple_of(2) => Err(format!(
+            "The width and height of {} frames must be even",
+            format
+        )),
+        Layout::Planar420 => Ok((width, pixels * 3 / 2)),
+    }
+}
+
+// A frame received by a video capture, `data` has all the planes one after the other.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct VideoFrame {
+    pub data: Vec<u8>,
+    pub stride: u32,
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn video_format(name: &str) -> Option<VideoFormat> {
+    Some(match name {
+        "RGBA" => VideoFormat::RGBA,
+        "BGRA" => VideoFormat::BGRA,
+        "ARGB" => VideoFormat::ARGB,
+        "ABGR" => VideoFormat::ABGR,
+        "RGBx" => VideoFormat::RGBx,
+        "BGRx" => VideoFormat::BGRx,
+        "xRGB" => VideoFormat::xRGB,
+        "xBGR" => VideoFormat::xBGR,
+        "RGB" => VideoFormat::RGB,
+        "BGR" => VideoFormat::BGR,
+        "GRAY8" => VideoFormat::GRAY8,
+        "YUY2" => VideoFormat::YUY2,
+        "UYVY" => VideoFormat::UYVY,
+        "YVYU" => VideoFormat::YVYU,
+        "I420" => VideoFormat::I420,
+        "NV12" => VideoFormat::NV12,
+        _ => return None,
+    })
+}
+
+fn format_name(format: VideoFormat) -> Option<&'static str> {
+    FORMATS
+        .iter()
+        .map(|(name, _)| *name)
+        .find(|name| video_format(name) == Some(format))
+}
+
+fn serialize(object: Object) -> Vec<u8> {
+    PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(object))
+        .expect("ERROR: error at serializing video param")
+        .0
+        .into_inner()
+}
+
+// The EnumFormat of a raw video stream, the format and the size are left open when they are None.
+fn enum_format(format: Option<VideoFormat>, size: Option<Rectangle>) -> Vec<u8> {
+    let format = match format {
+        Some(format) => Value::Id(Id(format.as_raw())),
+        None => {
+            let formats: Vec<Id> = FORMATS
+                .iter()
+                .filter_map(|(name, _)| video_format(name))
+                .map(|format| Id(format.as_raw()))
+                .collect();
+            Value::Choice(ChoiceValue::Id(Choice(
+                ChoiceFlags::empty(),
+                ChoiceEnum::Enum {
+                    default: formats[0],
+                    alternatives: formats,
+                },
+            )))
+        }
+    };
+    let size = match size {
+        Some(size) => Value::Rectangle(size),
+        None => Value::Choice(ChoiceValue::Rectangle(Choice(
+            ChoiceFlags::empty(),
+            ChoiceEnum::Range {
+                default: Rectangle {
+                    width: 640,
+                    height: 480,
+                },
+                min: Rectangle {
+                    width: 1,
+                    height: 1,
+                },
+                max: Rectangle {
+                    width: MAX_SIZE,
+                    height: MAX_SIZE,
+                },
+            },
+        ))),
+    };
+
+    serialize(object!(
+        SpaTypes::ObjectParamFormat,
+        ParamType::EnumFormat,
+        property!(FormatProperties::MediaType, Id, MediaType::Video),
+        property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
+        property!(FormatProperties::VideoFormat, format),
+        property!(FormatProperties::VideoSize, size),
+        property!(
+            FormatProperties::VideoFramerate,
+            Choice,
+            Range,
+            Fraction,
+            Fraction { num: 30, denom: 1 },
+            Fraction { num: 0, denom: 1 },
+            Fraction {
+                num: 1000,
+                denom: 1
+            }
+        ),
+    ))
+}
+
+// The raw video format negotiated by a stream, if it's one of FORMATS.
+fn negotiated_format(param: &Pod) -> Option<(&'static str, u32, u32)> {
+    let (media_type, media_subtype) = format_utils::parse_format(param).ok()?;
+    if media_type != MediaType::Video || media_subtype != MediaSubtype::Raw {
+        return None;
+    }
+
+    let mut info = VideoInfoRaw::new();
+    info.parse(param).ok()?;
+    let size = info.size();
+    Some((format_name(info.format())?, size.width, size.height))
+}
+
+fn send_state(
+    sender: &mpsc::Sender<MainOptions>,
+    id: u32,
+    stream: &Stream,
+    state: StreamState,
+    ready: &mut bool,
+) {
+    match state {
+        StreamState::Paused | StreamState::Streaming if !*ready => {
+            *ready = true;
+            sender
+                .send(MainOptions::VideoStreamReady {
+                    id,
+                    node_id: stream.node_id(),
+                })
+                .expect("ERROR: error at sending option to front");
+        }
+        StreamState::Error(_) => sender
+            .send(MainOptions::VideoStreamClosed { id })
+            .expect("ERROR: error at sending option to front"),
+        _ => {}
+    }
+}
+
+struct VideoCaptureData {
+    id: u32,
+    ready: bool,
+    format: Option<(&'static str, u32, u32)>,
+    // Set while a frame waits for the front, which clears it once the frame is handed to JS.
+    in_flight: Arc<AtomicBool>,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+// A stream receiving the raw frames of a video node, each frame is sent to the front.
+// The frames arriving while the previous one still waits for the front are dropped.
+// The stream is disconnected when this struct is dropped.
+pub(crate) struct VideoCapture {
+    _listener: StreamListener<VideoCaptureData>,
+    _stream: StreamRc,
+}
+
+impl VideoCapture {
+    pub fn new(
+        core: &CoreRc,
+        id: u32,
+        node_id: u32,
+        target_serial: Option<String>,
+        format: Option<&str>,
+        size: Option<(u32, u32)>,
+        sender: mpsc::Sender<MainOptions>,
+    ) -> Result<Self, pipewire::Error> {
+        let mut props = properties! {
+            "media.type" => "Video",
+            "media.category" => "Capture",
+            "media.role" => "Camera",
+            "node.name" => "node-pipewire:video-capture",
+        };
+
+        // Prefer the object serial to target the node, the node id is only used as fallback.
+        let connect_id = match target_serial {
+            Some(serial) => {
+                props.insert("target.object", serial);
+                None
+            }
+            None => Some(node_id),
+        };
+
+        let stream = StreamRc::new(core.clone(), "node-pipewire-video-capture", props)?;
+
+        let listener = stream
+            .add_local_listener_with_user_data(VideoCaptureData {
+                id,
+                ready: false,
+                format: None,
+                in_flight: Arc::new(AtomicBool::new(false)),
+                sender,
+            })
+            .state_changed(|stream, data, _, state| {
+                send_state(&data.sender, data.id, stream, state, &mut data.ready)
+            })
+            .param_changed(|_, data, id, param| {
+                // None means that the format was cleared
+                let Some(param) = param else {
+                    data.format = None;
+                    return;
+                };
+                if id == ParamType::Format.as_raw() {
+                    data.format = negotiated_format(param);
+                }
+            })
+            .process(|stream, data| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                let Some((format, width, height)) = data.format else {
+                    return;
+                };
+                // the previous frame wasn't handed to JS yet, skip this one instead of queueing it
+                if data.in_flight.load(Ordering::Acquire) {
+                    return;
+                }
+
+                let datas = buffer.datas_mut();
+                if datas.is_empty() {
+                    return;
+                }
+
+                let stride = match datas[0].chunk().stride() {
+                    stride if stride > 0 => stride as u32,
+                    _ => frame_layout(format, width, height).map_or(0, |(stride, _)| stride),
+                };
+
+                // the planes of a frame can be in one data or in one data each
+                let mut frame = Vec::new();
+                for plane in datas.iter_mut() {
+                    let chunk_offset = plane.chunk().offset() as usize;
+                    let chunk_size = plane.chunk().size() as usize;
+
+                    // not mapped (DMA-BUF, ...)
+                    let Some(bytes) = plane.data() else {
+                        return;
+                    };
+                    let start = chunk_offset.min(bytes.len());
+                    let end = (start + chunk_size).min(bytes.len());
+                    frame.extend_from_slice(&bytes[start..end]);
+                }
+                if frame.is_empty() {
+                    return;
+                }
+
+                data.in_flight.store(true, Ordering::Release);
+                data.sender
+                    .send(MainOptions::VideoFrame {
+                        id: data.id,
+                        in_flight: data.in_flight.clone(),
+                        frame: VideoFrame {
+                            data: frame,
+                            stride,
+                            format: format.to_string(),
+                            width,
+                            height,
+                        },
+                    })
+                    .expect("ERROR: error at sending option to front");
+            })
+            .register()?;
+
+        let format = enum_format(
+            format.and_then(video_format),
+            size.map(|(width, height)| Rectangle { width, height }),
+        );
+        let mut params = [Pod::from_bytes(&format).expect("ERROR: error at reading video format")];
+
+        stream.connect(
+            Direction::Input,
+            connect_id,
+            StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS | StreamFlags::DONT_RECONNECT,
+            &mut params,
+        )?;
+
+        Ok(Self {
+            _listener: listener,
+            _stream: stream,
+        })
+    }
+}
+
+// The Buffers param of a stream sending frames of `size` bytes.
+fn buffers_param(stride: u32, size: usize) -> Vec<u8> {
+    let int = |key: u32, value: i32| Property::new(key, Value::Int(value));
+
+    serialize(Object {
+        type_: SpaTypes::ObjectParamBuffers.as_raw(),
+        id: ParamType::Buffers.as_raw(),
+        properties: vec![
+            Property::new(
+                spa_sys::SPA_PARAM_BUFFERS_buffers,
+                Value::Choice(ChoiceValue::Int(Choice(
+                    ChoiceFlags::empty(),
+                    ChoiceEnum::Range {
+                        default: 4,
+                        min: 2,
+                        max: 16,
+                    },
+                ))),
+            ),
+            int(spa_sys::SPA_PARAM_BUFFERS_blocks, 1),
+            int(spa_sys::SPA_PARAM_BUFFERS_size, size as i32),
+            int(spa_sys::SPA_PARAM_BUFFERS_stride, stride as i32),
+            int(
+                spa_sys::SPA_PARAM_BUFFERS_dataType,
+                (1 << spa_sys::SPA_DATA_MemPtr) | (1 << spa_sys::SPA_DATA_MemFd),
+            ),
+        ],
+    })
+}
+
+struct VirtualCameraData {
+    id: u32,
+    ready: bool,
+    stride: u32,
+    size: usize,
+    frame: Rc<RefCell<Option<Vec<u8>>>>,
+    sender: mpsc::Sender<MainOptions>,
+}
+
+// A Video/Source node publishing the frames sent by JS. The stream drives its own graph and runs
+// one cycle for every frame. The node is destroyed when this struct is dropped.
+pub(crate) struct VirtualCamera {
+    frame: Rc<RefCell<Option<Vec<u8>>>>,
+    _listener: StreamListener<VirtualCameraData>,
+    stream: StreamRc,
+}
+
+impl VirtualCamera {
+    pub fn new(
+        core: &CoreRc,
+        id: u32,
+        name: &str,
+        format: &str,
+        width: u32,
+        height: u32,
+        sender: mpsc::Sender<MainOptions>,
+    ) -> Result<Self, pipewire::Error> {
+        let (stride, size) =
+            frame_layout(format, width, height).expect("ERROR: invalid virtual camera format");
+        let video_format = video_format(format).expect("ERROR: invalid virtual camera format");
+
+        let props = properties! {
+            "media.type" => "Video",
+            "media.category" => "Source",
+            "media.role" => "Camera",
+            "media.class" => "Video/Source",
+            "node.name" => format!("{}{}", NAME_PREFIX, name),
+            "node.nick" => name,
+            "node.description" => name,
+        };
+
+        let stream = StreamRc::new(core.clone(), "node-pipewire-virtual-camera", props)?;
+        let frame = Rc::new(RefCell::new(None));
+
+        let listener = stream
+            .add_local_listener_with_user_data(VirtualCameraData {
+                id,
+                ready: false,
+                stride,
+                size,
+                frame: frame.clone(),
+                sender,
+            })
+            .state_changed(|stream, data, _, state| {
+                send_state(&data.sender, data.id, stream, state, &mut data.ready)
+            })
+            .param_changed(|stream, data, id, param| {
+                if id != ParamType::Format.as_raw() || param.is_none() {
+                    return;
+                }
+
+                let buffers = buffers_param(data.stride, data.size);
+                let mut params =
+                    [Pod::from_bytes(&buffers).expect("ERROR: error at reading video buffers")];
+                // the stream goes to the error state when it fails
+                let _ = stream.update_params(&mut params);
+            })
+            .process(|stream, data| {
+                let Some(frame) = data.frame.borrow_mut().take() else {
+                    return;
+                };
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+
+                let datas = buffer.datas_mut();
+                if datas.is_empty() {
+                    return;
+                }
+
+                let size = match datas[0].data() {
+                    Some(bytes) => {
+                        let size = frame.len().min(bytes.len());
+                        bytes[..size].copy_from_slice(&frame[..size]);
+                        size
+                    }
+                    None => return,
+                };
+
+                let chunk = datas[0].chunk_mut();
+                *chunk.offset_mut() = 0;
+                *chunk.size_mut() = size as u32;
+                *chunk.stride_mut() = data.stride as i32;
+            })
+            .register()?;
+
+        let format = enum_format(Some(video_format), Some(Rectangle { width, height }));
+        let mut params = [Pod::from_bytes(&format).expect("ERROR: error at reading video format")];
+
+        // Like a camera, the node isn't linked automatically.
+        stream.connect(
+            Direction::Output,
+            None,
+            StreamFlags::DRIVER | StreamFlags::MAP_BUFFERS,
+            &mut params,
+        )?;
+
+        Ok(Self {
+            frame,
+            _listener: listener,
+            stream,
+        })
+    }
+
+    // Publish a frame in a new cycle of the graph, a frame not published yet is replaced.
+    pub fn send(&self, frame: Vec<u8>) -> Result<(), pipewire::Error> {
+        self.frame.borrow_mut().replace(frame);
+        self.stream.trigger_process()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_the_frame_layouts() {
+        assert_eq!(frame_layout("RGBA", 640, 480), Ok((2560, 1_228_800)));
+        assert_eq!(frame_layout("RGB", 3, 2), Ok((9, 18)));
+        assert_eq!(frame_layout("GRAY8", 7, 3), Ok((7, 21)));
+        assert_eq!(frame_layout("YUY2", 4, 3), Ok((8, 24)));
+        assert_eq!(frame_layout("I420", 4, 2), Ok((4, 12)));
+        assert_eq!(frame_layout("NV12", 1920, 1080), Ok((1920, 3_110_400)));
+
+        assert!(frame_layout("MJPG", 640, 480).is_err());
+        assert!(frame_layout("rgba", 640, 480).is_err());
+        assert!(frame_layout("RGBA", 0, 480).is_err());
+        assert!(frame_layout("RGBA", 640, MAX_SIZE + 1).is_err());
+        assert!(frame_layout("UYVY", 5, 4).is_err());
+        assert!(frame_layout("I420", 4, 3).is_err());
+    }
+}